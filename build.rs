@@ -0,0 +1,43 @@
+//! Build script of the Krust kernel
+//!
+//! - Links the kernel with `qemu-link.ld`
+//! - Builds the example user programs of `krust-user`, copied to `OUT_DIR/<name>.elf` to be embedded in the kernel
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Examples of `krust-user` embedded in the kernel
+const USER_PROGRAMS: [&str; 2] = ["proc_1", "proc_2"];
+const TARGET: &str = "thumbv7em-none-eabihf";
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let user_dir = manifest_dir.join("krust-user");
+    let user_target_dir = out_dir.join("krust-user");
+
+    println!("cargo:rustc-link-arg=-Tqemu-link.ld");
+    println!("cargo:rerun-if-changed=qemu-link.ld");
+    println!("cargo:rerun-if-changed=krust-user");
+
+    // krust-user is a separate crate with its own configuration (see krust-user/.cargo/config.toml),
+    // the flags of the kernel build must not leak into it
+    let status = Command::new(env::var("CARGO").unwrap())
+        .current_dir(&user_dir)
+        .args(["build", "--release", "--examples", "--target", TARGET, "--target-dir"])
+        .arg(&user_target_dir)
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_TARGET_DIR")
+        .env_remove("RUSTC_WORKSPACE_WRAPPER")
+        .status()
+        .expect("Failed to run cargo for krust-user");
+    assert!(status.success(), "Failed to build krust-user examples");
+
+    for program in USER_PROGRAMS {
+        let elf = user_target_dir.join(TARGET).join("release").join("examples").join(program);
+        fs::copy(&elf, out_dir.join(format!("{}.elf", program))).expect("Missing krust-user example");
+    }
+}
@@ -0,0 +1,7 @@
+//! Build script of the KRUST user SDK
+//!
+//! The user programs are linked with `user-link.ld` (see `.cargo/config.toml`) : relink them when it changes.
+
+fn main() {
+    println!("cargo:rerun-if-changed=user-link.ld");
+}
@@ -0,0 +1,18 @@
+//! proc_1 : writes a greeting, prints 0x0bad7001 followed by a counter, sleeping between prints, then exits with 0x0badc001
+
+#![no_std]
+#![no_main]
+
+use krust_user::{print, sleep, write};
+
+krust_user::entry!(main);
+
+fn main() -> u32 {
+    let _ = write("proc_1 started\n");
+    for i in 0..5 {
+        print(0x0bad7001);
+        print(i);
+        sleep(1000);
+    }
+    0x0badc001
+}
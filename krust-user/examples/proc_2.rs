@@ -0,0 +1,17 @@
+//! proc_2 : prints 0xdead7001 followed by a counter, sleeping between prints, then exits with 0xdeadc001
+
+#![no_std]
+#![no_main]
+
+use krust_user::{print, sleep};
+
+krust_user::entry!(main);
+
+fn main() -> u32 {
+    for i in 0..5 {
+        print(0xdead7001);
+        print(i);
+        sleep(1000);
+    }
+    0xdeadc001
+}
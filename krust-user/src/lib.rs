@@ -0,0 +1,451 @@
+//! User-space SDK for KRUST processes
+//!
+//! Provides the syscall wrappers, the `_start` entry point and the panic handler of a KRUST user program.
+//!
+//! A program is a `#![no_std]` `#![no_main]` binary declaring its main function with `entry!` :
+//!
+//! ```
+//! #![no_std]
+//! #![no_main]
+//!
+//! krust_user::entry!(main);
+//!
+//! fn main() -> u32 {
+//!     krust_user::print(0x1234);
+//!     0 // Exit code
+//! }
+//! ```
+//!
+//! It must be built as a position-independent executable linked with `user-link.ld`
+//! (see `.cargo/config.toml`), the resulting ELF is loaded by the kernel with `create_process_from_elf`.
+
+#![no_std]
+
+use core::arch::asm;
+use core::panic::PanicInfo;
+
+/// Syscall numbers, passed in R0
+pub mod syscall {
+    pub const SYS_EXIT: u32 = 0;
+    pub const SYS_PRINT: u32 = 1;
+    pub const SYS_SLEEP: u32 = 2;
+    pub const SYS_WRITE: u32 = 3;
+    pub const SYS_HEAP_VERIFY: u32 = 4;
+    pub const SYS_MMAP: u32 = 5;
+    pub const SYS_MUNMAP: u32 = 6;
+    pub const SYS_MQ_CREATE: u32 = 7;
+    pub const SYS_MQ_SEND: u32 = 8;
+    pub const SYS_MQ_RECV: u32 = 9;
+    pub const SYS_MUTEX_CREATE: u32 = 10;
+    pub const SYS_MUTEX_LOCK: u32 = 11;
+    pub const SYS_MUTEX_UNLOCK: u32 = 12;
+    pub const SYS_SEM_CREATE: u32 = 13;
+    pub const SYS_SEM_WAIT: u32 = 14;
+    pub const SYS_SEM_POST: u32 = 15;
+    pub const SYS_WAIT_EVENTS: u32 = 16;
+    pub const SYS_NOTIFY: u32 = 17;
+    pub const SYS_SHM_CREATE: u32 = 18;
+    pub const SYS_SHM_MAP: u32 = 19;
+    pub const SYS_SHM_UNMAP: u32 = 20;
+    pub const SYS_GETPID: u32 = 21;
+    pub const SYS_YIELD: u32 = 22;
+    pub const SYS_SPAWN: u32 = 23;
+    pub const SYS_WAIT: u32 = 24;
+    pub const SYS_KILL: u32 = 25;
+}
+
+/// Error numbers returned (negated) by syscalls
+pub mod errno {
+    pub const EPERM: i32 = 1;
+    pub const ENOENT: i32 = 2;
+    pub const ESRCH: i32 = 3;
+    pub const EIO: i32 = 5;
+    pub const ENOEXEC: i32 = 8;
+    pub const EBADF: i32 = 9;
+    pub const ECHILD: i32 = 10;
+    pub const EAGAIN: i32 = 11;
+    pub const ENOMEM: i32 = 12;
+    pub const EFAULT: i32 = 14;
+    pub const EBUSY: i32 = 16;
+    pub const EEXIST: i32 = 17;
+    pub const EINVAL: i32 = 22;
+    pub const ENFILE: i32 = 23;
+    pub const ENOSPC: i32 = 28;
+    pub const EDEADLK: i32 = 35;
+    pub const ENOSYS: i32 = 38;
+    pub const EIDRM: i32 = 43;
+    pub const EOVERFLOW: i32 = 75;
+    pub const ETIMEDOUT: i32 = 110;
+}
+
+/// Exit code of a process that panicked
+pub const PANIC_EXIT_CODE: u32 = 0xffff_ffff;
+
+/// Timeout of a blocking syscall waiting forever
+pub const WAIT_FOREVER: u32 = u32::MAX;
+
+/// Performs a syscall
+///
+/// # Call Convention
+///     R0 <- SYSCALL_ID
+///     R1 <- ARG0
+///     R2 <- ARG1
+///     R3 <- ARG2
+///
+/// The value of R0 when the kernel returns from the syscall is returned : the result of the syscall,
+/// or `-errno` on failure (see `syscall_result`).
+#[inline(always)]
+pub fn syscall(syscall_id: u32, arg0: u32, arg1: u32, arg2: u32) -> u32 {
+    let ret: u32;
+    unsafe {
+        asm!(
+            "svc 0",
+            inout("r0") syscall_id => ret,
+            in("r1") arg0,
+            in("r2") arg1,
+            in("r3") arg2,
+            options(nostack)
+        );
+    }
+    ret
+}
+
+/// Splits the raw value returned by `syscall` into a result or an error number
+///
+/// Values in `-4095..=-1` are error numbers, as on Linux.
+pub fn syscall_result(ret: u32) -> Result<u32, i32> {
+    let signed = ret as i32;
+    if (-4095..0).contains(&signed) {
+        Err(-signed)
+    } else {
+        Ok(ret)
+    }
+}
+
+/// Terminates the current process with an exit code
+pub fn exit(code: u32) -> ! {
+    syscall(syscall::SYS_EXIT, code, 0, 0);
+
+    // The process is killed on next scheduler call
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Prints a value in hex on the kernel console
+pub fn print(value: u32) {
+    syscall(syscall::SYS_PRINT, value, 0, 0);
+}
+
+/// Writes a string on the kernel console, returns the number of bytes written
+///
+/// Fails with `EFAULT` if the string is not in the memory of the process.
+pub fn write(text: &str) -> Result<u32, i32> {
+    syscall_result(syscall(syscall::SYS_WRITE, text.as_ptr() as u32, text.len() as u32, 0))
+}
+
+/// Puts the current process to sleep for `ms` milliseconds
+pub fn sleep(ms: u32) {
+    syscall(syscall::SYS_SLEEP, ms, 0, 0);
+}
+
+/// Asks the kernel to check the integrity of its heap, returns the number of heap blocks
+///
+/// Fails with `EIO` if the heap is corrupted, the corrupted block is logged by the kernel.
+pub fn heap_verify() -> Result<u32, i32> {
+    syscall_result(syscall(syscall::SYS_HEAP_VERIFY, 0, 0, 0))
+}
+
+/// Maps at least `len` bytes of zeroed memory in the process, returns its address
+///
+/// The memory is a power-of-two-sized block aligned on its size, protected by one of the free MPU regions
+/// of the process. Fails with `ENOSPC` if all the MPU regions are in use, `ENOMEM` if there is not enough
+/// memory or the process would exceed its memory quota, `EINVAL` if `len` is null or too large.
+pub fn mmap(len: usize) -> Result<*mut u8, i32> {
+    syscall_result(syscall(syscall::SYS_MMAP, len as u32, 0, 0)).map(|address| address as *mut u8)
+}
+
+/// Unmaps the memory mapped by `mmap` at `address`
+///
+/// Fails with `EINVAL` if `address` was not returned by `mmap`, or is already unmapped.
+pub fn munmap(address: *mut u8) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_MUNMAP, address as u32, 0, 0)).map(|_| ())
+}
+
+/// Creates a queue of `capacity` messages of `message_size` bytes, returns its handle
+///
+/// The queue is destroyed when the process exits. Fails with `EINVAL` if a message is larger than 64 bytes or the
+/// capacity larger than 32, `ENOSPC` if all the queues of the system are in use, `ENOMEM` if there is not enough memory.
+pub fn mq_create(message_size: usize, capacity: usize) -> Result<u32, i32> {
+    syscall_result(syscall(syscall::SYS_MQ_CREATE, message_size as u32, capacity as u32, 0))
+}
+
+/// Sends a message to a queue, waiting for a free slot if it is full
+///
+/// `message` must hold the message size of the queue. Fails with `EBADF` if the handle is invalid, `EFAULT` if
+/// `message` is too short, `EIDRM` if the queue is destroyed while waiting.
+pub fn mq_send(handle: u32, message: &[u8]) -> Result<(), i32> {
+    mq_send_timeout(handle, message, WAIT_FOREVER)
+}
+
+/// Sends a message to a queue, fails with `EAGAIN` if it is full
+pub fn mq_try_send(handle: u32, message: &[u8]) -> Result<(), i32> {
+    mq_send_timeout(handle, message, 0)
+}
+
+/// Sends a message to a queue, waiting up to `timeout_ms` milliseconds for a free slot
+///
+/// Fails with `ETIMEDOUT` if the queue is still full after `timeout_ms` milliseconds.
+pub fn mq_send_timeout(handle: u32, message: &[u8], timeout_ms: u32) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_MQ_SEND, handle, message.as_ptr() as u32, timeout_ms)).map(|_| ())
+}
+
+/// Receives a message from a queue, waiting for one if it is empty, returns its size
+///
+/// `buffer` must hold the message size of the queue. Fails with `EBADF` if the handle is invalid, `EFAULT` if
+/// `buffer` is too short, `EIDRM` if the queue is destroyed while waiting.
+pub fn mq_receive(handle: u32, buffer: &mut [u8]) -> Result<usize, i32> {
+    mq_receive_timeout(handle, buffer, WAIT_FOREVER)
+}
+
+/// Receives a message from a queue, fails with `EAGAIN` if it is empty
+pub fn mq_try_receive(handle: u32, buffer: &mut [u8]) -> Result<usize, i32> {
+    mq_receive_timeout(handle, buffer, 0)
+}
+
+/// Receives a message from a queue, waiting up to `timeout_ms` milliseconds for one
+///
+/// Fails with `ETIMEDOUT` if the queue is still empty after `timeout_ms` milliseconds.
+pub fn mq_receive_timeout(handle: u32, buffer: &mut [u8], timeout_ms: u32) -> Result<usize, i32> {
+    syscall_result(syscall(syscall::SYS_MQ_RECV, handle, buffer.as_mut_ptr() as u32, timeout_ms)).map(|len| len as usize)
+}
+
+/// Creates a mutex, returns its handle
+///
+/// The mutex is destroyed when the process exits. Fails with `ENOSPC` if all the mutexes of the system are in use.
+pub fn mutex_create() -> Result<u32, i32> {
+    syscall_result(syscall(syscall::SYS_MUTEX_CREATE, 0, 0, 0))
+}
+
+/// Takes a mutex, waiting for it if it is held by another process
+///
+/// While the process waits, the holder runs at its priority if it is higher. Fails with `EDEADLK` if the process
+/// already holds the mutex, `EIDRM` if the mutex is destroyed while waiting.
+pub fn mutex_lock(handle: u32) -> Result<(), i32> {
+    mutex_lock_timeout(handle, WAIT_FOREVER)
+}
+
+/// Takes a mutex, fails with `EAGAIN` if it is held by another process
+pub fn mutex_try_lock(handle: u32) -> Result<(), i32> {
+    mutex_lock_timeout(handle, 0)
+}
+
+/// Takes a mutex, waiting up to `timeout_ms` milliseconds for it
+pub fn mutex_lock_timeout(handle: u32, timeout_ms: u32) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_MUTEX_LOCK, handle, timeout_ms, 0)).map(|_| ())
+}
+
+/// Releases a mutex, fails with `EPERM` if it is not held by the process
+pub fn mutex_unlock(handle: u32) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_MUTEX_UNLOCK, handle, 0, 0)).map(|_| ())
+}
+
+/// Creates a semaphore of count `initial_count`, which cannot exceed `max_count`, returns its handle
+///
+/// The semaphore is destroyed when the process exits. Fails with `EINVAL` if `initial_count` is larger than
+/// `max_count`, or `max_count` is null or larger than 65535.
+pub fn sem_create(initial_count: u32, max_count: u32) -> Result<u32, i32> {
+    syscall_result(syscall(syscall::SYS_SEM_CREATE, initial_count, max_count, 0))
+}
+
+/// Decrements a semaphore, waiting for it to be posted if its count is null
+pub fn sem_wait(handle: u32) -> Result<(), i32> {
+    sem_wait_timeout(handle, WAIT_FOREVER)
+}
+
+/// Decrements a semaphore, fails with `EAGAIN` if its count is null
+pub fn sem_try_wait(handle: u32) -> Result<(), i32> {
+    sem_wait_timeout(handle, 0)
+}
+
+/// Decrements a semaphore, waiting up to `timeout_ms` milliseconds for it to be posted
+pub fn sem_wait_timeout(handle: u32, timeout_ms: u32) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_SEM_WAIT, handle, timeout_ms, 0)).map(|_| ())
+}
+
+/// Increments a semaphore, waking up the highest priority process waiting on it
+///
+/// Fails with `EOVERFLOW` if the semaphore is at its maximum count.
+pub fn sem_post(handle: u32) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_SEM_POST, handle, 0, 0)).map(|_| ())
+}
+
+/// Condition on the events a process waits for
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EventWait {
+    /// At least one event of the mask
+    Any = 0,
+    /// All the events of the mask
+    All = 1,
+}
+
+/// Waits for events of `mask` to be notified to the process, returns the events received
+///
+/// The events returned are cleared, the other pending events are kept. Fails with `EINVAL` if `mask` is null.
+pub fn wait_events(mask: u32, wait: EventWait) -> Result<u32, i32> {
+    wait_events_timeout(mask, wait, WAIT_FOREVER)
+}
+
+/// Gets the pending events of `mask`, fails with `EAGAIN` if they do not satisfy `wait`
+pub fn try_wait_events(mask: u32, wait: EventWait) -> Result<u32, i32> {
+    wait_events_timeout(mask, wait, 0)
+}
+
+/// Waits up to `timeout_ms` milliseconds for events of `mask`, fails with `ETIMEDOUT` if they are not notified
+pub fn wait_events_timeout(mask: u32, wait: EventWait, timeout_ms: u32) -> Result<u32, i32> {
+    syscall_result(syscall(syscall::SYS_WAIT_EVENTS, mask, wait as u32, timeout_ms))
+}
+
+/// Notifies events to a process, fails with `ESRCH` if there is no process `pid`
+pub fn notify(pid: u16, events: u32) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_NOTIFY, pid as u32, events, 0)).map(|_| ())
+}
+
+/// Access of the process to a shared memory it maps
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ShmAccess {
+    ReadOnly = 0,
+    ReadWrite = 1,
+}
+
+/// Creates a shared memory of at least `size` bytes named `name`, maps it read-write, returns its address
+///
+/// The memory is zeroed, and freed once unmapped by all the processes. Fails with `EEXIST` if a shared memory
+/// with this name exists, `EINVAL` if `name` is empty or longer than 16 bytes, `ENFILE` if all the shared memory
+/// objects of the system are in use, `ENOSPC` if all the MPU regions of the process are in use.
+pub fn shm_create(name: &str, size: usize) -> Result<*mut u8, i32> {
+    syscall_result(syscall(syscall::SYS_SHM_CREATE, name.as_ptr() as u32, name.len() as u32, size as u32))
+        .map(|address| address as *mut u8)
+}
+
+/// Maps the shared memory named `name`, returns its address
+///
+/// Fails with `ENOENT` if there is no shared memory with this name.
+pub fn shm_map(name: &str, access: ShmAccess) -> Result<*mut u8, i32> {
+    syscall_result(syscall(syscall::SYS_SHM_MAP, name.as_ptr() as u32, name.len() as u32, access as u32))
+        .map(|address| address as *mut u8)
+}
+
+/// Unmaps the shared memory mapped at `address`
+pub fn shm_unmap(address: *mut u8) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_SHM_UNMAP, address as u32, 0, 0)).map(|_| ())
+}
+
+/// Returns the PID of the process
+pub fn getpid() -> u16 {
+    syscall(syscall::SYS_GETPID, 0, 0, 0) as u16
+}
+
+/// Gives up the rest of the time slice of the process, to the next ready process of the same priority
+pub fn yield_now() {
+    syscall(syscall::SYS_YIELD, 0, 0, 0);
+}
+
+/// Starts the program registered in the kernel as `name`, as a child of the process, returns its PID
+///
+/// Fails with `ENOENT` if there is no such program, `ENOEXEC` if its image is invalid, `ENOMEM` if there is not
+/// enough memory to load it.
+pub fn spawn(name: &str) -> Result<u16, i32> {
+    if name.is_empty() {
+        return Err(errno::ENOENT);
+    }
+    syscall_result(syscall(syscall::SYS_SPAWN, name.as_ptr() as u32, name.len() as u32, 0)).map(|pid| pid as u16)
+}
+
+/// Starts the program registered in the kernel with ID `program_id`, as a child of the process, returns its PID
+pub fn spawn_id(program_id: u32) -> Result<u16, i32> {
+    syscall_result(syscall(syscall::SYS_SPAWN, program_id, 0, 0)).map(|pid| pid as u16)
+}
+
+/// How a child process terminated
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExitStatus {
+    /// Returned from its main function or called `exit`, with this exit code
+    Exited(u32),
+    /// Killed by a fault, with the value of the CFSR
+    Faulted(u32),
+    /// Killed by a process or by the kernel
+    Killed,
+    /// Killed because its stack overflowed
+    StackOverflow,
+}
+
+impl ExitStatus {
+    /// Decodes the exit status written by the kernel : its kind, then its value
+    fn decode(status: [u32; 2]) -> Self {
+        match status {
+            [0, code] => ExitStatus::Exited(code),
+            [1, cfsr] => ExitStatus::Faulted(cfsr),
+            [3, _] => ExitStatus::StackOverflow,
+            _ => ExitStatus::Killed,
+        }
+    }
+}
+
+/// Waits for the child `pid` to terminate, returns its exit status
+///
+/// Fails with `ECHILD` if `pid` is not a child of the process, or was already waited for.
+pub fn wait(pid: u16) -> Result<ExitStatus, i32> {
+    wait_timeout(pid, WAIT_FOREVER)
+}
+
+/// Gets the exit status of the child `pid`, fails with `EAGAIN` if it is still running
+pub fn try_wait(pid: u16) -> Result<ExitStatus, i32> {
+    wait_timeout(pid, 0)
+}
+
+/// Waits up to `timeout_ms` milliseconds for the child `pid` to terminate, fails with `ETIMEDOUT` if it is still running
+pub fn wait_timeout(pid: u16, timeout_ms: u32) -> Result<ExitStatus, i32> {
+    let mut status = [0u32; 2];
+    syscall_result(syscall(syscall::SYS_WAIT, pid as u32, status.as_mut_ptr() as u32, timeout_ms))?;
+    Ok(ExitStatus::decode(status))
+}
+
+/// Kills the process itself or one of its children
+///
+/// Fails with `EPERM` if `pid` is another process, `ESRCH` if there is no process `pid`.
+pub fn kill(pid: u16) -> Result<(), i32> {
+    syscall_result(syscall(syscall::SYS_KILL, pid as u32, 0, 0)).map(|_| ())
+}
+
+/// Declares the main function of the program, called by `_start`
+///
+/// The main function takes no argument and returns the exit code of the process.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn __krust_main() -> u32 {
+            let main: fn() -> u32 = $main;
+            main()
+        }
+    };
+}
+
+/// Entry point of the program, placed first in `.text`
+///
+/// Calls the main function declared with `entry!`, then exits with its return value.
+#[unsafe(no_mangle)]
+#[unsafe(link_section = ".text._start")]
+pub extern "C" fn _start() -> ! {
+    unsafe extern "C" {
+        fn __krust_main() -> u32;
+    }
+
+    exit(unsafe { __krust_main() })
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    exit(PANIC_EXIT_CODE)
+}
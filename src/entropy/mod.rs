@@ -0,0 +1,167 @@
+//! Kernel entropy subsystem
+//!
+//! A ChaCha20 based CSPRNG, seeded at boot by `init` from the best available entropy source :
+//! - the RNG peripheral of the STM32F405, when present
+//! - otherwise the jitter of the SysTick and DWT cycle counters, as under QEMU which does not emulate the RNG
+//!
+//! Random values are used for the heap cookies, the stack canaries of the processes, and any future
+//! address randomization. The generator erases its key after each block (the first half of each
+//! block becomes the next key), so that a leaked state does not reveal the previous outputs.
+//!
+//! ```
+//! ChaCha20 block (16 words)
+//! +---------+
+//! | 0 .. 7  | Next key
+//! +---------+
+//! | 8 .. 15 | Random output
+//! +---------+
+//! ```
+
+mod sources;
+
+use cortex_m::interrupt;
+use spin::Mutex;
+
+use crate::log_debug;
+
+/// Number of words of the seed, the size of the ChaCha20 key
+const SEED_WORDS: usize = 8;
+const BLOCK_WORDS: usize = 16;
+const CHACHA_ROUNDS: usize = 20;
+/// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+/// Source the CSPRNG has been seeded from
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum EntropySource {
+    /// Not seeded yet
+    None,
+    /// STM32F405 RNG peripheral
+    Hardware,
+    /// SysTick and DWT cycle counters jitter
+    Jitter
+}
+
+struct Csprng {
+    key: [u32; SEED_WORDS],
+    counter: u64,
+    buffer: [u32; BLOCK_WORDS],
+    /// Next unused word of `buffer`
+    index: usize,
+    source: EntropySource
+}
+
+static CSPRNG: Mutex<Csprng> = Mutex::new(Csprng {
+    key: [0; SEED_WORDS],
+    counter: 0,
+    buffer: [0; BLOCK_WORDS],
+    index: BLOCK_WORDS,
+    source: EntropySource::None
+});
+
+impl Csprng {
+    /// Mixes a seed into the key, and drops the buffered output
+    fn reseed(&mut self, seed: &[u32; SEED_WORDS], source: EntropySource) {
+        for (key, word) in self.key.iter_mut().zip(seed) {
+            *key ^= word;
+        }
+        self.index = BLOCK_WORDS;
+        if self.source != EntropySource::Hardware {
+            self.source = source;
+        }
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.source == EntropySource::None {
+            // Used before `init` : seed from the jitter, which is always available
+            self.reseed(&sources::jitter_seed(), EntropySource::Jitter);
+        }
+        if self.index == BLOCK_WORDS {
+            self.refill();
+        }
+        let word = self.buffer[self.index];
+        self.buffer[self.index] = 0;
+        self.index += 1;
+        word
+    }
+
+    /// Generates the next block, whose first half replaces the key
+    fn refill(&mut self) {
+        chacha20_block(&self.key, self.counter, &mut self.buffer);
+        self.counter = self.counter.wrapping_add(1);
+        self.key.copy_from_slice(&self.buffer[..SEED_WORDS]);
+        self.buffer[..SEED_WORDS].fill(0);
+        self.index = SEED_WORDS;
+    }
+}
+
+/// Seeds the CSPRNG from the RNG peripheral, or from the counters jitter if there is no RNG
+///
+/// Called once at boot, before the heap is initialized.
+pub fn init() {
+    let (seed, source) = match sources::hardware_seed() {
+        Some(seed) => (seed, EntropySource::Hardware),
+        None => (sources::jitter_seed(), EntropySource::Jitter)
+    };
+    interrupt::free(|_cs| CSPRNG.lock().reseed(&seed, source));
+    log_debug!("Entropy source : {:?}", source);
+}
+
+/// Get the source the CSPRNG has been seeded from
+#[allow(dead_code)]
+pub fn source() -> EntropySource {
+    interrupt::free(|_cs| CSPRNG.lock().source)
+}
+
+/// Get a random `u32`
+pub fn random_u32() -> u32 {
+    interrupt::free(|_cs| CSPRNG.lock().next_u32())
+}
+
+/// Get a random `usize`
+pub fn random_usize() -> usize {
+    random_u32() as usize
+}
+
+/// Fills a buffer with random bytes
+#[allow(dead_code)]
+pub fn fill_bytes(buffer: &mut [u8]) {
+    for chunk in buffer.chunks_mut(4) {
+        let word = random_u32().to_le_bytes();
+        chunk.copy_from_slice(&word[..chunk.len()]);
+    }
+}
+
+fn quarter_round(state: &mut [u32; BLOCK_WORDS], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]); state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]); state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]); state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]); state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// ChaCha20 block function, with a 64 bits block counter and a null nonce
+fn chacha20_block(key: &[u32; SEED_WORDS], counter: u64, output: &mut [u32; BLOCK_WORDS]) {
+    let mut initial = [0u32; BLOCK_WORDS];
+    initial[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    initial[4..12].copy_from_slice(key);
+    initial[12] = counter as u32;
+    initial[13] = (counter >> 32) as u32;
+
+    let mut state = initial;
+    for _ in 0..CHACHA_ROUNDS / 2 {
+        // Column rounds
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        // Diagonal rounds
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    for (word, (mixed, initial)) in output.iter_mut().zip(state.iter().zip(initial.iter())) {
+        *word = mixed.wrapping_add(*initial);
+    }
+}
@@ -0,0 +1,127 @@
+//! Entropy sources used to seed the CSPRNG
+//!
+//! - `hardware_seed` : RNG peripheral of the STM32F405, clocked from AHB2. Each 32 bits word is checked
+//!   against the seed and clock errors of RNG_SR, and against the previous word (stuck generator).
+//! - `jitter_seed` : timing jitter of a busy loop, measured with the DWT cycle counter and the SysTick
+//!   current value. Always available, but weaker : only used when there is no RNG.
+
+use core::ptr;
+
+const RCC_AHB2ENR_ADDR: u32 = 0x4002_3834;
+const RCC_AHB2ENR_RNGEN: u32 = 1 << 6;
+
+const RNG_CR_ADDR: u32 = 0x5006_0800;
+const RNG_SR_ADDR: u32 = 0x5006_0804;
+const RNG_DR_ADDR: u32 = 0x5006_0808;
+const RNG_CR_RNGEN: u32 = 1 << 2;
+const RNG_SR_DRDY: u32 = 1 << 0;
+const RNG_SR_CECS: u32 = 1 << 1;
+const RNG_SR_SECS: u32 = 1 << 2;
+
+/// Number of RNG_SR reads before considering that there is no RNG
+const RNG_TIMEOUT: u32 = 10_000;
+
+const DEMCR_ADDR: u32 = 0xE000_EDFC;
+const DEMCR_TRCENA: u32 = 1 << 24;
+const DWT_CTRL_ADDR: u32 = 0xE000_1000;
+const DWT_CYCCNT_ADDR: u32 = 0xE000_1004;
+const DWT_CTRL_CYCCNTENA: u32 = 1 << 0;
+const SYST_CSR_ADDR: u32 = 0xE000_E010;
+const SYST_RVR_ADDR: u32 = 0xE000_E014;
+const SYST_CVR_ADDR: u32 = 0xE000_E018;
+const SYST_CSR_ENABLE: u32 = 1 << 0;
+const SYST_CSR_CLKSOURCE: u32 = 1 << 2;
+const SYST_MAX_RELOAD: u32 = 0x00FF_FFFF;
+
+/// Number of timing samples folded in each word of a jitter seed
+const JITTER_SAMPLES_PER_WORD: u32 = 64;
+
+/// Reads a seed from the RNG peripheral, `None` if there is no RNG or if it does not work
+pub fn hardware_seed() -> Option<[u32; super::SEED_WORDS]> {
+    unsafe {
+        let ahb2enr = ptr::read_volatile(RCC_AHB2ENR_ADDR as *const u32);
+        ptr::write_volatile(RCC_AHB2ENR_ADDR as *mut u32, ahb2enr | RCC_AHB2ENR_RNGEN);
+        ptr::write_volatile(RNG_CR_ADDR as *mut u32, RNG_CR_RNGEN);
+    }
+
+    // The first word is only used for the continuous test, as advised by the reference manual
+    let mut previous = read_rng()?;
+    let mut seed = [0u32; super::SEED_WORDS];
+    for word in seed.iter_mut() {
+        let value = read_rng()?;
+        if value == previous {
+            return None;
+        }
+        *word = value;
+        previous = value;
+    }
+    Some(seed)
+}
+
+fn read_rng() -> Option<u32> {
+    for _ in 0..RNG_TIMEOUT {
+        let status = unsafe { ptr::read_volatile(RNG_SR_ADDR as *const u32) };
+        if status & (RNG_SR_CECS | RNG_SR_SECS) != 0 {
+            return None;
+        }
+        if status & RNG_SR_DRDY != 0 {
+            return Some(unsafe { ptr::read_volatile(RNG_DR_ADDR as *const u32) });
+        }
+    }
+    None
+}
+
+/// Builds a seed from the jitter of the DWT cycle counter and the SysTick current value
+///
+/// If SysTick is not started yet, it runs without its interrupt while the seed is built.
+pub fn jitter_seed() -> [u32; super::SEED_WORDS] {
+    let (syst_csr, syst_rvr) = unsafe {
+        (ptr::read_volatile(SYST_CSR_ADDR as *const u32), ptr::read_volatile(SYST_RVR_ADDR as *const u32))
+    };
+    let start_systick = syst_csr & SYST_CSR_ENABLE == 0;
+
+    unsafe {
+        if start_systick {
+            ptr::write_volatile(SYST_RVR_ADDR as *mut u32, SYST_MAX_RELOAD);
+            ptr::write_volatile(SYST_CVR_ADDR as *mut u32, 0);
+            ptr::write_volatile(SYST_CSR_ADDR as *mut u32, SYST_CSR_CLKSOURCE | SYST_CSR_ENABLE);
+        }
+
+        let demcr = ptr::read_volatile(DEMCR_ADDR as *const u32);
+        ptr::write_volatile(DEMCR_ADDR as *mut u32, demcr | DEMCR_TRCENA);
+        let dwt_ctrl = ptr::read_volatile(DWT_CTRL_ADDR as *const u32);
+        ptr::write_volatile(DWT_CTRL_ADDR as *mut u32, dwt_ctrl | DWT_CTRL_CYCCNTENA);
+    }
+
+    let mut seed = [0u32; super::SEED_WORDS];
+    let mut accumulator = timestamp();
+    for word in seed.iter_mut() {
+        for _ in 0..JITTER_SAMPLES_PER_WORD {
+            let start = timestamp();
+            // Busy loop whose length depends on the previous measures
+            let mut work = accumulator;
+            for _ in 0..(start & 0xF) + 1 {
+                work = (work ^ 0x9E37_79B9).rotate_left(7).wrapping_mul(0x85EB_CA6B);
+            }
+            let delta = timestamp().wrapping_sub(start);
+            accumulator = accumulator.rotate_left(5) ^ delta ^ core::hint::black_box(work);
+        }
+        *word = accumulator;
+    }
+
+    if start_systick {
+        unsafe {
+            ptr::write_volatile(SYST_CSR_ADDR as *mut u32, syst_csr);
+            ptr::write_volatile(SYST_RVR_ADDR as *mut u32, syst_rvr);
+        }
+    }
+    seed
+}
+
+fn timestamp() -> u32 {
+    unsafe {
+        let cycles = ptr::read_volatile(DWT_CYCCNT_ADDR as *const u32);
+        let systick = ptr::read_volatile(SYST_CVR_ADDR as *const u32);
+        cycles ^ systick.rotate_left(16)
+    }
+}
@@ -0,0 +1,241 @@
+//! Crash report of the last fault
+//!
+//! When a fault occurs, the fault handlers build a `CrashReport` : the exception frame stacked by the
+//! processor, the callee-saved registers, the fault status registers, the faulting process, and the
+//! words of the stack above the exception frame.
+//!
+//! The report is logged, and kept in the `.noinit` RAM section, which is not initialized by the Reset
+//! handler : the report of the last fault survives a warm reset, and is retrieved with `last_crash`.
+//!
+//! ```
+//! Faulting stack (MSP or PSP, see EXC_RETURN)
+//! +--------+ < frame
+//! | R0     |
+//! | ...    | Exception frame
+//! | xPSR   |
+//! +--------+ < frame + frame size (8 words, 26 with the FPU context)
+//! | ...    | Stack dump, STACK_DUMP_WORDS words at most
+//! +--------+
+//! ```
+
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+
+use crate::log_info;
+use crate::proc::FaultKind;
+use crate::SYSTEM_PROCESS;
+use super::handlers::{CalleeSavedRegisters, ExceptionFrame};
+
+/// Number of stack words kept in a crash report
+pub const STACK_DUMP_WORDS: usize = 16;
+/// Maximum length of the process name kept in a crash report
+const PROC_NAME_LEN: usize = 16;
+/// Marks a valid crash report in `.noinit`
+const CRASH_MAGIC: u32 = 0xC4A5_11ED;
+/// PID recorded when the fault comes from the kernel
+pub const KERNEL_PID: u32 = 0xFFFF_FFFF;
+
+const CFSR_ADDR: u32 = 0xE000ED28;
+const HFSR_ADDR: u32 = 0xE000ED2C;
+const MMFAR_ADDR: u32 = 0xE000ED34;
+const BFAR_ADDR: u32 = 0xE000ED38;
+
+const MSTKERR_BIT: u32 = 4;
+const STKERR_BIT: u32 = 12;
+/// EXC_RETURN bit 4 is 0 when the frame holds the FPU context
+const EXC_RETURN_STD_FRAME: u32 = 1 << 4;
+const FRAME_WORDS: usize = 8;
+const FPU_FRAME_WORDS: usize = 26;
+
+/// Crash report of a fault, as stored in `.noinit`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CrashReport {
+    magic: u32,
+    kind: u32,
+    pub exc_return: u32,
+    /// Exception frame, only meaningful if `is_frame_valid`
+    pub frame: ExceptionFrame,
+    frame_valid: u32,
+    pub callee_saved: CalleeSavedRegisters,
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+    /// PID of the faulting process, `KERNEL_PID` if the fault comes from the kernel
+    pub proc_id: u32,
+    proc_name: [u8; PROC_NAME_LEN],
+    proc_name_len: u32,
+    /// Address of the first word of the stack dump
+    pub stack_address: u32,
+    pub stack: [u32; STACK_DUMP_WORDS],
+    pub stack_words: u32,
+    checksum: u32
+}
+
+#[unsafe(link_section = ".noinit")]
+static mut LAST_CRASH: MaybeUninit<CrashReport> = MaybeUninit::uninit();
+
+impl CrashReport {
+    /// Fault exception of the report
+    pub fn kind(&self) -> FaultKind {
+        match self.kind {
+            1 => FaultKind::MemManage,
+            2 => FaultKind::BusFault,
+            3 => FaultKind::UsageFault,
+            _ => FaultKind::HardFault
+        }
+    }
+
+    /// Check if the exception frame could be stacked, it is unknown after a stacking fault
+    pub fn is_frame_valid(&self) -> bool {
+        self.frame_valid != 0
+    }
+
+    /// Name of the faulting process, "kernel" if the fault comes from the kernel
+    pub fn proc_name(&self) -> &str {
+        let len = (self.proc_name_len as usize).min(PROC_NAME_LEN);
+        core::str::from_utf8(&self.proc_name[..len]).unwrap_or("?")
+    }
+
+    /// Emits the report through the logging layer
+    pub fn log(&self) {
+        log_info!("=== CRASH REPORT ===");
+        log_info!("{:?} in {} (PID {:#x}), EXC_RETURN {:#010x}", self.kind(), self.proc_name(), self.proc_id, self.exc_return);
+        log_info!("CFSR {:#010x} HFSR {:#010x} MMFAR {:#010x} BFAR {:#010x}", self.cfsr, self.hfsr, self.mmfar, self.bfar);
+
+        let frame = &self.frame;
+        if self.is_frame_valid() {
+            log_info!("R0  {:#010x} R1  {:#010x} R2  {:#010x} R3  {:#010x}", frame.r0, frame.r1, frame.r2, frame.r3);
+            log_info!("R12 {:#010x} LR  {:#010x} PC  {:#010x} xPSR {:#010x}", frame.r12, frame.lr, frame.pc, frame.xpsr);
+        } else {
+            log_info!("Exception frame not stacked (stacking fault)");
+        }
+
+        let regs = &self.callee_saved;
+        log_info!("R4  {:#010x} R5  {:#010x} R6  {:#010x} R7  {:#010x}", regs.r4, regs.r5, regs.r6, regs.r7);
+        log_info!("R8  {:#010x} R9  {:#010x} R10 {:#010x} R11 {:#010x}", regs.r8, regs.r9, regs.r10, regs.r11);
+
+        let words = (self.stack_words as usize).min(STACK_DUMP_WORDS);
+        for (i, line) in self.stack[..words].chunks(4).enumerate() {
+            log_info!("{:#010x} : {:08x?}", self.stack_address as usize + i * 16, line);
+        }
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        let words = unsafe {
+            core::slice::from_raw_parts(self as *const CrashReport as *const u32, size_of::<CrashReport>() / 4 - 1)
+        };
+        words.iter().fold(0x811C_9DC5, |checksum, word| checksum.rotate_left(5) ^ word)
+    }
+}
+
+/// Builds the crash report of a fault, stores it in `.noinit`, and logs it
+///
+/// # Arguments
+/// * `kind` - Fault exception
+/// * `exc_return` - EXC_RETURN value of the fault handler
+/// * `frame` - Exception frame, on the stack given by EXC_RETURN
+/// * `callee_saved` - R4-R11, saved by the fault handler
+/// * `from_process` - The fault comes from a process (Thread mode, Process Stack)
+pub fn record(kind: FaultKind, exc_return: u32, frame: *const ExceptionFrame, callee_saved: *const CalleeSavedRegisters, from_process: bool) -> CrashReport {
+    let cfsr = read_register(CFSR_ADDR);
+    let frame_valid = !frame.is_null() && (cfsr >> MSTKERR_BIT) & 1 == 0 && (cfsr >> STKERR_BIT) & 1 == 0;
+
+    let mut report = CrashReport {
+        magic: CRASH_MAGIC,
+        kind: kind as u32,
+        exc_return,
+        frame: if frame_valid { unsafe { ptr::read_volatile(frame) } } else { ExceptionFrame::default() },
+        frame_valid: frame_valid as u32,
+        callee_saved: if callee_saved.is_null() { CalleeSavedRegisters::default() } else { unsafe { ptr::read(callee_saved) } },
+        cfsr,
+        hfsr: read_register(HFSR_ADDR),
+        mmfar: read_register(MMFAR_ADDR),
+        bfar: read_register(BFAR_ADDR),
+        proc_id: KERNEL_PID,
+        proc_name: [0; PROC_NAME_LEN],
+        proc_name_len: 0,
+        stack_address: 0,
+        stack: [0; STACK_DUMP_WORDS],
+        stack_words: 0,
+        checksum: 0
+    };
+
+    // The fault may come from code holding the lock : never wait for it
+    let name = if !from_process {
+        "kernel"
+    } else {
+        match SYSTEM_PROCESS.try_lock() {
+            Some(system_process) => {
+                report.proc_id = system_process.get_current_process_id() as u32;
+                system_process.get_current_process_name().unwrap_or("?")
+            }
+            None => "?"
+        }
+    };
+    let name_len = name.len().min(PROC_NAME_LEN);
+    report.proc_name[..name_len].copy_from_slice(&name.as_bytes()[..name_len]);
+    report.proc_name_len = name_len as u32;
+
+    if frame_valid {
+        let frame_words = if exc_return & EXC_RETURN_STD_FRAME == 0 { FPU_FRAME_WORDS } else { FRAME_WORDS };
+        dump_stack(&mut report, frame as u32 + (frame_words * 4) as u32);
+    }
+
+    report.checksum = report.compute_checksum();
+    unsafe {
+        ptr::write_volatile(&raw mut LAST_CRASH, MaybeUninit::new(report));
+    }
+
+    report.log();
+    report
+}
+
+/// Gets the report of the last fault, kept across warm resets
+pub fn last_crash() -> Option<CrashReport> {
+    let report = unsafe { ptr::read_volatile(&raw const LAST_CRASH) };
+    // Before the first fault, `.noinit` holds whatever was in RAM : only the magic value is read
+    let magic = unsafe { ptr::read_volatile(report.as_ptr() as *const u32) };
+    if magic != CRASH_MAGIC {
+        return None;
+    }
+
+    let report = unsafe { report.assume_init() };
+    if report.checksum != report.compute_checksum() {
+        return None;
+    }
+    Some(report)
+}
+
+/// Forgets the report of the last fault
+#[allow(dead_code)]
+pub fn clear() {
+    unsafe {
+        ptr::write_volatile(&raw mut LAST_CRASH as *mut u32, 0);
+    }
+}
+
+/// Copies the stack words above the exception frame, within RAM, to the report
+fn dump_stack(report: &mut CrashReport, stack_address: u32) {
+    unsafe extern "C" {
+        static _sram: u32;
+        static _sstack: u32;
+    }
+    let ram_start = &raw const _sram as u32;
+    let ram_end = &raw const _sstack as u32;
+
+    if stack_address < ram_start || stack_address >= ram_end || !stack_address.is_multiple_of(4) {
+        return;
+    }
+    let words = (((ram_end - stack_address) / 4) as usize).min(STACK_DUMP_WORDS);
+    for (i, word) in report.stack[..words].iter_mut().enumerate() {
+        *word = unsafe { ptr::read_volatile((stack_address as *const u32).add(i)) };
+    }
+    report.stack_address = stack_address;
+    report.stack_words = words as u32;
+}
+
+fn read_register(address: u32) -> u32 {
+    unsafe { ptr::read_volatile(address as *const u32) }
+}
@@ -1,8 +1,12 @@
 use core::sync::atomic::{compiler_fence, Ordering};
-use crate::{log_debug, log_info};
+use crate::log_debug;
+use crate::syscall;
 use core::arch::asm;
 use cortex_m::interrupt;
+use cortex_m::register::{self, control::Npriv};
 use crate::SYSTEM_PROCESS;
+use crate::proc::{ExitStatus, FaultInfo, FaultKind, IpcError};
+use super::{crash_dump, systick};
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn DefaultHandler() -> ! {
@@ -22,8 +26,37 @@ pub unsafe extern "C" fn NMIHandler() -> ! {
 }
 
 
+/// Body of the fault handlers trampolines, calling `handler(exc_return, frame, callee_saved)`
+///
+/// Bit 2 of EXC_RETURN (LR) tells which stack holds the exception frame. R4-R11 are pushed on the
+/// Main Stack with EXC_RETURN, which is popped to PC to return from the exception.
+macro_rules! fault_trampoline {
+    ($handler:ident) => {
+        core::arch::naked_asm!(
+            "mov r0, lr",           // EXC_RETURN
+            "tst lr, #4",
+            "ite eq",
+            "mrseq r1, msp",        // Fault in the kernel (Main Stack)
+            "mrsne r1, psp",        // Fault in a process (Process Stack)
+            "push {{r4-r11, lr}}",
+            "mov r2, sp",           // Callee-saved registers
+            "sub sp, #4",           // Keep the stack 8-byte aligned
+            "bl {handler}",
+            "add sp, #4",
+            "pop {{r4-r11, pc}}",   // Return from the exception
+            handler = sym $handler,
+        )
+    };
+}
+
+/// Handles hard faults : gets EXC_RETURN, the exception frame and R4-R11, and hands them to `hard_fault`
+#[unsafe(naked)]
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn HardFaultHandler() -> ! {
+pub unsafe extern "C" fn HardFaultHandler() {
+    fault_trampoline!(hard_fault);
+}
+
+extern "C" fn hard_fault(exc_return: u32, frame: *const ExceptionFrame, callee_saved: *const CalleeSavedRegisters) {
 
     /*
         Hard Fault status Register
@@ -44,6 +77,7 @@ pub unsafe extern "C" fn HardFaultHandler() -> ! {
      */
 
     const HFSR_ADDR: u32 = 0xE000ED2C;
+    const CFSR_ADDR: u32 = 0xE000ED28;
     let hfsr_value: u32;
     unsafe {
         // Read the value from the HFSR address
@@ -56,12 +90,6 @@ pub unsafe extern "C" fn HardFaultHandler() -> ! {
     if debug_vt == 1 {
         log_debug!("Debug is used.");
     }
-    if forced == 1 {
-        // inspect other fault status registers
-        log_debug!("Forced hard fault. Need to inspect the other fault status registers.");
-
-        FaultHandler();
-    }
     if vecttbl == 1 {
         log_debug!("Bus fault while trying to read the vector table.");
         //asm!(
@@ -69,15 +97,23 @@ pub unsafe extern "C" fn HardFaultHandler() -> ! {
         //);
     }
 
-    // Keep the program in an infinite loop
-    loop {
-        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
-    }
+    let fault = if forced == 1 {
+        // inspect other fault status registers
+        log_debug!("Forced hard fault. Need to inspect the other fault status registers.");
+
+        FaultHandler(frame)
+    } else {
+        let cfsr_value = unsafe { core::ptr::read_volatile(CFSR_ADDR as *const u32) };
+        FaultInfo { kind: FaultKind::HardFault, cfsr: cfsr_value, pc: stacked_pc(frame, cfsr_value), address: None }
+    };
+
+    handle_fault(exc_return, fault, frame, callee_saved);
 }
 
 
+/// Decodes the fault escalated to a hard fault, from the Configurable Fault Status Register
 #[allow(non_snake_case)]
-fn FaultHandler() -> ! {
+fn FaultHandler(frame: *const ExceptionFrame) -> FaultInfo {
     const CFSR_ADDR: u32 = 0xE000ED28;
     let cfsr_value: u32;
 
@@ -98,27 +134,30 @@ fn FaultHandler() -> ! {
     let mmfsr_mask: u32 = 0b10111011;
 
     if (ufsr & ufsr_mask) != 0 {
-        unsafe {
-            UsageFaultHandler();
-        }
+        usage_fault_info(frame)
     } else if (bfsr & bfsr_mask) != 0 {
-        unsafe {
-            BusFaultHandler();
-        }
+        bus_fault_info(frame)
     } else if (mmfsr & mmfsr_mask) != 0 {
-        unsafe {
-            MemoryManagementFaultHandler();
-        }
-    }
-
-    loop {
-        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+        memory_management_fault_info(frame)
+    } else {
+        FaultInfo { kind: FaultKind::HardFault, cfsr: cfsr_value, pc: stacked_pc(frame, cfsr_value), address: None }
     }
 }
 
 
+/// Handles usage faults : gets EXC_RETURN, the exception frame and R4-R11, and hands them to `usage_fault`
+#[unsafe(naked)]
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn UsageFaultHandler() -> ! {
+pub unsafe extern "C" fn UsageFaultHandler() {
+    fault_trampoline!(usage_fault);
+}
+
+extern "C" fn usage_fault(exc_return: u32, frame: *const ExceptionFrame, callee_saved: *const CalleeSavedRegisters) {
+    handle_fault(exc_return, usage_fault_info(frame), frame, callee_saved);
+}
+
+/// Decodes and logs a usage fault
+fn usage_fault_info(frame: *const ExceptionFrame) -> FaultInfo {
     /*
         Configurable Fault Status Register
 
@@ -178,15 +217,23 @@ pub unsafe extern "C" fn UsageFaultHandler() -> ! {
         } 
     }
 
-    loop {
-        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
-    }
-
+    FaultInfo { kind: FaultKind::UsageFault, cfsr: cfsr_value, pc: stacked_pc(frame, cfsr_value), address: None }
 }
 
 
+/// Handles bus faults : gets EXC_RETURN, the exception frame and R4-R11, and hands them to `bus_fault`
+#[unsafe(naked)]
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn BusFaultHandler() -> ! {
+pub unsafe extern "C" fn BusFaultHandler() {
+    fault_trampoline!(bus_fault);
+}
+
+extern "C" fn bus_fault(exc_return: u32, frame: *const ExceptionFrame, callee_saved: *const CalleeSavedRegisters) {
+    handle_fault(exc_return, bus_fault_info(frame), frame, callee_saved);
+}
+
+/// Decodes and logs a bus fault
+fn bus_fault_info(frame: *const ExceptionFrame) -> FaultInfo {
     /*
         Configurable Fault Status Register
 
@@ -232,7 +279,7 @@ pub unsafe extern "C" fn BusFaultHandler() -> ! {
     const BFAR_ADDR: u32 = 0xE000ED38;
 
     let cfsr_value: u32;
-    let bfar_value: u32;
+    let mut bfar_value = None;
 
     unsafe {
         cfsr_value = core::ptr::read_volatile(CFSR_ADDR as *const u32);
@@ -270,8 +317,9 @@ pub unsafe extern "C" fn BusFaultHandler() -> ! {
 
     // Bus Fault Address Register (BFAR) valid flag.
     if (cfsr_value >> BFARVALID_BIT) & 1 == 1 {
-        bfar_value = GetFaultAddress(BFAR_ADDR);
-        log_debug!("Fault at address {:#X}", bfar_value);
+        let address = GetFaultAddress(BFAR_ADDR);
+        log_debug!("Fault at address {:#X}", address);
+        bfar_value = Some(address);
         // TO CHECK, PRINT LR/ EXC_RETURN value. (Seems to be but not referenced in the table exception return behavior)
         // OUTPUT : 
         //      Bus Fault.
@@ -279,14 +327,23 @@ pub unsafe extern "C" fn BusFaultHandler() -> ! {
         //      Fault at address 0xFFFFFFFC
     }
 
-    loop {
-        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
-    }
+    FaultInfo { kind: FaultKind::BusFault, cfsr: cfsr_value, pc: stacked_pc(frame, cfsr_value), address: bfar_value }
 }
 
 
+/// Handles memory management faults : gets EXC_RETURN, the exception frame and R4-R11, and hands them to `memory_management_fault`
+#[unsafe(naked)]
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn MemoryManagementFaultHandler() -> ! {
+pub unsafe extern "C" fn MemoryManagementFaultHandler() {
+    fault_trampoline!(memory_management_fault);
+}
+
+extern "C" fn memory_management_fault(exc_return: u32, frame: *const ExceptionFrame, callee_saved: *const CalleeSavedRegisters) {
+    handle_fault(exc_return, memory_management_fault_info(frame), frame, callee_saved);
+}
+
+/// Decodes and logs a memory management fault
+fn memory_management_fault_info(frame: *const ExceptionFrame) -> FaultInfo {
     /*
         Configurable Fault Status Register
 
@@ -330,7 +387,7 @@ pub unsafe extern "C" fn MemoryManagementFaultHandler() -> ! {
     const MMFAR_ADDR: u32 = 0xE000ED34;
 
     let cfsr_value: u32;
-    let mmfar_value: u32;
+    let mut mmfar_value = None;
 
     unsafe {
         cfsr_value = core::ptr::read_volatile(CFSR_ADDR as *const u32);
@@ -363,17 +420,98 @@ pub unsafe extern "C" fn MemoryManagementFaultHandler() -> ! {
         }
     }
 
+    let pc = stacked_pc(frame, cfsr_value);
+
     // Memory Management Fault Address Register (MMAR) valid flag.
     if (cfsr_value >> MMARVALID_BIT) & 1 == 1 {
-        mmfar_value = GetFaultAddress(MMFAR_ADDR);
-        log_debug!("Fault at address {:#X}", mmfar_value);
+        let address = GetFaultAddress(MMFAR_ADDR);
+        log_debug!("Fault at address {:#X}", address);
+        mmfar_value = Some(address);
+    } else if (cfsr_value >> IACCVIOL_BIT) & 1 == 1 {
+        // MMFAR is not written on an instruction fetch : the faulting address is the stacked PC,
+        // e.g. a jump into a stack or a data segment, which are execute-never
+        if let Some(pc) = pc {
+            log_debug!("Instruction fetch from execute-never or inaccessible memory at {:#X}", pc);
+        }
+        mmfar_value = pc;
     }
 
+    FaultInfo { kind: FaultKind::MemManage, cfsr: cfsr_value, pc, address: mmfar_value }
+}
+
+/// Records the crash report of the fault, then kills the faulting process, or halts the system if the
+/// fault comes from the kernel
+///
+/// Once the process is killed, the fault status bits are cleared (write 1 to clear) so that the next
+/// fault is reported properly, and the handler returns : PendSV then switches to the next process.
+fn handle_fault(exc_return: u32, fault: FaultInfo, frame: *const ExceptionFrame, callee_saved: *const CalleeSavedRegisters) {
+    const CFSR_ADDR: u32 = 0xE000ED28;
+    const HFSR_ADDR: u32 = 0xE000ED2C;
+
+    crash_dump::record(fault.kind, exc_return, frame, callee_saved, is_process_fault(exc_return));
+
+    if kill_faulting_process(exc_return, fault) {
+        unsafe {
+            core::ptr::write_volatile(CFSR_ADDR as *mut u32, fault.cfsr);
+            core::ptr::write_volatile(HFSR_ADDR as *mut u32, core::ptr::read_volatile(HFSR_ADDR as *const u32));
+        }
+        return;
+    }
+
+    log_debug!("Fault in the kernel, system halted.");
     loop {
         core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
     }
 }
 
+/// Bits 3 and 2 of EXC_RETURN tell if the faulting code ran in Thread mode on the Process Stack, i.e. in a process
+fn is_process_fault(exc_return: u32) -> bool {
+    const EXC_RETURN_THREAD_PSP: u32 = 0b1100;
+
+    exc_return & EXC_RETURN_THREAD_PSP == EXC_RETURN_THREAD_PSP
+}
+
+
+/// Kills the running process if the fault comes from it, and requests a context switch
+///
+/// Returns `false` if the fault comes from the kernel (handlers, or the idle process).
+fn kill_faulting_process(exc_return: u32, fault: FaultInfo) -> bool {
+    if !is_process_fault(exc_return) {
+        return false;
+    }
+
+    let pid = interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock(); // Lock the Mutex
+        if system_process.is_current_process_privileged() {
+            // The kernel idle process runs kernel code
+            return None;
+        }
+        system_process.exit_current_process(ExitStatus::Faulted(fault));
+        Some(system_process.get_current_process_id())
+    });
+    match pid {
+        Some(pid) => log_debug!("Process {} killed", pid),
+        None => return false
+    };
+
+    // The process stack may be invalid (stacking fault) : its context must not be saved
+    unsafe {
+        CURRENT_PROCESS_SP = 0;
+    }
+    trigger_pendsv();
+    true
+}
+
+/// PC stacked in the exception frame, unknown if the fault occurred while stacking the frame (MSTKERR, STKERR)
+fn stacked_pc(frame: *const ExceptionFrame, cfsr: u32) -> Option<u32> {
+    const MSTKERR_BIT: u32 = 4;
+    const STKERR_BIT: u32 = 12;
+
+    if frame.is_null() || (cfsr >> MSTKERR_BIT) & 1 == 1 || (cfsr >> STKERR_BIT) & 1 == 1 {
+        return None;
+    }
+    unsafe { Some(core::ptr::read_volatile(&raw const (*frame).pc)) }
+}
 
 #[allow(non_snake_case)]
 fn GetFaultAddress(ADDR: u32) -> u32 {
@@ -387,65 +525,88 @@ fn GetFaultAddress(ADDR: u32) -> u32 {
 }
 
 
+/// Number of SysTick periods between two heap integrity checks, with the `heap-verify` feature
+#[cfg(feature = "heap-verify")]
+const HEAP_VERIFY_PERIOD: u64 = 10;
+
+/// Increments the monotonic tick counter and accounts the elapsed tick to the processes (time slice, sleep deadlines),
+/// then triggers a context switch if the scheduler has to be called
+///
+/// With the `heap-verify` feature, the heap integrity is checked every `HEAP_VERIFY_PERIOD` ticks.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn SysTickHandler() {
-    trigger_pendsv();
+    let now = systick::increment_ticks();
+
+    let need_resched = interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock(); // Lock the Mutex
+
+        #[cfg(feature = "heap-verify")]
+        if now.is_multiple_of(HEAP_VERIFY_PERIOD) && system_process.verify_heap().is_err() {
+            panic!("Heap corruption detected!");
+        }
+
+        system_process.tick(now)
+    });
+
+    if need_resched {
+        trigger_pendsv();
+    }
 }
 
 
-/// Handles system calls (SVC) by processing the syscall number and its associated arguments.
+/// Handles system calls (SVC) : gets the exception frame stacked by the caller, and hands it to the syscall dispatcher.
+/// 
+/// Bit 2 of EXC_RETURN (LR) tells which stack the caller was using when the SVC was executed :
+/// - 0 : Main Stack (kernel)
+/// - 1 : Process Stack (process)
 /// 
-/// This function is typically invoked when a system call (SVC) instruction is executed in user mode,
-/// and it performs the corresponding action based on the syscall number.
-///
 /// # Call Convention
 ///     R0 <- SYSCALL_ID
 ///     R1 <- ARG0
 ///     R2 <- ARG1
 ///     R3 <- ARG2
+///     R0 -> Return value, or negative errno
 /// 
-/// # Syscalls
-/// - `0`: SYS_EXIT - Terminates the current process.
-/// - `1`: SYS_PRINT - Prints arg0 in hex
+/// See `syscall::dispatch` for the list of syscalls.
+#[unsafe(naked)]
 #[unsafe(no_mangle)]
-#[allow(unused_variables,unused_assignments)]
 pub unsafe extern "C" fn SVCallHandler() {
-    
-    let syscall_n: u32;
-    let arg0: u32;
-    let arg1: u32;
-    let arg2: u32;
+    core::arch::naked_asm!(
+        "tst lr, #4",
+        "ite eq",
+        "mrseq r0, msp",    // Caller used the Main Stack
+        "mrsne r0, psp",    // Caller used the Process Stack
+        "b {dispatch}",     // LR still holds EXC_RETURN, dispatch returns from the exception
+        dispatch = sym syscall::dispatch,
+    );
+}
 
-    unsafe { 
-        asm!(
-            "mov {0}, r0", 
-            "mov {1}, r1", 
-            "mov {2}, r2", 
-            "mov {3}, r3", 
-            out(reg) syscall_n, out(reg) arg0, out(reg) arg1, out(reg) arg2
-        ); 
-    }
+/// Exception frame stacked by the processor on exception entry
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ExceptionFrame {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32
+}
 
-    log_debug!("\n### SVCAll Handler ###");
-
-    // Handle the syscall based on the value of R0
-    match syscall_n {
-        0 => {
-            // SYS_EXIT
-            log_debug!("[SYS_EXIT] Return code {:#x}",arg0);
-            interrupt::free(|_cs| {
-                let mut system_process = SYSTEM_PROCESS.lock();
-                system_process.exit_current_process();
-            });
-        }
-        1 => {
-            // SYS_PRINT
-            log_info!("[SYS_PRINT] {:#x}",arg0);
-        }
-        _ => {
-            log_debug!("Unknown syscall : {}", syscall_n);
-        }
-    }
+/// Callee-saved registers, pushed by the fault handlers
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct CalleeSavedRegisters {
+    pub r4: u32,
+    pub r5: u32,
+    pub r6: u32,
+    pub r7: u32,
+    pub r8: u32,
+    pub r9: u32,
+    pub r10: u32,
+    pub r11: u32
 }
 
 /// PendSV_Handler performing context switch
@@ -482,6 +643,8 @@ pub unsafe extern "C" fn SVCallHandler() {
 /// - Saves the state (stack pointer and callee-saved registers) of the current process.
 /// - Schedules the next process using the scheduler.
 /// - Restores the state (stack pointer and callee-saved registers) of the next process.
+/// - Enables the MPU regions of the next process, and drops the privileges of Thread mode (CONTROL.nPRIV)
+///   unless the next process is the kernel idle process.
 /// - Re-enables interrupts and returns to the process that will resume execution.
 #[unsafe(no_mangle)]
 #[allow(static_mut_refs)]
@@ -527,11 +690,17 @@ pub unsafe extern "C" fn PendSV_Handler() {
             );
         }
 
-        interrupt::free(|_cs| {
+        let privileged = interrupt::free(|_cs| {
             let system_process = SYSTEM_PROCESS.lock(); // Lock the Mutex
             system_process.enable_current_mpu();
+            system_process.is_current_process_privileged()
         });
 
+        // Processes run unprivileged (CONTROL.nPRIV = 1), only the kernel idle process runs privileged
+        let mut control = register::control::read();
+        control.set_npriv(if privileged { Npriv::Privileged } else { Npriv::Unprivileged });
+        register::control::write(control);
+
         asm!(
             "CPSIE I",  // Enable interrupts
             "isb",
@@ -556,4 +725,22 @@ pub fn trigger_pendsv() {
     unsafe {
     core::ptr::write_volatile(icsr, PENDSVSET);
     }
-}
\ No newline at end of file
+}
+/// Notifies events to a process from an interrupt handler (SysTick, peripheral IRQ) or any kernel code
+///
+/// The process list is only updated within critical sections, so that the interrupted code never holds it.
+/// If the notified process becomes ready and has a higher priority than the running one, the context switch
+/// happens when the handler returns.
+#[allow(dead_code)]
+pub fn notify_process(proc_id: u16, events: u32) -> Result<(), IpcError> {
+    let need_resched = interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock(); // Lock the Mutex
+        system_process.notify(proc_id, events)?;
+        Ok(system_process.need_resched())
+    })?;
+
+    if need_resched {
+        trigger_pendsv();
+    }
+    Ok(())
+}
@@ -46,19 +46,24 @@ use core::sync::atomic::{compiler_fence, Ordering};
 
 mod panic;
 mod handlers;
-mod systick;
+pub mod crash_dump;
+pub mod systick;
 pub use crate::init::systick::SysTick;
 use crate::main;
-pub use crate::init::handlers::{CURRENT_PROCESS_SP, NEXT_PROCESS_SP};
+pub use crate::init::handlers::{CURRENT_PROCESS_SP, NEXT_PROCESS_SP, ExceptionFrame, trigger_pendsv};
+#[allow(unused_imports)]
+pub use crate::init::handlers::notify_process;
+#[allow(unused_imports)]
+pub use crate::init::handlers::CalleeSavedRegisters;
 
 #[repr(C)]
 #[allow(non_snake_case)]
 pub struct ExceptionsHandlers {
     NMI: unsafe extern "C" fn() -> !,
-    HardFault: unsafe extern "C" fn() -> !,
-    MemManage: unsafe extern "C" fn() -> !,
-    BusFault: unsafe extern "C" fn() -> !,
-    UsageFault: unsafe extern "C" fn() -> !,
+    HardFault: unsafe extern "C" fn(),
+    MemManage: unsafe extern "C" fn(),
+    BusFault: unsafe extern "C" fn(),
+    UsageFault: unsafe extern "C" fn(),
     Reserved_7: u32,
     Reserved_8: u32,
     Reserved_9: u32,
@@ -124,7 +129,7 @@ pub unsafe fn setup_priority_handler() {
      */
 
      /*
-        PendSV : 0
+        PendSV : 0xFF (lowest, so that a context switch requested by a syscall happens once SVCall returns)
         SysTick : 0
         SVCall : 2
       */
@@ -139,11 +144,11 @@ pub unsafe fn setup_priority_handler() {
         let mut shpr3_value: u32 = core::ptr::read_volatile(SHPR3_ADDR as *const u32);
 
         // Modify SHPR3:
-        // PendSV (PRI_14: Bits 23-16) -> Priority 0
+        // PendSV (PRI_14: Bits 23-16) -> Priority 0xFF
         // SysTick (PRI_15: Bits 31-24) -> Priority 0
         shpr3_value &= !(0xFF << 16); // Clear bits for PendSV
         shpr3_value &= !(0xFF << 24); // Clear bits for SysTick
-        shpr3_value |= 0x00 << 16;    // Set PendSV to 0
+        shpr3_value |= 0xFF << 16;    // Set PendSV to 0xFF
         shpr3_value |= 0x00 << 24;    // Set SysTick to 0
         core::ptr::write_volatile(SHPR3_ADDR as *mut u32, shpr3_value);
 
@@ -1,9 +1,38 @@
+use cortex_m::interrupt;
 use crate::log_debug;
 
 const SYST_CSR_ADDR: u32 = 0xE000_E010;
 const SYST_RVR_ADDR: u32 = 0xE000_E014;
 const SYST_CALIB_ADDR: u32 = 0xE000_E01C;
 
+/// Monotonic counter of SysTick periods elapsed since SysTick was started
+static mut TICKS: u64 = 0;
+/// Effective SysTick period in microseconds, as configured by `set_sys_tick_reload_us`
+static mut TICK_PERIOD_US: u64 = 0;
+
+/// Get the number of SysTick periods elapsed since SysTick was started
+#[allow(dead_code)]
+pub fn get_ticks() -> u64 {
+    interrupt::free(|_cs| unsafe { TICKS })
+}
+
+/// Increment the tick counter, called on each SysTick exception
+pub(super) fn increment_ticks() -> u64 {
+    interrupt::free(|_cs| unsafe {
+        TICKS += 1;
+        TICKS
+    })
+}
+
+/// Convert a duration in milliseconds to a number of SysTick periods, rounded up
+pub fn ms_to_ticks(ms: u32) -> u64 {
+    let period_us = interrupt::free(|_cs| unsafe { TICK_PERIOD_US });
+    if period_us == 0 {
+        return ms as u64;
+    }
+    (ms as u64 * 1000).div_ceil(period_us)
+}
+
 pub struct SysTick {
     freq: u32
 }
@@ -52,6 +81,10 @@ impl SysTick {
         // Sanitize reload_value
         reload_value &= 0x00FF_FFFF;
 
+        // Keep track of the effective period, used to convert durations to ticks
+        let period_us = if self.freq != 0 { (reload_value * 1_000_000) / self.freq as u64 } else { us_time };
+        interrupt::free(|_cs| unsafe { TICK_PERIOD_US = period_us });
+
         unsafe {
             //Set SysTick Reload Value Register
             syst_rvr = core::ptr::read_volatile(SYST_RVR_ADDR as *const u32);
@@ -4,6 +4,7 @@
 #![no_main]
 
 #![feature(custom_test_frameworks)]
+#![feature(allocator_api)]
 #![test_runner(test::test_runner)]
 #![reexport_test_harness_main = "test_runner"]
 
@@ -14,6 +15,8 @@ mod utils;
 mod test;
 mod proc;
 mod memory_management;
+mod syscall;
+mod entropy;
 
 use crate::proc::SystemProcess;
 use init::SysTick;
@@ -28,8 +31,8 @@ lazy_static! {
 
 /// Krust main function called by the Reset handler
 /// 
-/// Through function calls, enables System Handlers, System Heap and SysTick
-/// Create 2 process, proc_1 and proc_2, then start SysTick
+/// Through function calls, enables System Handlers, seeds the entropy subsystem, enables System Heap, the kernel pools and SysTick
+/// Create the idle process and 2 process, proc_1 and proc_2, then start SysTick
 pub fn main() -> ! {
     
     log_debug!("=== KRUST ===");
@@ -37,8 +40,16 @@ pub fn main() -> ! {
     unsafe {
         init::enable_system_handler_fault();
         init::setup_priority_handler();
+        entropy::init();
         memory_management::heap::initialize_heap();
     }
+    memory_management::pool::init();
+
+    // Post-mortem report of a fault before the last warm reset
+    if let Some(report) = init::crash_dump::last_crash() {
+        log_debug!("Crash report of the previous run :");
+        report.log();
+    }
 
     #[cfg(test)]
     test_runner();
@@ -51,12 +62,21 @@ pub fn main() -> ! {
 
     let mut pid: u16;
 
+    SYSTEM_PROCESS.lock().create_idle_process();
+
+    // Programs the processes may start with SYS_SPAWN
+    {
+        let mut system_process = SYSTEM_PROCESS.lock(); // Lock the Mutex
+        system_process.register_program("proc_1", PROC_1_ELF, 1).expect("Too many programs");
+        system_process.register_program("proc_2", PROC_2_ELF, 0).expect("Too many programs");
+    }
+
     log_debug!("\n### NEW PROC 1 ###");
 
     // Create PROC 1
     {
         let mut system_process = SYSTEM_PROCESS.lock(); // Lock the Mutex
-        pid = system_process.create_process("proc_1", TEST_1_PROC_BYTE_CODE, TEST_1_PROC_BYTE_CODE.len(), 1);
+        pid = system_process.create_process_from_elf("proc_1", PROC_1_ELF, 1).expect("Invalid proc_1 image");
 
         let proc = system_process.get_process_by_id(pid).expect("No process with this ID");
 
@@ -70,7 +90,7 @@ pub fn main() -> ! {
     // Create PROC 2
     {
         let mut system_process = SYSTEM_PROCESS.lock(); // Lock the Mutex
-        pid = system_process.create_process("proc_2", TEST_2_PROC_BYTE_CODE, TEST_2_PROC_BYTE_CODE.len(), 0);
+        pid = system_process.create_process_from_elf("proc_2", PROC_2_ELF, 0).expect("Invalid proc_2 image");
 
         let proc = system_process.get_process_by_id(pid).expect("No process with this ID");
 
@@ -84,58 +104,8 @@ pub fn main() -> ! {
     loop{}
 }
 
-/// proc_1 bytecode
-const TEST_1_PROC_BYTE_CODE: &[u8;64] = b"\x0b\x4a\x0c\x49\x08\xf1\x01\x08\x88\x45\xfb\xdb\x09\xf1\x01\x09\x89\x45\xf7\xdb\x4f\xf0\x01\x00\x07\x49\x00\xdf\x00\xf1\x01\x00\x88\x42\xef\xdb\x4f\xf0\x00\x00\x04\x49\x00\xdf\xfe\xe7\x00\xbf\xde\xc0\xad\x0b\xff\xff\xff\xff\x01\x70\xad\x0b\x01\xc0\xad\x0b";
-// LDR R2, =0xbadc0de
-// LDR R1, =0xffffffff
-
-// loop_0:
-//     loop_1:
-//         loop_2:
-//             ADD R8, R8, #1
-//             CMP R8, R1
-//             BLT loop_2
-//         ADD R9, R9, #1
-//         CMP R9, R1
-//         BLT loop_1
-//     MOV R0, #1
-//     LDR R1, =0xbad7001
-//     SVC 0
-//     ADD R0, R0, #1
-//     CMP R0, R1
-//     BLT loop_0
-
-// MOV R0, #0
-// LDR R1, =0xbadc001
-// SVC 0
-
-// end_loop:
-//     B end_loop
-
-/// proc_2 bytecode
-const TEST_2_PROC_BYTE_CODE: &[u8;64] = b"\x0b\x4a\x0c\x49\x08\xf1\x01\x08\x88\x45\xfb\xdb\x09\xf1\x01\x09\x89\x45\xf7\xdb\x4f\xf0\x01\x00\x07\x49\x00\xdf\x00\xf1\x01\x00\x88\x42\xef\xdb\x4f\xf0\x00\x00\x04\x49\x00\xdf\xfe\xe7\x00\xbf\xde\xc0\xad\xde\xff\xff\xff\xff\x01\x70\xad\xde\x01\xc0\xad\xde";
-// LDR R2, =0xdeadc0de
-// LDR R1, = 0xffffffff
-
-// loop_0:
-//     loop_1:
-//         loop_2:
-//             ADD R8, R8, #1
-//             CMP R8, R1
-//             BLT loop_2
-//         ADD R9, R9, #1
-//         CMP R9, R1
-//         BLT loop_1
-//     MOV R0, #1
-//     LDR R1, =0xdead7001
-//     SVC 0
-//     ADD R0, R0, #1
-//     CMP R0, R1
-//     BLT loop_0
-
-// MOV R0, #0
-// LDR R1, =0xdeadc001
-// SVC 0
-
-// end_loop:
-//     B end_loop
\ No newline at end of file
+/// proc_1 image, built from `krust-user/examples/proc_1.rs`
+const PROC_1_ELF: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/proc_1.elf"));
+
+/// proc_2 image, built from `krust-user/examples/proc_2.rs`
+const PROC_2_ELF: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/proc_2.elf"));
@@ -1,26 +1,81 @@
+//! Global allocator of the kernel, on top of the kernel heap
+//!
+//! The heap only guarantees `usize` alignment. Layouts aligned on more than a word are allocated with
+//! `heap::allocate_aligned`, which places the heap block itself on the alignment : reserving `align` more
+//! bytes would about double the memory used by the large MPU-aligned layouts.
+//!
+//! Other layouts reserve `align` more bytes, the allocator returns the first aligned address after a word
+//! holding the offset back to the start of the heap block, and reads this offset back on `dealloc` and `realloc`.
+//!
+//! ```
+//! Heap block
+//! +--------+ < heap::allocate
+//! | ...    | Padding
+//! +--------+
+//! | OFFSET | Distance between the heap block and the returned pointer
+//! +--------+ < returned pointer (aligned on layout.align())
+//! | ...    | layout.size() bytes
+//! +--------+
+//! ```
+
 use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::ptr;
 use super::heap;
 
+const OFFSET_SIZE: usize = size_of::<usize>();
+
 pub struct SimpleAllocator;
 
+impl SimpleAllocator {
+    /// Check if a layout is allocated on its alignment by the heap, without the offset word
+    fn is_heap_aligned(layout: Layout) -> bool {
+        layout.align() > OFFSET_SIZE
+    }
+
+    /// Gets the heap block of a pointer returned by `alloc`, and its offset in this block
+    unsafe fn block_of(ptr: *mut u8, layout: Layout) -> (*mut u8, usize) {
+        if Self::is_heap_aligned(layout) {
+            return (ptr, 0);
+        }
+        unsafe {
+            let offset = ptr::read((ptr as *mut usize).sub(1));
+            (ptr.sub(offset), offset)
+        }
+    }
+}
+
 unsafe impl GlobalAlloc for SimpleAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if Self::is_heap_aligned(layout) {
+            return unsafe { heap::allocate_aligned(layout.size(), layout.align()) };
+        }
+
+        let align = layout.align().max(OFFSET_SIZE);
+        let block = unsafe { heap::allocate(layout.size() + OFFSET_SIZE + align - 1) };
+        if block.is_null() {
+            return ptr::null_mut();
+        }
+
+        let aligned = (block as usize + OFFSET_SIZE + align - 1) & !(align - 1);
         unsafe {
-            heap::allocate(layout.size() + layout.align())
+            ptr::write((aligned as *mut usize).sub(1), aligned - block as usize);
         }
+        aligned as *mut u8
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         unsafe {
-            heap::deallocate(ptr);
+            let (block, _) = Self::block_of(ptr, layout);
+            heap::deallocate(block);
         }
     }
 
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
         unsafe {
-            let ptr = heap::allocate(layout.size() + layout.align());
+            let ptr = self.alloc(layout);
             if !ptr.is_null() {
-                heap::zeroes_region(ptr);
+                ptr::write_bytes(ptr, 0, layout.size());
             }
             ptr
         }
@@ -28,10 +83,16 @@ unsafe impl GlobalAlloc for SimpleAllocator {
 
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
         unsafe {
-            let new_ptr = heap::allocate(new_size + layout.align());
-            if !new_ptr.is_null() && !ptr.is_null() {
-                core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size());
-                heap::deallocate(ptr);
+            // Grow or shrink the heap block without moving the data if possible
+            let (block, offset) = Self::block_of(ptr, layout);
+            if heap::resize_in_place(block, offset + new_size) {
+                return ptr;
+            }
+
+            let new_ptr = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+            if !new_ptr.is_null() {
+                ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
             }
             new_ptr
         }
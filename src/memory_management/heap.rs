@@ -1,21 +1,101 @@
 use core::mem::{align_of, size_of};
 use core::ptr;
+use cortex_m::interrupt;
+use cortex_m::register::primask;
 use crate::check_cookie;
+use crate::entropy;
 
-const HEAP_SIZE: usize = 0x10000; // Taille totale de la heap (RAM/2)
 const ALIGNMENT: usize = align_of::<usize>();
 const ALIGNMENT_MASK: usize = !(ALIGNMENT - 1);
 
-#[unsafe(link_section = ".ram_heap")]
-static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
-
 static mut HEAP_INIT: bool = false;
 
+/// Number of heap regions
+pub const HEAP_REGION_COUNT: usize = 2;
+
+/// Memory areas given to the heap by the linker script (`_ram_heap_start`, `_ccm_heap_start`, ...)
+///
+/// The free blocks of all the regions are chained in the same free list, ordered by address. The regions
+/// must not be contiguous, so that their blocks are never merged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeapRegion {
+    /// Main SRAM (`.ram_heap`), used by default
+    Sram,
+    /// Core Coupled Memory (`.ccm_heap`), only used when requested with `allocate_in`
+    Ccm
+}
+
+/// Attributes of a heap region
+#[allow(dead_code)]
+pub mod region_attr {
+    /// Reachable by the DMA controllers
+    pub const DMA_CAPABLE: u32 = 1 << 0;
+    /// Reachable by the instruction bus, may hold process code
+    pub const EXECUTABLE: u32 = 1 << 1;
+}
+
+impl HeapRegion {
+    /// Heap regions, by address
+    pub const ALL: [HeapRegion; HEAP_REGION_COUNT] = [HeapRegion::Ccm, HeapRegion::Sram];
+
+    /// Attributes of the region, see `region_attr`
+    #[allow(dead_code)]
+    pub fn attributes(self) -> u32 {
+        match self {
+            HeapRegion::Sram => region_attr::DMA_CAPABLE | region_attr::EXECUTABLE,
+            // The CCM is only connected to the data bus of the core
+            HeapRegion::Ccm => 0
+        }
+    }
+
+    /// Start and end addresses of the region, as provided by the linker script, the start being aligned
+    /// on `ALIGNMENT`. The region is empty if the linker script gives it no memory.
+    pub fn bounds(self) -> (usize, usize) {
+        unsafe extern "C" {
+            static _ram_heap_start: u8;
+            static _ram_heap_end: u8;
+            static _ccm_heap_start: u8;
+            static _ccm_heap_end: u8;
+        }
+        let (start, end) = match self {
+            HeapRegion::Sram => (&raw const _ram_heap_start as usize, &raw const _ram_heap_end as usize),
+            HeapRegion::Ccm => (&raw const _ccm_heap_start as usize, &raw const _ccm_heap_end as usize)
+        };
+        let aligned_start = (start + ALIGNMENT - 1) & ALIGNMENT_MASK;
+        (aligned_start, end.max(aligned_start))
+    }
+
+    /// Check if `address` is in the region
+    pub fn contains(self, address: usize) -> bool {
+        let (start, end) = self.bounds();
+        (start..end).contains(&address)
+    }
+
+    /// Check if the region has room for at least one block
+    fn is_usable(self) -> bool {
+        let (start, end) = self.bounds();
+        end - start >= MINIMUM_BLOCK_SIZE
+    }
+}
+
+/// Number of regions holding memory, the others being left empty by the linker script
+#[allow(dead_code)]
+pub fn region_count() -> usize {
+    HeapRegion::ALL.iter().filter(|region| region.is_usable()).count()
+}
+
+/// Random value mixed in the end cookies, drawn when the heap is initialized
+static mut HEAP_SECRET: usize = 0;
+/// Odd multiplier spreading the block address over all the bits of the end cookie
+const ADDRESS_MIX: usize = 0x9E37_79B9;
+
 #[derive(Debug)]
 struct BlockLink {
     next_free: *mut BlockLink,
     block_size: usize,
     cookie: usize,
+    /// PID of the process owning the block, `KERNEL_PID` for kernel memory
+    owner: u16,
 }
 
 const BLOCK_HEADER_SIZE: usize = size_of::<BlockLink>();
@@ -26,59 +106,204 @@ static mut START: BlockLink = BlockLink {
     next_free: ptr::null_mut(),
     block_size: 0,
     cookie: 0,
+    owner: KERNEL_PID,
 };
 
 static mut END: BlockLink = BlockLink {
     next_free: ptr::null_mut(),
     block_size: 0,
     cookie: 0,
+    owner: KERNEL_PID,
 };
 
-static mut FREE_BYTES_REMAINING: usize = HEAP_SIZE;
+/// Owner of the blocks allocated by the kernel for itself
+pub const KERNEL_PID: u16 = 0;
+
+/// Process a block is allocated for, and the maximum heap memory this process may own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Owner {
+    pub pid: u16,
+    /// Maximum size of the blocks owned by the process, headers included
+    pub quota: usize
+}
+
+/// The kernel, without quota
+pub const KERNEL_OWNER: Owner = Owner { pid: KERNEL_PID, quota: usize::MAX };
+
+/// Heap memory held by a process
+#[derive(Clone, Copy)]
+struct OwnerUsage {
+    pid: u16,
+    /// Size of the blocks owned by the process, headers included
+    bytes: usize
+}
+
+/// Maximum number of processes owning heap blocks at the same time
+const MAX_OWNERS: usize = 32;
+
+/// Heap memory held by each process, updated when a block is allocated, given to a process or freed, so that
+/// quotas are checked without walking the heap. A slot is free when its PID is `KERNEL_PID`.
+static mut OWNER_USAGE: [OwnerUsage; MAX_OWNERS] = [OwnerUsage { pid: KERNEL_PID, bytes: 0 }; MAX_OWNERS];
+
+static mut FREE_BYTES_REMAINING: usize = 0;
+static mut MINIMUM_EVER_FREE_BYTES: usize = 0;
+static mut ALLOCATION_COUNT: usize = 0;
+static mut FREE_COUNT: usize = 0;
+
+/// Heap usage and fragmentation statistics, see `heap_stats`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeapStats {
+    /// Free bytes, block headers included
+    pub free_bytes: usize,
+    /// Size of the largest free block, header included
+    pub largest_free_block: usize,
+    /// Number of blocks in the free list
+    pub free_fragments: usize,
+    /// Lowest value of `free_bytes` since the heap was initialized
+    pub minimum_ever_free_bytes: usize,
+    /// Number of successful allocations since the heap was initialized
+    pub allocation_count: usize,
+    /// Number of blocks freed since the heap was initialized
+    pub free_count: usize
+}
+
+/// Corruption found by `verify`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeapCorruption {
+    /// Block size null, unaligned, too small, or going past the end of the heap
+    InvalidSize,
+    /// Cookies of an allocated block don't match : the block, or the end of the previous block, overflowed
+    BadCookie,
+    /// Free list not ordered by address, or pointing outside of the heap or inside a block
+    BadFreeList,
+    /// Two contiguous free blocks, which should have been merged
+    NotCoalesced,
+    /// The sizes of the free blocks don't sum up to the free bytes count
+    FreeBytesMismatch
+}
+
+/// First corrupted block found by `verify`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeapError {
+    pub corruption: HeapCorruption,
+    /// Address of the block header
+    pub block: usize,
+    /// Address of the user memory of the block, as returned by `allocate`
+    pub user_memory: usize,
+    /// Owner of the block, as read from its header, if it is an allocated block
+    pub owner: Option<u16>
+}
+
+/// Blocks walked by `verify`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HeapReport {
+    pub allocated_blocks: usize,
+    /// Size of the allocated blocks, headers included
+    pub allocated_bytes: usize,
+    pub free_blocks: usize,
+    /// Size of the free blocks, headers included
+    pub free_bytes: usize
+}
 
+/// Masks interrupts while the heap is used, so that an interrupt handler (allocating, or checking the
+/// heap with `verify`) never sees a half-updated heap. The previous PRIMASK state is restored on drop.
+struct HeapGuard {
+    interrupts_enabled: bool
+}
+
+impl HeapGuard {
+    fn new() -> Self {
+        let interrupts_enabled = primask::read().is_active();
+        interrupt::disable();
+        HeapGuard { interrupts_enabled }
+    }
+}
+
+impl Drop for HeapGuard {
+    fn drop(&mut self) {
+        if self.interrupts_enabled {
+            unsafe { interrupt::enable() };
+        }
+    }
+}
+
+/// Initializes the heap : each usable region becomes a free block, chained by address
 #[allow(unsafe_op_in_unsafe_fn)]
 pub unsafe fn initialize_heap() -> () {
     if HEAP_INIT {
         return;
     }
 
-    let aligned_heap_start = (&raw const HEAP as *const _ as usize + (ALIGNMENT - 1)) & ALIGNMENT_MASK;
-    let aligned_heap_end = &raw const HEAP as *const _ as usize + HEAP_SIZE;
-    let heap_size = aligned_heap_end - aligned_heap_start;
-
-    let first_free = aligned_heap_start as *mut BlockLink;
-    
-    (*first_free).next_free = &raw const END as *mut _;
-    (*first_free).block_size = heap_size - BLOCK_HEADER_SIZE;
-
-    START.next_free = first_free;
+    START.next_free = &raw const END as *mut _;
     START.block_size = 0;
+    OWNER_USAGE = [OwnerUsage { pid: KERNEL_PID, bytes: 0 }; MAX_OWNERS];
 
     END.next_free = ptr::null_mut();
     END.block_size = 0;
 
-    FREE_BYTES_REMAINING = heap_size - BLOCK_HEADER_SIZE;
+    let mut heap_size = 0;
+    let mut previous_block = &raw const START as *mut BlockLink;
+    for region in HeapRegion::ALL.iter().filter(|region| region.is_usable()) {
+        let (start, end) = region.bounds();
+        let free_block = start as *mut BlockLink;
+        (*free_block).next_free = &raw const END as *mut _;
+        (*free_block).block_size = (end - start) & ALIGNMENT_MASK;
+
+        (*previous_block).next_free = free_block;
+        previous_block = free_block;
+        heap_size += (*free_block).block_size;
+    }
+
+    FREE_BYTES_REMAINING = heap_size;
+    MINIMUM_EVER_FREE_BYTES = heap_size;
+    ALLOCATION_COUNT = 0;
+    FREE_COUNT = 0;
+    HEAP_SECRET = entropy::random_usize();
     HEAP_INIT = true;
 }
 
+/// Allocates `wanted_size` bytes of kernel memory
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn allocate(wanted_size: usize) -> *mut u8 {
+    allocate_for(KERNEL_OWNER, wanted_size)
+}
+
+/// Allocates `wanted_size` bytes owned by a process, fails if the process would exceed its quota
+///
+/// The block is released with `deallocate`, or with all the blocks of the process by `reclaim`.
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn allocate_for(owner: Owner, wanted_size: usize) -> *mut u8 {
+    allocate_in_region(HeapRegion::Sram, owner, wanted_size)
+}
+
+/// Allocates `wanted_size` bytes of kernel memory in a specific heap region
+///
+/// Used for memory with specific needs : for instance, buffers of the DMA controllers must not be
+/// allocated in a region without `region_attr::DMA_CAPABLE`.
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn allocate_in(region: HeapRegion, wanted_size: usize) -> *mut u8 {
+    allocate_in_region(region, KERNEL_OWNER, wanted_size)
+}
+
+/// First-fit allocation of `wanted_size` bytes owned by `owner`, from the free blocks of `region`
 #[allow(unsafe_op_in_unsafe_fn)]
-pub unsafe fn allocate(mut wanted_size: usize) -> *mut u8 {
+unsafe fn allocate_in_region(region: HeapRegion, owner: Owner, mut wanted_size: usize) -> *mut u8 {
     if wanted_size == 0 {
         return ptr::null_mut();
     }
+    let _guard = HeapGuard::new();
 
-    // Add the header size and space for the end cookie
-    wanted_size += BLOCK_HEADER_SIZE + size_of::<usize>();
+    // Add the header size and space for the end cookie, aligned
+    wanted_size = block_size_for(wanted_size);
 
-    // Align the requested size
-    if wanted_size & ALIGNMENT_MASK != 0 {
-        wanted_size = (wanted_size + ALIGNMENT) & ALIGNMENT_MASK;
+    if !is_within_quota(owner, wanted_size) {
+        return ptr::null_mut();
     }
 
     let mut previous_block = &raw const START as *mut _;
     let mut current_block = START.next_free;
 
-    while !current_block.is_null() && (*current_block).block_size < wanted_size {
+    while !current_block.is_null() && ((*current_block).block_size < wanted_size || !region.contains(current_block as usize)) {
         previous_block = current_block;
         current_block = (*current_block).next_free;
     }
@@ -87,8 +312,78 @@ pub unsafe fn allocate(mut wanted_size: usize) -> *mut u8 {
         return ptr::null_mut(); // Allocation failed
     }
 
-    let allocated_block = current_block;
+    allocate_block(previous_block, current_block, wanted_size, owner.pid)
+}
+
+/// Allocates `wanted_size` bytes whose address is a multiple of `align` (a power of two)
+///
+/// Used for memory protected by the MPU, whose regions must be aligned on their size. The free space
+/// before the aligned address stays in the free list, so the block is freed with `deallocate`.
+/// The block is allocated in main SRAM, as it may hold process code.
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn allocate_aligned(wanted_size: usize, align: usize) -> *mut u8 {
+    allocate_aligned_for(KERNEL_OWNER, wanted_size, align)
+}
+
+/// Allocates `wanted_size` bytes aligned on `align` (see `allocate_aligned`), owned by a process
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn allocate_aligned_for(owner: Owner, wanted_size: usize, align: usize) -> *mut u8 {
+    if align <= ALIGNMENT {
+        return allocate_for(owner, wanted_size);
+    }
+    if wanted_size == 0 || !align.is_power_of_two() {
+        return ptr::null_mut();
+    }
+    let _guard = HeapGuard::new();
 
+    let wanted_size = block_size_for(wanted_size);
+    if !is_within_quota(owner, wanted_size) {
+        return ptr::null_mut();
+    }
+
+    let mut previous_block = &raw const START as *mut BlockLink;
+    let mut current_block = START.next_free;
+
+    while !current_block.is_null() && !ptr::eq(current_block, &raw const END) {
+        let block_start = current_block as usize;
+        let block_end = block_start + (*current_block).block_size;
+        if !HeapRegion::Sram.contains(block_start) {
+            previous_block = current_block;
+            current_block = (*current_block).next_free;
+            continue;
+        }
+
+        // The space left before the aligned block must be large enough to stay a free block
+        let mut user_memory = (block_start + BLOCK_HEADER_SIZE + align - 1) & !(align - 1);
+        if user_memory - BLOCK_HEADER_SIZE != block_start && user_memory - BLOCK_HEADER_SIZE - block_start < MINIMUM_BLOCK_SIZE {
+            user_memory = (block_start + BLOCK_HEADER_SIZE + MINIMUM_BLOCK_SIZE + align - 1) & !(align - 1);
+        }
+        let aligned_block = (user_memory - BLOCK_HEADER_SIZE) as *mut BlockLink;
+
+        if aligned_block as usize + wanted_size <= block_end {
+            if aligned_block != current_block {
+                // Split the leading free space from the aligned block
+                let leading_size = aligned_block as usize - block_start;
+                (*aligned_block).block_size = (*current_block).block_size - leading_size;
+                (*aligned_block).next_free = (*current_block).next_free;
+                (*current_block).block_size = leading_size;
+                (*current_block).next_free = aligned_block;
+                previous_block = current_block;
+            }
+            return allocate_block(previous_block, aligned_block, wanted_size, owner.pid);
+        }
+
+        previous_block = current_block;
+        current_block = (*current_block).next_free;
+    }
+
+    ptr::null_mut() // Allocation failed
+}
+
+/// Removes `allocated_block` from the free list, splitting it if it is larger than `wanted_size`,
+/// and returns a pointer to its user memory
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn allocate_block(previous_block: *mut BlockLink, allocated_block: *mut BlockLink, wanted_size: usize, owner: u16) -> *mut u8 {
     if (*allocated_block).block_size - wanted_size >= MINIMUM_BLOCK_SIZE {
         // Split the block if possible
         let new_block = (allocated_block as usize + wanted_size) as *mut BlockLink;
@@ -104,65 +399,146 @@ pub unsafe fn allocate(mut wanted_size: usize) -> *mut u8 {
     }
 
     FREE_BYTES_REMAINING -= (*allocated_block).block_size;
+    MINIMUM_EVER_FREE_BYTES = MINIMUM_EVER_FREE_BYTES.min(FREE_BYTES_REMAINING);
+    ALLOCATION_COUNT += 1;
+    (*allocated_block).owner = owner;
+    // A slot is available, checked by `is_within_quota`
+    add_usage(owner, (*allocated_block).block_size);
 
-    // Generate a unique COOKIE for this block
-    let cookie = generate_random();
-    (*allocated_block).cookie = cookie; // Store the first cookie in the structure
-
-    let user_memory = (allocated_block as *mut u8).add(BLOCK_HEADER_SIZE);
-    *(user_memory.add((*allocated_block).block_size - size_of::<usize>()) as *mut usize) = cookie;
+    // Generate a random COOKIE for this block, stored in the header and at the end of the block
+    (*allocated_block).cookie = entropy::random_usize();
+    write_end_cookie(allocated_block);
 
     // Return a pointer to the start of the user memory (after the header)
-    user_memory
+    (allocated_block as *mut u8).add(BLOCK_HEADER_SIZE)
+}
+
+/// Value of the end cookie of a block : the cookie of its header mixed with the block address and
+/// `HEAP_SECRET`, so that a header (or a whole block) copied from another block does not match
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn end_cookie(block: *const BlockLink) -> usize {
+    (*block).cookie ^ (block as usize).wrapping_mul(ADDRESS_MIX) ^ HEAP_SECRET
+}
+
+/// Stores the end cookie in the last word of the block
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn write_end_cookie(block: *mut BlockLink) {
+    *((block as *mut u8).add((*block).block_size - size_of::<usize>()) as *mut usize) = end_cookie(block);
 }
 
 /// Checks the integrity of a memory block by verifying its cookies.
 /// 
-/// Each allocated block has a unique `COOKIE` stored at the start (within the `BlockLink` structure)
-/// and at the end of the user memory. This function ensures that both cookies match, indicating
-/// that the block has not been corrupted.
+/// Each allocated block has a random `COOKIE` stored at the start (within the `BlockLink` structure),
+/// and mixed with the block address in the last word of the block, after the user memory. This function
+/// ensures that both cookies match, indicating that the block has not been corrupted.
 pub fn check_cookie(ptr: *mut u8) -> bool {
     unsafe {
         let block_link = (ptr as usize - BLOCK_HEADER_SIZE) as *mut BlockLink;
         let block_size = (*block_link).block_size; // Retrieve block size from BlockLink
-        let start_cookie = (*block_link).cookie;
-        let end_cookie = *(ptr.add(block_size - size_of::<usize>()) as *mut usize);
-        start_cookie == end_cookie
+        let stored_end_cookie = *((block_link as *mut u8).add(block_size - size_of::<usize>()) as *mut usize);
+        stored_end_cookie == end_cookie(block_link)
     }
 }
 
+/// Frees a block, and merges it with the adjacent free blocks so that the heap does not fragment
 #[allow(unsafe_op_in_unsafe_fn)]
 pub unsafe fn deallocate(ptr: *mut u8) {
     if ptr.is_null() {
         return;
     }
 
+    let _guard = HeapGuard::new();
+
     // Move back to find the header
     let block_to_free = (ptr as usize - BLOCK_HEADER_SIZE) as *mut BlockLink;
 
     // Check cookies using the new function
     check_cookie!(ptr);
 
-    // Add the block to the list of free blocks
-    let mut previous_block = &raw const START as *mut _;
+    FREE_COUNT += 1;
+    remove_usage((*block_to_free).owner, (*block_to_free).block_size);
+    insert_free_block(block_to_free);
+}
+
+/// Adds a block to the list of free blocks, ordered by address, merging it with the adjacent free blocks
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn insert_free_block(block_to_free: *mut BlockLink) {
+    let end_block = &raw const END as *mut BlockLink;
+    let mut previous_block = &raw const START as *mut BlockLink;
     let mut current_block = START.next_free;
 
-    while !current_block.is_null() && current_block < block_to_free {
+    while !current_block.is_null() && current_block != end_block && current_block < block_to_free {
         previous_block = current_block;
         current_block = (*current_block).next_free;
     }
 
-    (*block_to_free).next_free = current_block;
-    (*previous_block).next_free = block_to_free;
-
     FREE_BYTES_REMAINING += (*block_to_free).block_size;
+
+    // Merge with the next block if they are contiguous
+    if current_block != end_block && block_to_free as usize + (*block_to_free).block_size == current_block as usize {
+        (*block_to_free).block_size += (*current_block).block_size;
+        (*block_to_free).next_free = (*current_block).next_free;
+    } else {
+        (*block_to_free).next_free = current_block;
+    }
+
+    // Merge with the previous block if they are contiguous
+    if !ptr::eq(previous_block, &raw const START) && previous_block as usize + (*previous_block).block_size == block_to_free as usize {
+        (*previous_block).block_size += (*block_to_free).block_size;
+        (*previous_block).next_free = (*block_to_free).next_free;
+    } else {
+        (*previous_block).next_free = block_to_free;
+    }
 }
 
-pub unsafe fn zeroes_region(block: *mut u8) {
-    unsafe {
-        let user_memory_size = (*(block as *mut BlockLink)).block_size - BLOCK_HEADER_SIZE;
-        ptr::write_bytes(block, 0, user_memory_size);
+/// Resizes an allocated block without moving it, so that it holds `wanted_size` bytes of user memory
+///
+/// A block shrinks by giving its end back to the free list. It grows by taking the next block, if this
+/// block is free and large enough. Returns `false` if the block can't be resized in place.
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn resize_in_place(ptr: *mut u8, wanted_size: usize) -> bool {
+    if ptr.is_null() {
+        return false;
+    }
+    let _guard = HeapGuard::new();
+    check_cookie!(ptr);
+
+    let block = (ptr as usize - BLOCK_HEADER_SIZE) as *mut BlockLink;
+    let wanted_size = block_size_for(wanted_size);
+    let old_size = (*block).block_size;
+
+    if wanted_size > (*block).block_size {
+        // Take the next block, if it is free
+        let next_block = (block as usize + (*block).block_size) as *mut BlockLink;
+        let mut previous_block = &raw const START as *mut BlockLink;
+        let mut current_block = START.next_free;
+        while !current_block.is_null() && current_block != next_block {
+            previous_block = current_block;
+            current_block = (*current_block).next_free;
+        }
+        if current_block.is_null() || (*block).block_size + (*next_block).block_size < wanted_size {
+            return false;
+        }
+
+        (*previous_block).next_free = (*next_block).next_free;
+        FREE_BYTES_REMAINING -= (*next_block).block_size;
+        MINIMUM_EVER_FREE_BYTES = MINIMUM_EVER_FREE_BYTES.min(FREE_BYTES_REMAINING);
+        (*block).block_size += (*next_block).block_size;
     }
+
+    // Give the end of the block back, if it is large enough to be a block
+    if (*block).block_size - wanted_size >= MINIMUM_BLOCK_SIZE {
+        let remaining_block = (block as usize + wanted_size) as *mut BlockLink;
+        (*remaining_block).block_size = (*block).block_size - wanted_size;
+        (*block).block_size = wanted_size;
+        insert_free_block(remaining_block);
+    }
+
+    // The end cookie moves with the end of the block
+    write_end_cookie(block);
+    remove_usage((*block).owner, old_size);
+    add_usage((*block).owner, (*block).block_size);
+    true
 }
 
 #[allow(dead_code)]
@@ -172,13 +548,309 @@ pub fn get_free_heap_size() -> usize {
     }
 }
 
+/// Get heap usage and fragmentation statistics, walking the free list
+#[allow(dead_code)]
+pub fn heap_stats() -> HeapStats {
+    let _guard = HeapGuard::new();
+    unsafe {
+        let end_block = &raw const END as *mut BlockLink;
+        let mut largest_free_block = 0;
+        let mut free_fragments = 0;
+
+        let mut current_block = START.next_free;
+        while !current_block.is_null() && current_block != end_block {
+            largest_free_block = largest_free_block.max((*current_block).block_size);
+            free_fragments += 1;
+            current_block = (*current_block).next_free;
+        }
+
+        HeapStats {
+            free_bytes: FREE_BYTES_REMAINING,
+            largest_free_block,
+            free_fragments,
+            minimum_ever_free_bytes: MINIMUM_EVER_FREE_BYTES,
+            allocation_count: ALLOCATION_COUNT,
+            free_count: FREE_COUNT
+        }
+    }
+}
+
+/// Check if `address` is in a usable heap region
+fn is_in_heap(address: usize) -> bool {
+    HeapRegion::ALL.iter().any(|region| region.is_usable() && region.contains(address))
+}
+
+/// Walks every block of the heap, allocated or free, region by region, and checks its integrity
+///
+/// Checks the size of each block, the cookies of the allocated blocks, that the free list is ordered by
+/// address and only holds non-contiguous blocks of the heap, and that the free blocks sum up to
+/// `FREE_BYTES_REMAINING`. Returns the first corrupted block found.
+///
+/// The free list is walked along with the blocks : a block is free if it is the next block of the free list.
+pub fn verify() -> Result<HeapReport, HeapError> {
+    let _guard = HeapGuard::new();
+    unsafe {
+        let end_block = &raw const END as *mut BlockLink;
+        let error = |corruption, block: usize, owner| HeapError { corruption, block, user_memory: block + BLOCK_HEADER_SIZE, owner };
+
+        let mut report = HeapReport::default();
+        let mut next_free = START.next_free;
+
+        for region in HeapRegion::ALL.iter().filter(|region| region.is_usable()) {
+            let (heap_start, heap_end) = region.bounds();
+            let mut previous_is_free = false;
+            let mut block = heap_start;
+
+            while block < heap_end {
+                let header = block as *mut BlockLink;
+                let block_size = (*header).block_size;
+                let is_free = ptr::eq(header, next_free);
+                let owner = if is_free { None } else { Some((*header).owner) };
+                if block_size < BLOCK_HEADER_SIZE + size_of::<usize>() || block_size & !ALIGNMENT_MASK != 0 || block_size > heap_end - block {
+                    return Err(error(HeapCorruption::InvalidSize, block, owner));
+                }
+
+                if is_free {
+                    if previous_is_free {
+                        return Err(error(HeapCorruption::NotCoalesced, block, None));
+                    }
+                    // The next free block must be after this block, in the heap
+                    next_free = (*header).next_free;
+                    let next_free_address = next_free as usize;
+                    if !ptr::eq(next_free, end_block) && (next_free_address < block + block_size || !is_in_heap(next_free_address)) {
+                        return Err(error(HeapCorruption::BadFreeList, block, None));
+                    }
+                    report.free_blocks += 1;
+                    report.free_bytes += block_size;
+                    previous_is_free = true;
+                } else {
+                    if !ptr::eq(next_free, end_block) && (next_free as usize) < block {
+                        // The free list points inside the previous block
+                        return Err(error(HeapCorruption::BadFreeList, next_free as usize, None));
+                    }
+                    if !check_cookie((block + BLOCK_HEADER_SIZE) as *mut u8) {
+                        return Err(error(HeapCorruption::BadCookie, block, owner));
+                    }
+                    report.allocated_blocks += 1;
+                    report.allocated_bytes += block_size;
+                    previous_is_free = false;
+                }
+
+                block += block_size;
+            }
+        }
+
+        if !ptr::eq(next_free, end_block) {
+            return Err(error(HeapCorruption::BadFreeList, next_free as usize, None));
+        }
+        if report.free_bytes != FREE_BYTES_REMAINING {
+            return Err(error(HeapCorruption::FreeBytesMismatch, HeapRegion::Sram.bounds().0, None));
+        }
+        Ok(report)
+    }
+}
+
+/// Calls `f` with each block of the heap, region by region, and whether it is free, until `f` returns
+/// `false`. Stops at the first block with an invalid size, see `verify` to find it.
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn walk_blocks(mut f: impl FnMut(*mut BlockLink, bool) -> bool) {
+    let mut next_free = START.next_free;
+
+    for region in HeapRegion::ALL.iter().filter(|region| region.is_usable()) {
+        let (heap_start, heap_end) = region.bounds();
+        let mut block = heap_start;
+
+        while block < heap_end {
+            let header = block as *mut BlockLink;
+            let block_size = (*header).block_size;
+            if block_size < BLOCK_HEADER_SIZE + size_of::<usize>() || block_size > heap_end - block {
+                return;
+            }
+
+            let is_free = ptr::eq(header, next_free);
+            if is_free {
+                next_free = (*header).next_free;
+            }
+            if !f(header, is_free) {
+                return;
+            }
+            block += block_size;
+        }
+    }
+}
+
+/// Size of the block holding `wanted_size` bytes : header and end cookie included, aligned. This is the
+/// size counted in the quota of the owner.
+pub const fn block_size_for(wanted_size: usize) -> usize {
+    (wanted_size + BLOCK_HEADER_SIZE + size_of::<usize>() + ALIGNMENT - 1) & ALIGNMENT_MASK
+}
+
+/// Get the size of an allocated block, header included
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn block_size(ptr: *mut u8) -> usize {
+    check_cookie!(ptr);
+    (*((ptr as usize - BLOCK_HEADER_SIZE) as *const BlockLink)).block_size
+}
+
+/// Checks that `owner` can allocate a block of `block_size` bytes (header included) within its quota, and
+/// that its usage can be counted
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn is_within_quota(owner: Owner, block_size: usize) -> bool {
+    if owner.pid == KERNEL_PID {
+        return true;
+    }
+    usage_slot(owner.pid).is_some_and(|slot| owner.quota == usize::MAX || slot.bytes + block_size <= owner.quota)
+}
+
+/// Get the usage slots of the processes, the heap being locked by the caller
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn owner_usage() -> &'static mut [OwnerUsage; MAX_OWNERS] {
+    let owner_usage = &raw mut OWNER_USAGE;
+    &mut *owner_usage
+}
+
+/// Get the usage slot of a process, or a free slot if it owns no block
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn usage_slot(pid: u16) -> Option<&'static mut OwnerUsage> {
+    let owner_usage = owner_usage();
+    let index = owner_usage.iter().position(|slot| slot.pid == pid)
+        .or_else(|| owner_usage.iter().position(|slot| slot.pid == KERNEL_PID))?;
+    Some(&mut owner_usage[index])
+}
+
+/// Counts a block in the usage of its owner, fails if all the slots are in use
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn add_usage(pid: u16, block_size: usize) -> bool {
+    if pid == KERNEL_PID {
+        return true;
+    }
+    match usage_slot(pid) {
+        Some(slot) => {
+            slot.pid = pid;
+            slot.bytes += block_size;
+            true
+        }
+        None => false
+    }
+}
+
+/// Removes a block from the usage of its owner, the slot is freed with the last block
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn remove_usage(pid: u16, block_size: usize) {
+    if pid == KERNEL_PID {
+        return;
+    }
+    if let Some(slot) = owner_usage().iter_mut().find(|slot| slot.pid == pid) {
+        slot.bytes = slot.bytes.saturating_sub(block_size);
+        if slot.bytes == 0 {
+            slot.pid = KERNEL_PID;
+        }
+    }
+}
+
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn usage_unlocked(pid: u16) -> usize {
+    if pid != KERNEL_PID {
+        return owner_usage().iter().find(|slot| slot.pid == pid).map_or(0, |slot| slot.bytes);
+    }
+    // The kernel is not counted : walk the heap
+    let mut used = 0;
+    walk_blocks(|block, is_free| {
+        if !is_free && (*block).owner == pid {
+            used += (*block).block_size;
+        }
+        true
+    });
+    used
+}
+
+/// Get the size of the blocks owned by a process, headers included
+pub fn usage(pid: u16) -> usize {
+    let _guard = HeapGuard::new();
+    unsafe { usage_unlocked(pid) }
+}
+
+/// Gives an allocated block to a process, the block is then released by `reclaim` when the process is killed
+///
+/// Returns `false` if the usage of the process can't be counted, all the slots being used by other processes.
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn set_owner(ptr: *mut u8, pid: u16) -> bool {
+    if ptr.is_null() {
+        return false;
+    }
+    let _guard = HeapGuard::new();
+    check_cookie!(ptr);
+    let block = (ptr as usize - BLOCK_HEADER_SIZE) as *mut BlockLink;
+    if !add_usage(pid, (*block).block_size) {
+        return false;
+    }
+    remove_usage((*block).owner, (*block).block_size);
+    (*block).owner = pid;
+    true
+}
+
+/// Frees all the blocks owned by a process, returns the number of bytes freed, headers included
+///
+/// The heap is walked once, by address : each owned block is chained after the last free block before it, or
+/// merged with it if they are contiguous.
+#[allow(unsafe_op_in_unsafe_fn)]
+pub unsafe fn reclaim(pid: u16) -> usize {
+    if pid == KERNEL_PID {
+        return 0;
+    }
+    let _guard = HeapGuard::new();
+    let mut reclaimed = 0;
+    let mut last_free = &raw const START as *mut BlockLink;
+    let mut next_free = START.next_free;
+
+    let is_contiguous = |previous: *mut BlockLink, block: *mut BlockLink| {
+        !ptr::eq(previous, &raw const START) && previous as usize + (*previous).block_size == block as usize
+    };
+
+    for region in HeapRegion::ALL.iter().filter(|region| region.is_usable()) {
+        let (heap_start, heap_end) = region.bounds();
+        let mut block = heap_start;
+
+        while block < heap_end {
+            let header = block as *mut BlockLink;
+            let block_size = (*header).block_size;
+            if block_size < BLOCK_HEADER_SIZE + size_of::<usize>() || block_size > heap_end - block {
+                break;
+            }
+
+            if ptr::eq(header, next_free) {
+                next_free = (*header).next_free;
+                if is_contiguous(last_free, header) {
+                    // Follows a block freed just before
+                    (*last_free).block_size += block_size;
+                    (*last_free).next_free = next_free;
+                } else {
+                    last_free = header;
+                }
+            } else if (*header).owner == pid {
+                check_cookie!((block + BLOCK_HEADER_SIZE) as *mut u8);
+                reclaimed += block_size;
+                FREE_COUNT += 1;
+                FREE_BYTES_REMAINING += block_size;
+                if is_contiguous(last_free, header) {
+                    (*last_free).block_size += block_size;
+                } else {
+                    (*header).next_free = next_free;
+                    (*last_free).next_free = header;
+                    last_free = header;
+                }
+            }
+            block += block_size;
+        }
+    }
+
+    remove_usage(pid, reclaimed);
+    reclaimed
+}
+
 #[allow(dead_code)]
 #[allow(unsafe_op_in_unsafe_fn)]
 pub unsafe fn reset_heap() {
     HEAP_INIT = false;
     initialize_heap();
 }
-
-pub fn generate_random() -> usize {
-    return 0xdeadbeef;
-}
\ No newline at end of file
@@ -1,3 +1,4 @@
 pub mod heap;
 pub mod allocator;
 pub mod mpu;
+pub mod pool;
@@ -1,5 +1,261 @@
+//! Gestion de la MPU (Memory Protection Unit) de l'ARMv7-M : construction et vérification des régions,
+//! configuration des régions d'un processus et contrôle des accès non privilégiés
+//!
+//! Par convention, la documentation et les messages d'erreur de ce fichier sont rédigés en français.
+
+use core::fmt;
+
+/// Nombre de régions de la MPU
+pub const REGION_COUNT: u8 = 8;
+
+/// Erreur de configuration d'une région MPU
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MpuError {
+    /// Le numéro de région dépasse `REGION_COUNT`
+    InvalidRegionNumber,
+    /// La taille est inférieure à `MIN_REGION_SIZE`
+    SizeTooSmall,
+    /// La taille dépasse l'espace d'adressage
+    SizeTooLarge,
+    /// La taille n'est pas une puissance de 2
+    SizeNotPowerOfTwo,
+    /// L'adresse de base n'est pas alignée sur la taille de la région
+    UnalignedBase,
+    /// Les sous-régions n'existent que pour les régions de 256 octets ou plus
+    SubregionsNotSupported,
+    /// Toutes les sous-régions sont désactivées
+    NoSubregionEnabled,
+    /// Région à la fois accessible en écriture et exécutable (W^X)
+    WritableAndExecutable,
+}
+
+impl fmt::Display for MpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MpuError::InvalidRegionNumber => write!(f, "Numéro de région invalide"),
+            MpuError::SizeTooSmall => write!(f, "Taille de région inférieure à {} octets", MIN_REGION_SIZE),
+            MpuError::SizeTooLarge => write!(f, "Taille de région supérieure à 4 Go"),
+            MpuError::SizeNotPowerOfTwo => write!(f, "Taille de région qui n'est pas une puissance de 2"),
+            MpuError::UnalignedBase => write!(f, "Adresse de base non alignée sur la taille de la région"),
+            MpuError::SubregionsNotSupported => write!(f, "Sous-régions désactivées dans une région de moins de 256 octets"),
+            MpuError::NoSubregionEnabled => write!(f, "Toutes les sous-régions sont désactivées"),
+            MpuError::WritableAndExecutable => write!(f, "Région accessible en écriture et exécutable"),
+        }
+    }
+}
+
+/// Permissions d'accès d'une région (champ AP de MPU_RASR)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessPermission {
+    NoAccess = 0b000,
+    PrivilegedRw = 0b001,
+    PrivilegedRwUnprivilegedRo = 0b010,
+    FullAccess = 0b011,
+    PrivilegedRo = 0b101,
+    ReadOnly = 0b110,
+}
+
+/// Exécution d'instructions depuis une région (bit XN de MPU_RASR)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Execute {
+    Allowed,
+    Never,
+}
+
+/// Type mémoire et politique de cache d'une région (champs TEX, C et B de MPU_RASR)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryType {
+    /// TEX = 000, C = 0, B = 0
+    StronglyOrdered,
+    /// TEX = 000, C = 0, B = 1 : périphériques partagés
+    Device,
+    /// TEX = 010, C = 0, B = 0 : périphériques non partagés
+    DeviceNonShareable,
+    /// TEX = 000, C = 1, B = 0
+    NormalWriteThrough,
+    /// TEX = 000, C = 1, B = 1
+    NormalWriteBack,
+    /// TEX = 001, C = 0, B = 0
+    NormalNonCacheable,
+    /// TEX = 001, C = 1, B = 1
+    NormalWriteBackAllocate,
+}
+
+impl MemoryType {
+    /// Valeurs des champs (TEX, C, B)
+    fn encoding(self) -> (u32, u32, u32) {
+        match self {
+            MemoryType::StronglyOrdered => (0b000, 0, 0),
+            MemoryType::Device => (0b000, 0, 1),
+            MemoryType::DeviceNonShareable => (0b010, 0, 0),
+            MemoryType::NormalWriteThrough => (0b000, 1, 0),
+            MemoryType::NormalWriteBack => (0b000, 1, 1),
+            MemoryType::NormalNonCacheable => (0b001, 0, 0),
+            MemoryType::NormalWriteBackAllocate => (0b001, 1, 1),
+        }
+    }
+}
+
+/// Partage d'une région entre plusieurs maîtres du bus (bit S de MPU_RASR)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shareability {
+    NonShareable,
+    Shareable,
+}
+
+/// Champs de MPU_RBAR et MPU_RASR
+const RBAR_VALID: u32 = 1 << 4;
+const RASR_XN_SHIFT: u32 = 28;
+const RASR_AP_SHIFT: u32 = 24;
+const RASR_TEX_SHIFT: u32 = 19;
+const RASR_S_SHIFT: u32 = 18;
+const RASR_C_SHIFT: u32 = 17;
+const RASR_B_SHIFT: u32 = 16;
+const RASR_SRD_SHIFT: u32 = 8;
+const RASR_SRD_MASK: u32 = 0xFF << RASR_SRD_SHIFT;
+const RASR_SIZE_SHIFT: u32 = 1;
+
+/// Taille minimale d'une région découpée en sous-régions, en log2 du nombre d'octets
+const MIN_SUBREGION_REGION_SIZE: u32 = 8;
+
+/// Constructeur d'une région MPU, qui vérifie les contraintes de l'ARMv7-M avant de produire la région
+///
+/// Par défaut, la région est accessible en lecture-écriture en mode privilégié uniquement, non exécutable,
+/// de type mémoire normale write-through partagée (la SRAM du STM32F405).
+///
+/// Une région exécutable doit être en lecture seule (W^X) : le code d'un processus ne peut pas être
+/// modifié, et ses données ne peuvent pas être exécutées.
+///
+/// ```
+/// let region = RegionBuilder::new(0, 0x2000_0000, 1024)
+///     .access(AccessPermission::ReadOnly)
+///     .execute(Execute::Allowed)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionBuilder {
+    number: u8,
+    base_address: u32,
+    size: usize,
+    access: AccessPermission,
+    execute: Execute,
+    memory_type: MemoryType,
+    shareability: Shareability,
+    subregion_disable: u8,
+}
+
+impl RegionBuilder {
+    /// Région `number` de `size` octets à partir de `base_address`
+    pub fn new(number: u8, base_address: u32, size: usize) -> Self {
+        RegionBuilder {
+            number,
+            base_address,
+            size,
+            access: AccessPermission::PrivilegedRw,
+            execute: Execute::Never,
+            memory_type: MemoryType::NormalWriteThrough,
+            shareability: Shareability::Shareable,
+            subregion_disable: 0,
+        }
+    }
+
+    /// Région alignée la plus petite couvrant `[start, start + len)`, voir `covering_region`
+    ///
+    /// Les sous-régions entièrement hors de la plage sont désactivées, afin d'approcher une taille qui
+    /// n'est pas une puissance de 2 à un huitième de la région près.
+    pub fn covering(number: u8, start: u32, len: usize) -> Self {
+        let (base, size) = covering_region(start as usize, len);
+        let mut builder = Self::new(number, base as u32, size);
+        if size.trailing_zeros() >= MIN_SUBREGION_REGION_SIZE {
+            let subregion_size = size / 8;
+            let end = start as usize + len;
+            for index in 0..8 {
+                let subregion_start = base + index * subregion_size;
+                if subregion_start + subregion_size <= start as usize || subregion_start >= end {
+                    builder.subregion_disable |= 1 << index;
+                }
+            }
+        }
+        builder
+    }
+
+    /// Permissions d'accès
+    pub fn access(mut self, access: AccessPermission) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Autorise ou interdit l'exécution d'instructions
+    pub fn execute(mut self, execute: Execute) -> Self {
+        self.execute = execute;
+        self
+    }
+
+    /// Type mémoire et politique de cache
+    pub fn memory_type(mut self, memory_type: MemoryType) -> Self {
+        self.memory_type = memory_type;
+        self
+    }
+
+    /// Partage entre plusieurs maîtres du bus
+    pub fn shareability(mut self, shareability: Shareability) -> Self {
+        self.shareability = shareability;
+        self
+    }
+
+    /// Désactive des sous-régions : le bit `i` désactive le `i`-ème huitième de la région
+    pub fn disable_subregions(mut self, mask: u8) -> Self {
+        self.subregion_disable = mask;
+        self
+    }
+
+    /// Vérifie la configuration et produit la région
+    pub fn build(self) -> Result<MpuRegion, MpuError> {
+        if self.number >= REGION_COUNT {
+            return Err(MpuError::InvalidRegionNumber);
+        }
+        if self.size < MIN_REGION_SIZE {
+            return Err(MpuError::SizeTooSmall);
+        }
+        if !self.size.is_power_of_two() {
+            return Err(MpuError::SizeNotPowerOfTwo);
+        }
+        if !(self.base_address as usize).is_multiple_of(self.size) {
+            return Err(MpuError::UnalignedBase);
+        }
+        let size = self.size.trailing_zeros();
+        if self.subregion_disable != 0 && size < MIN_SUBREGION_REGION_SIZE {
+            return Err(MpuError::SubregionsNotSupported);
+        }
+        if self.subregion_disable == 0xFF {
+            return Err(MpuError::NoSubregionEnabled);
+        }
+        let writable = matches!(self.access, AccessPermission::PrivilegedRw | AccessPermission::PrivilegedRwUnprivilegedRo | AccessPermission::FullAccess);
+        if writable && self.execute == Execute::Allowed {
+            return Err(MpuError::WritableAndExecutable);
+        }
+
+        let (tex, c, b) = self.memory_type.encoding();
+        let attributes = MPU_REGION_ENABLE
+            | ((self.execute == Execute::Never) as u32) << RASR_XN_SHIFT
+            | (self.access as u32) << RASR_AP_SHIFT
+            | tex << RASR_TEX_SHIFT
+            | ((self.shareability == Shareability::Shareable) as u32) << RASR_S_SHIFT
+            | c << RASR_C_SHIFT
+            | b << RASR_B_SHIFT
+            | (self.subregion_disable as u32) << RASR_SRD_SHIFT;
+
+        Ok(MpuRegion {
+            base_address: self.base_address,
+            size,
+            attributes,
+            number: self.number,
+        })
+    }
+}
+
 /// Représente une région MPU
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct MpuRegion {
     base_address: u32,
     size: u32,
@@ -7,6 +263,90 @@ pub struct MpuRegion {
     number: u8,
 }
 
+/// Type d'accès d'un processus non privilégié à une plage mémoire
+#[derive(Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Lecture d'instructions
+    Execute,
+}
+
+impl MpuRegion {
+    /// Adresse de début de la région
+    fn start(&self) -> u64 {
+        self.base_address as u64
+    }
+
+    /// Adresse de fin (exclue) de la région, la taille étant encodée comme dans `Mpu::enable`
+    fn end(&self) -> u64 {
+        self.start() + (1u64 << self.size)
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.attributes & MPU_REGION_ENABLE != 0
+    }
+
+    /// Vérifie si le bit XN de la région autorise l'exécution d'instructions
+    pub fn is_executable(&self) -> bool {
+        self.attributes & (1 << RASR_XN_SHIFT) == 0
+    }
+
+    /// Vérifie si la région est accessible en écriture, en mode privilégié ou non
+    pub fn is_writable(&self) -> bool {
+        matches!(self.attributes & mpu_perm::AP_MASK, mpu_perm::PRIVILEGED_RW | mpu_perm::PRIVILEGED_RW_UNPRIVILEGED_RO | mpu_perm::FULL_ACCESS)
+    }
+
+    /// Taille d'une sous-région, la région entière si elle n'est pas découpée
+    fn subregion_size(&self) -> u64 {
+        if self.size >= MIN_SUBREGION_REGION_SIZE {
+            1u64 << (self.size - 3)
+        } else {
+            1u64 << self.size
+        }
+    }
+
+    fn contains(&self, address: u64) -> bool {
+        if !self.is_enabled() || address < self.start() || address >= self.end() {
+            return false;
+        }
+        let subregion = (address - self.start()) / self.subregion_size();
+        (self.attributes & RASR_SRD_MASK) >> RASR_SRD_SHIFT & (1 << subregion) == 0
+    }
+
+    /// Première limite de sous-région (début, fin ou limite interne) strictement après `address`
+    fn next_boundary(&self, address: u64) -> Option<u64> {
+        if address < self.start() {
+            Some(self.start())
+        } else if address < self.end() {
+            let subregion_size = self.subregion_size();
+            Some(self.start() + ((address - self.start()) / subregion_size + 1) * subregion_size)
+        } else {
+            None
+        }
+    }
+
+    /// Valeur de MPU_RBAR : adresse de base, bit VALID et numéro de région
+    pub fn rbar(&self) -> u32 {
+        self.base_address | RBAR_VALID | self.number as u32
+    }
+
+    /// Valeur de MPU_RASR : attributs, sous-régions désactivées, taille et bit ENABLE
+    pub fn rasr(&self) -> u32 {
+        self.attributes | ((self.size - 1) << RASR_SIZE_SHIFT)
+    }
+
+    /// Vérifie si les permissions (AP) de la région autorisent un accès non privilégié
+    fn allows_unprivileged(&self, access: Access) -> bool {
+        let ap = self.attributes & mpu_perm::AP_MASK;
+        match access {
+            Access::Read => matches!(ap, mpu_perm::PRIVILEGED_RW_UNPRIVILEGED_RO | mpu_perm::FULL_ACCESS | mpu_perm::READ_ONLY | mpu_perm::READ_ONLY_ALT),
+            Access::Write => ap == mpu_perm::FULL_ACCESS,
+            Access::Execute => self.is_executable() && self.allows_unprivileged(Access::Read),
+        }
+    }
+}
+
 /// Gestionnaire de la MPU
 #[derive(PartialEq, Clone, Copy)]
 pub struct Mpu {
@@ -21,15 +361,29 @@ impl Mpu {
         }
     }
 
-    /// Configure une région MPU
+    /// Configure une région MPU à partir de valeurs brutes, `size` étant le log2 de sa taille
+    ///
+    /// Préférer `RegionBuilder` et `configure`, qui ne manipulent pas les champs de MPU_RASR.
     pub fn configure_region(&mut self, 
         number: u8, 
         base_address: u32, 
         size: u32, 
         attributes: u32
-    ) -> Result<(), &'static str> {
-        if number >= 8 {
-            return Err("Numéro de région invalide");
+    ) -> Result<(), MpuError> {
+        if number >= REGION_COUNT {
+            return Err(MpuError::InvalidRegionNumber);
+        }
+        if size < MIN_REGION_SIZE.trailing_zeros() {
+            return Err(MpuError::SizeTooSmall);
+        }
+        if size > 32 {
+            return Err(MpuError::SizeTooLarge);
+        }
+        if !(base_address as u64).is_multiple_of(1u64 << size) {
+            return Err(MpuError::UnalignedBase);
+        }
+        if attributes & RASR_SRD_MASK != 0 && size < MIN_SUBREGION_REGION_SIZE {
+            return Err(MpuError::SubregionsNotSupported);
         }
         let region = MpuRegion {
             base_address,
@@ -42,22 +396,100 @@ impl Mpu {
         Ok(())
     }
 
+    /// Vérifie et installe la région décrite par `builder`
+    pub fn configure(&mut self, builder: RegionBuilder) -> Result<(), MpuError> {
+        let region = builder.build()?;
+        self.regions[region.number as usize] = Some(region);
+        Ok(())
+    }
+
+    /// Supprime une région MPU, qui sera désactivée par `enable`
+    pub fn clear_region(&mut self, number: u8) {
+        if let Some(region) = self.regions.get_mut(number as usize) {
+            *region = None;
+        }
+    }
+
+    /// Premier numéro de région non configurée, à partir de la région `first`
+    pub fn find_free_region(&self, first: u8) -> Option<u8> {
+        (first..8).find(|number| self.regions[*number as usize].is_none())
+    }
+
+    /// Région configurée sous le numéro `number`
+    pub fn region(&self, number: u8) -> Option<MpuRegion> {
+        self.regions.get(number as usize).copied().flatten()
+    }
+
+    /// Adresse de base d'une région configurée
+    pub fn region_base(&self, number: u8) -> Option<u32> {
+        self.regions.get(number as usize).copied().flatten().map(|region| region.base_address)
+    }
+
+    /// Vérifie qu'un processus non privilégié peut accéder à la plage `[address, address + len)`
+    ///
+    /// Comme pour le matériel, la région de numéro le plus élevé l'emporte lorsque plusieurs régions
+    /// se chevauchent. Une adresse couverte par aucune région est refusée.
+    pub fn check_unprivileged_access(&self, address: u32, len: usize, access: Access) -> bool {
+        let end = address as u64 + len as u64;
+        if end > 1u64 << 32 {
+            return false;
+        }
+
+        let mut cursor = address as u64;
+        while cursor < end {
+            let region = match self.regions.iter().rev().flatten().find(|region| region.contains(cursor)) {
+                Some(region) => region,
+                None => return false,
+            };
+            if !region.allows_unprivileged(access) {
+                return false;
+            }
+
+            // La région reste déterminante jusqu'à la fin de sa sous-région ou jusqu'à une limite de
+            // sous-région d'une région prioritaire
+            let mut next = region.next_boundary(cursor).unwrap_or(end).min(end);
+            for higher in self.regions[region.number as usize + 1..].iter().flatten() {
+                if let Some(boundary) = higher.next_boundary(cursor).filter(|_| higher.is_enabled()) {
+                    next = next.min(boundary);
+                }
+            }
+            cursor = next;
+        }
+        true
+    }
+
     /// Active la MPU
+    ///
+    /// Les régions non définies sont désactivées, afin qu'un processus n'hérite pas des régions du
+    /// processus précédent. PRIVDEFENA conserve la carte mémoire par défaut pour le noyau (mode
+    /// privilégié) uniquement : un processus non privilégié n'accède qu'à ses régions.
     pub fn enable(&self) {
         unsafe {
-            // Configure et active chaque région définie
-            for region in self.regions.iter().flatten() {
+            write_mpu_ctrl(0);
+
+            for (number, region) in self.regions.iter().enumerate() {
                 // Sélectionne la région
-                write_mpu_rnr(region.number);
-                // Configure la base et les attributs
-                write_mpu_rbar(region.base_address | 1);
-                write_mpu_rasr(region.attributes | ((region.size - 1) << 1) | 1);
+                write_mpu_rnr(number as u8);
+                match region {
+                    Some(region) => {
+                        // Configure la base et les attributs
+                        write_mpu_rbar(region.rbar());
+                        write_mpu_rasr(region.rasr());
+                    }
+                    None => {
+                        write_mpu_rbar(0);
+                        write_mpu_rasr(0);
+                    }
+                }
             }
 
             // Active la MPU et autorise les interruptions pendant les fault handlers
             let mut ctrl = 1u32 | (1 << 2);
             ctrl |= 1 << 1; // Enable MPU during hard fault, NMI, and FAULTMASK handlers
             write_mpu_ctrl(ctrl);
+
+            cortex_m::asm::dsb();
+            cortex_m::asm::isb();
         }
     }
 
@@ -99,38 +531,62 @@ fn write_mpu_rasr(value: u32) {
     }  
 }
 
+/// Taille minimale d'une région MPU
+pub const MIN_REGION_SIZE: usize = 32;
+
+/// Taille de la plus petite région MPU pouvant contenir `len` octets (puissance de 2, 32 octets minimum)
+///
+/// Une région doit être alignée sur sa taille : la mémoire d'un processus est allouée avec
+/// `heap::allocate_aligned(region_size(len), region_size(len))`.
+pub fn region_size(len: usize) -> usize {
+    len.max(MIN_REGION_SIZE).next_power_of_two()
+}
+
+/// Plus petite région alignée couvrant `[start, start + len)`, renvoie sa base et sa taille
+pub fn covering_region(start: usize, len: usize) -> (usize, usize) {
+    let mut size = region_size(len);
+    loop {
+        let base = start & !(size - 1);
+        if base + size >= start + len {
+            return (base, size);
+        }
+        size <<= 1;
+    }
+}
+
 // Constantes pour les attributs de région
 #[allow(dead_code)]
 pub const MPU_REGION_ENABLE: u32 = 1;
 
 
+/// Taille d'une région, en log2 du nombre d'octets (valeur attendue par `Mpu::configure_region`)
 #[allow(non_camel_case_types, dead_code)]
 pub enum sizeRegion {
-    SIZE_32B = 4,
-    SIZE_64B = 5,
-    SIZE_128B = 6,
-    SIZE_256B = 7,
-    SIZE_512B = 8,
-    SIZE_1KB = 9,
-    SIZE_2KB = 10,
-    SIZE_4KB = 11,
-    SIZE_8KB = 12,
-    SIZE_16KB = 13,
-    SIZE_32KB = 14,
-    SIZE_64KB = 15,
-    SIZE_128KB = 16,
-    SIZE_256KB = 17,
-    SIZE_512KB = 18,
-    SIZE_1MB = 19,
-    SIZE_2MB = 20,
-    SIZE_4MB = 21,
-    SIZE_8MB = 22,
-    SIZE_16MB = 23,
-    SIZE_32MB = 24,
-    SIZE_64MB = 25,
-    SIZE_128MB = 26,
-    SIZE_256MB = 27,
-    SIZE_512MB = 28
+    SIZE_32B = 5,
+    SIZE_64B = 6,
+    SIZE_128B = 7,
+    SIZE_256B = 8,
+    SIZE_512B = 9,
+    SIZE_1KB = 10,
+    SIZE_2KB = 11,
+    SIZE_4KB = 12,
+    SIZE_8KB = 13,
+    SIZE_16KB = 14,
+    SIZE_32KB = 15,
+    SIZE_64KB = 16,
+    SIZE_128KB = 17,
+    SIZE_256KB = 18,
+    SIZE_512KB = 19,
+    SIZE_1MB = 20,
+    SIZE_2MB = 21,
+    SIZE_4MB = 22,
+    SIZE_8MB = 23,
+    SIZE_16MB = 24,
+    SIZE_32MB = 25,
+    SIZE_64MB = 26,
+    SIZE_128MB = 27,
+    SIZE_256MB = 28,
+    SIZE_512MB = 29
 }
 
 // Constantes pour les attributs de permission
@@ -141,6 +597,9 @@ pub mod mpu_perm {
     pub const PRIVILEGED_RW: u32 = 0x1 << 24;
     pub const PRIVILEGED_RW_UNPRIVILEGED_RO: u32 = 0x2 << 24;
     pub const FULL_ACCESS: u32 = 0x3 << 24;
+    pub const READ_ONLY: u32 = 0x6 << 24;
+    pub const READ_ONLY_ALT: u32 = 0x7 << 24;
+    pub const AP_MASK: u32 = 0x7 << 24;
 } 
 
 #[allow(non_camel_case_types, dead_code)] 
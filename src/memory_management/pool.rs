@@ -0,0 +1,257 @@
+//! Fixed-size block pools for kernel objects
+//!
+//! A `Pool` is an array of blocks of the same size carved from the heap, whose free blocks are chained
+//! through their first word : allocating and freeing a block are constant-time, whatever the state of the
+//! heap. This keeps the scheduler latency independent of the heap free list.
+//!
+//! ```
+//! Pool memory (one heap block)
+//! +--------+--------+--------+--------+
+//! | used   | free   | used   | free   |
+//! +--------+---|----+--------+---|----+
+//!              +-----------------+---> null
+//! free_list ---^
+//! ```
+//!
+//! The kernel pools (`KERNEL_POOLS`) hold the small kernel objects : list nodes, process control blocks,
+//! message buffers, timers. They are used through `PoolAllocator`, an `Allocator` for `Box::new_in` and
+//! `LinkedList::new_in`, which picks the smallest pool large enough for the object.
+
+use core::alloc::{AllocError, Allocator, Layout};
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+
+use alloc::alloc::Global;
+use cortex_m::interrupt;
+use spin::Mutex;
+
+use crate::log_debug;
+use super::heap::{self, HeapRegion};
+
+/// Alignment of the blocks of a pool
+pub const POOL_ALIGN: usize = align_of::<u64>();
+
+/// Error of a pool operation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PoolError {
+    /// All the blocks of the pool are allocated
+    Exhausted,
+    /// The memory of the pool could not be allocated from the heap
+    OutOfMemory,
+    /// The pool has not been initialized with `Pool::init`
+    NotInitialized,
+    /// The pointer is not a block of the pool
+    InvalidPointer
+}
+
+/// Usage statistics of a pool, see `Pool::stats`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolStats {
+    pub name: &'static str,
+    pub block_size: usize,
+    pub capacity: usize,
+    /// Number of allocated blocks
+    pub used: usize,
+    /// Highest value of `used` since the pool was initialized
+    pub peak_used: usize,
+    /// Number of allocations which failed because the pool was exhausted
+    pub exhausted_count: usize
+}
+
+/// Pool of `capacity` blocks of `block_size` bytes
+pub struct Pool {
+    name: &'static str,
+    block_size: usize,
+    capacity: usize,
+    memory: *mut u8,
+    free_list: *mut u8,
+    used: usize,
+    peak_used: usize,
+    exhausted_count: usize
+}
+
+unsafe impl Send for Pool {}
+
+impl Pool {
+    /// Creates an empty pool, its memory is allocated by `init`
+    ///
+    /// The block size is rounded up to hold the free list link, and to keep the blocks aligned on `POOL_ALIGN`.
+    pub const fn new(name: &'static str, block_size: usize, capacity: usize) -> Pool {
+        let block_size = if block_size < size_of::<usize>() { size_of::<usize>() } else { block_size };
+        Pool {
+            name,
+            block_size: (block_size + POOL_ALIGN - 1) & !(POOL_ALIGN - 1),
+            capacity,
+            memory: ptr::null_mut(),
+            free_list: ptr::null_mut(),
+            used: 0,
+            peak_used: 0,
+            exhausted_count: 0
+        }
+    }
+
+    /// Allocates the memory of the pool from the heap, and chains all its blocks in the free list
+    ///
+    /// The pool is taken from the CCM, which kernel objects do not need to share with the DMA, and from
+    /// main SRAM if the CCM is full.
+    pub fn init(&mut self) -> Result<(), PoolError> {
+        if !self.memory.is_null() {
+            return Ok(());
+        }
+        // Aligned by hand rather than with `heap::allocate_aligned`, which may leave a free fragment before the block
+        let size = self.block_size * self.capacity + POOL_ALIGN - 1;
+        let mut block = unsafe { heap::allocate_in(HeapRegion::Ccm, size) };
+        if block.is_null() {
+            block = unsafe { heap::allocate(size) };
+        }
+        if block.is_null() {
+            return Err(PoolError::OutOfMemory);
+        }
+        let memory = ((block as usize + POOL_ALIGN - 1) & !(POOL_ALIGN - 1)) as *mut u8;
+
+        self.memory = memory;
+        self.free_list = ptr::null_mut();
+        for index in (0..self.capacity).rev() {
+            unsafe {
+                let block = memory.add(index * self.block_size);
+                ptr::write(block as *mut *mut u8, self.free_list);
+                self.free_list = block;
+            }
+        }
+        self.used = 0;
+        self.peak_used = 0;
+        Ok(())
+    }
+
+    /// Takes a block from the free list
+    pub fn allocate(&mut self) -> Result<NonNull<u8>, PoolError> {
+        if self.memory.is_null() {
+            return Err(PoolError::NotInitialized);
+        }
+        let block = match NonNull::new(self.free_list) {
+            Some(block) => block,
+            None => {
+                self.exhausted_count += 1;
+                return Err(PoolError::Exhausted);
+            }
+        };
+
+        self.free_list = unsafe { ptr::read(block.as_ptr() as *const *mut u8) };
+        self.used += 1;
+        self.peak_used = self.peak_used.max(self.used);
+        Ok(block)
+    }
+
+    /// Gives a block back to the free list
+    pub fn deallocate(&mut self, block: NonNull<u8>) -> Result<(), PoolError> {
+        if !self.contains(block.as_ptr()) || !(block.as_ptr() as usize - self.memory as usize).is_multiple_of(self.block_size) {
+            return Err(PoolError::InvalidPointer);
+        }
+
+        unsafe {
+            ptr::write(block.as_ptr() as *mut *mut u8, self.free_list);
+        }
+        self.free_list = block.as_ptr();
+        self.used -= 1;
+        Ok(())
+    }
+
+    /// Check if `address` is in the memory of the pool
+    pub fn contains(&self, address: *const u8) -> bool {
+        let start = self.memory as usize;
+        !self.memory.is_null() && (start..start + self.block_size * self.capacity).contains(&(address as usize))
+    }
+
+    /// Size of the blocks of the pool
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Get the usage statistics of the pool
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            name: self.name,
+            block_size: self.block_size,
+            capacity: self.capacity,
+            used: self.used,
+            peak_used: self.peak_used,
+            exhausted_count: self.exhausted_count
+        }
+    }
+}
+
+/// Number of kernel pools
+pub const KERNEL_POOL_COUNT: usize = 4;
+
+/// Kernel pools, ordered by block size
+static KERNEL_POOLS: [Mutex<Pool>; KERNEL_POOL_COUNT] = [
+    Mutex::new(Pool::new("node", 16, 64)),          // Nodes of the scheduler lists
+    Mutex::new(Pool::new("small", 32, 32)),         // Timers, small buffers
+    Mutex::new(Pool::new("message", 64, 32)),       // Message buffers
+    Mutex::new(Pool::new("process", 320, 16)),      // Process control blocks (process list nodes)
+];
+
+/// Number of `PoolAllocator` allocations served by the heap, no pool being large enough or available
+static mut HEAP_FALLBACKS: usize = 0;
+
+/// Carves the kernel pools from the heap, called once at boot after the heap is initialized
+pub fn init() {
+    for pool in KERNEL_POOLS.iter() {
+        interrupt::free(|_cs| {
+            let mut pool = pool.lock();
+            if let Err(error) = pool.init() {
+                log_debug!("Pool {} not initialized : {:?}", pool.name, error);
+            }
+        });
+    }
+}
+
+/// Get the statistics of the kernel pools
+pub fn kernel_pool_stats() -> [PoolStats; KERNEL_POOL_COUNT] {
+    core::array::from_fn(|index| interrupt::free(|_cs| KERNEL_POOLS[index].lock().stats()))
+}
+
+/// Get the number of `PoolAllocator` allocations served by the heap
+#[allow(dead_code)]
+pub fn heap_fallbacks() -> usize {
+    interrupt::free(|_cs| unsafe { HEAP_FALLBACKS })
+}
+
+/// Allocator of kernel objects from the kernel pools
+///
+/// An object is allocated from the smallest pool large enough for it. If this pool is exhausted or not
+/// initialized, or if the object is larger than the largest pool, the object is allocated from the heap
+/// (counted by `heap_fallbacks`) : the pool statistics tell which pool should be larger.
+#[derive(Clone, Copy, Default)]
+pub struct PoolAllocator;
+
+unsafe impl Allocator for PoolAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.align() <= POOL_ALIGN {
+            let block = interrupt::free(|_cs| {
+                KERNEL_POOLS.iter()
+                    .map(|pool| pool.lock())
+                    .find(|pool| pool.block_size() >= layout.size())
+                    .map(|mut pool| pool.allocate().map(|block| (block, pool.block_size())))
+            });
+            if let Some(Ok((block, block_size))) = block {
+                return Ok(NonNull::slice_from_raw_parts(block, block_size));
+            }
+        }
+
+        interrupt::free(|_cs| unsafe { HEAP_FALLBACKS += 1 });
+        Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let released = interrupt::free(|_cs| {
+            KERNEL_POOLS.iter()
+                .map(|pool| pool.lock())
+                .find(|pool| pool.contains(ptr.as_ptr()))
+                .map(|mut pool| pool.deallocate(ptr))
+        });
+        if released.is_none() {
+            unsafe { Global.deallocate(ptr, layout) };
+        }
+    }
+}
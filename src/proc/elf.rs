@@ -0,0 +1,394 @@
+//! ELF32 ARM loader for user processes
+//!
+//! Loads the `PT_LOAD` segments of an executable (`ET_EXEC`) or position-independent (`ET_DYN`) image
+//! in a single heap block, keeping the distance between segments, so that PC-relative accesses between
+//! text and data stay valid. The block is aligned on its MPU region size, so that the whole image can be
+//! protected by one region.
+//!
+//! ```
+//! ELF file                         Heap block
+//! +-----------------+              +-----------------+ < base (vaddr = min p_vaddr)
+//! | ELF header      |              | .text (R X)     |
+//! +-----------------+              +-----------------+
+//! | Program headers |   PT_LOAD    | .rodata (R)     |
+//! +-----------------+  ----------> +-----------------+
+//! | .text .rodata   |              | .data (R W)     |
+//! +-----------------+              +-----------------+
+//! | .data           |              | .bss (R W) = 0  |
+//! +-----------------+              +-----------------+ < base + max(p_vaddr + p_memsz)
+//! ```
+//!
+//! Relocations listed in the `PT_DYNAMIC` segment (`DT_REL`) are then applied, with the load bias
+//! `base - min p_vaddr` : `R_ARM_RELATIVE` (B + A) and `R_ARM_ABS32` (S + A).
+
+use core::ptr;
+
+use crate::memory_management::{heap::{self, HeapRegion}, mpu};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const EM_ARM: u16 = 40;
+
+const ELF_HEADER_SIZE: usize = 52;
+const PROGRAM_HEADER_SIZE: usize = 32;
+const DYNAMIC_ENTRY_SIZE: usize = 8;
+const REL_ENTRY_SIZE: usize = 8;
+const SYM_ENTRY_SIZE: usize = 16;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+const DT_NULL: u32 = 0;
+const DT_SYMTAB: u32 = 6;
+const DT_RELA: u32 = 7;
+const DT_REL: u32 = 17;
+const DT_RELSZ: u32 = 18;
+const DT_RELENT: u32 = 19;
+
+const R_ARM_NONE: u8 = 0;
+const R_ARM_ABS32: u8 = 2;
+const R_ARM_RELATIVE: u8 = 23;
+
+/// Segment permission flags (`p_flags`)
+pub const PF_X: u32 = 0x1;
+pub const PF_W: u32 = 0x2;
+pub const PF_R: u32 = 0x4;
+
+/// Maximum number of `PT_LOAD` segments in an image, each one uses an MPU region
+pub const MAX_SEGMENTS: usize = 4;
+
+/// Errors returned when an ELF image can't be loaded
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ElfError {
+    /// The image is shorter than the headers it describes
+    Truncated,
+    /// The image does not start with `\x7fELF`
+    BadMagic,
+    /// The image is not a 32 bits little endian ELF
+    UnsupportedClass,
+    /// The image is not built for ARM
+    UnsupportedMachine(u16),
+    /// The image is neither `ET_EXEC` nor `ET_DYN`
+    UnsupportedType(u16),
+    /// A program header is malformed (size, `p_filesz` greater than `p_memsz`, ...)
+    BadProgramHeader,
+    /// A segment content lies outside of the file
+    SegmentOutOfBounds,
+    /// The image has no `PT_LOAD` segment
+    NoLoadableSegment,
+    /// The image has more than `MAX_SEGMENTS` `PT_LOAD` segments
+    TooManySegments,
+    /// The entry point is not in an executable segment
+    BadEntryPoint,
+    /// The dynamic section or the relocation table is malformed
+    BadDynamicSection,
+    /// The image uses `DT_RELA` or a relocation type other than `R_ARM_RELATIVE` and `R_ARM_ABS32`
+    UnsupportedRelocation(u8),
+    /// A relocation targets an address outside of the loaded image
+    RelocationOutOfBounds,
+    /// Not enough heap memory to load the image
+    OutOfMemory
+}
+
+/// A segment loaded in memory
+#[derive(Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: *mut u8,
+    pub size: usize,
+    /// Permissions of the segment (`PF_R`, `PF_W`, `PF_X`)
+    pub flags: u32
+}
+
+/// An image loaded in memory, ready to be run
+pub struct LoadedImage {
+    /// Heap block holding all the segments
+    pub base: *mut u8,
+    pub size: usize,
+    pub entry_point: *mut u8,
+    pub segments: [Option<Segment>; MAX_SEGMENTS]
+}
+
+impl LoadedImage {
+    /// Describe a raw position-independent code blob, already copied in memory, as a single executable segment
+    pub fn from_raw(code: *mut u8, code_len: usize) -> LoadedImage {
+        let mut segments = [None; MAX_SEGMENTS];
+        segments[0] = Some(Segment { start: code, size: code_len, flags: PF_R | PF_X });
+
+        LoadedImage {
+            base: code,
+            size: code_len,
+            entry_point: code,
+            segments
+        }
+    }
+}
+
+/// Program header fields used by the loader
+#[derive(Clone, Copy)]
+struct ProgramHeader {
+    p_type: u32,
+    p_offset: u32,
+    p_vaddr: u32,
+    p_filesz: u32,
+    p_memsz: u32,
+    p_flags: u32
+}
+
+/// Loads an ELF32 ARM image in heap memory
+///
+/// # Arguments
+/// * `elf` - The ELF image
+///
+/// # Returns
+/// * The loaded image, whose heap block has to be freed with `heap::deallocate(image.base)`
+/// * An `ElfError` if the image is malformed or unsupported, nothing stays allocated in that case
+pub fn load(elf: &[u8]) -> Result<LoadedImage, ElfError> {
+    if elf.len() < ELF_HEADER_SIZE {
+        return Err(ElfError::Truncated);
+    }
+    if elf[0..4] != ELF_MAGIC {
+        return Err(ElfError::BadMagic);
+    }
+    if elf[4] != ELFCLASS32 || elf[5] != ELFDATA2LSB {
+        return Err(ElfError::UnsupportedClass);
+    }
+
+    let e_type = read_u16(elf, 16)?;
+    let e_machine = read_u16(elf, 18)?;
+    let e_entry = read_u32(elf, 24)?;
+    let e_phoff = read_u32(elf, 28)? as usize;
+    let e_phentsize = read_u16(elf, 42)? as usize;
+    let e_phnum = read_u16(elf, 44)? as usize;
+
+    if e_machine != EM_ARM {
+        return Err(ElfError::UnsupportedMachine(e_machine));
+    }
+    if e_type != ET_EXEC && e_type != ET_DYN {
+        return Err(ElfError::UnsupportedType(e_type));
+    }
+    if e_phentsize != PROGRAM_HEADER_SIZE {
+        return Err(ElfError::BadProgramHeader);
+    }
+
+    // Collect the PT_LOAD segments, and compute the memory span of the image
+    let mut load_headers: [Option<ProgramHeader>; MAX_SEGMENTS] = [None; MAX_SEGMENTS];
+    let mut load_count = 0;
+    let mut dynamic_header = None;
+    let mut min_vaddr = u32::MAX;
+    let mut max_vaddr = 0;
+
+    for i in 0..e_phnum {
+        let offset = i.checked_mul(PROGRAM_HEADER_SIZE)
+            .and_then(|offset| offset.checked_add(e_phoff))
+            .ok_or(ElfError::Truncated)?;
+        let header = read_program_header(elf, offset)?;
+
+        match header.p_type {
+            PT_LOAD => {
+                if header.p_memsz == 0 {
+                    continue;
+                }
+                if header.p_filesz > header.p_memsz {
+                    return Err(ElfError::BadProgramHeader);
+                }
+                let file_end = header.p_offset.checked_add(header.p_filesz).ok_or(ElfError::SegmentOutOfBounds)?;
+                if file_end as usize > elf.len() {
+                    return Err(ElfError::SegmentOutOfBounds);
+                }
+                let vaddr_end = header.p_vaddr.checked_add(header.p_memsz).ok_or(ElfError::BadProgramHeader)?;
+                if load_count == MAX_SEGMENTS {
+                    return Err(ElfError::TooManySegments);
+                }
+
+                min_vaddr = min_vaddr.min(header.p_vaddr);
+                max_vaddr = max_vaddr.max(vaddr_end);
+                load_headers[load_count] = Some(header);
+                load_count += 1;
+            }
+            PT_DYNAMIC => dynamic_header = Some(header),
+            _ => {}
+        }
+    }
+
+    if load_count == 0 {
+        return Err(ElfError::NoLoadableSegment);
+    }
+
+    // The image is loaded in main SRAM : a larger span could not be allocated, and would overflow the region size
+    let size = (max_vaddr - min_vaddr) as usize;
+    let (heap_start, heap_end) = HeapRegion::Sram.bounds();
+    if size > heap_end - heap_start {
+        return Err(ElfError::OutOfMemory);
+    }
+    let base: *mut u8;
+    let region_size = mpu::region_size(size);
+    unsafe {
+        base = heap::allocate_aligned(region_size, region_size);
+    }
+    if base.is_null() {
+        return Err(ElfError::OutOfMemory);
+    }
+
+    // Copy the segments, and zero-fill the part not present in the file (.bss)
+    let mut image = LoadedImage {
+        base,
+        size,
+        entry_point: ptr::null_mut(),
+        segments: [None; MAX_SEGMENTS]
+    };
+
+    for (i, header) in load_headers.iter().flatten().enumerate() {
+        unsafe {
+            let start = base.add((header.p_vaddr - min_vaddr) as usize);
+            ptr::copy_nonoverlapping(elf.as_ptr().add(header.p_offset as usize), start, header.p_filesz as usize);
+            ptr::write_bytes(start.add(header.p_filesz as usize), 0, (header.p_memsz - header.p_filesz) as usize);
+
+            image.segments[i] = Some(Segment { start, size: header.p_memsz as usize, flags: header.p_flags });
+        }
+    }
+
+    let result = finish_load(elf, &mut image, e_entry, min_vaddr, dynamic_header);
+    if result.is_err() {
+        unsafe {
+            heap::deallocate(base);
+        }
+    }
+    result.map(|_| image)
+}
+
+/// Resolve the entry point and apply the relocations of a loaded image
+fn finish_load(elf: &[u8], image: &mut LoadedImage, e_entry: u32, min_vaddr: u32, dynamic_header: Option<ProgramHeader>) -> Result<(), ElfError> {
+    let entry_offset = e_entry.wrapping_sub(min_vaddr) as usize & !1;
+    let entry_point = image.base.wrapping_add(entry_offset);
+
+    let in_executable_segment = image.segments.iter().flatten().any(|segment| {
+        segment.flags & PF_X != 0 && entry_point >= segment.start && entry_point < segment.start.wrapping_add(segment.size)
+    });
+    if !in_executable_segment {
+        return Err(ElfError::BadEntryPoint);
+    }
+    // Thumb bit is cleared : the stacked PC must be halfword aligned
+    image.entry_point = entry_point;
+
+    if let Some(dynamic_header) = dynamic_header {
+        apply_relocations(elf, image, min_vaddr, &dynamic_header)?;
+    }
+    Ok(())
+}
+
+/// Apply the `DT_REL` relocations of the dynamic section to a loaded image
+fn apply_relocations(elf: &[u8], image: &LoadedImage, min_vaddr: u32, dynamic_header: &ProgramHeader) -> Result<(), ElfError> {
+    let bias = (image.base as u32).wrapping_sub(min_vaddr);
+
+    let mut rel_vaddr = None;
+    let mut rel_size = 0;
+    let mut rel_entry_size = REL_ENTRY_SIZE as u32;
+    let mut symtab_vaddr = None;
+
+    let dynamic_start = dynamic_header.p_offset as usize;
+    let dynamic_end = dynamic_start.checked_add(dynamic_header.p_filesz as usize).ok_or(ElfError::BadDynamicSection)?;
+    if dynamic_end > elf.len() {
+        return Err(ElfError::BadDynamicSection);
+    }
+
+    let mut offset = dynamic_start;
+    while offset + DYNAMIC_ENTRY_SIZE <= dynamic_end {
+        let d_tag = read_u32(elf, offset)?;
+        let d_val = read_u32(elf, offset + 4)?;
+
+        match d_tag {
+            DT_NULL => break,
+            DT_REL => rel_vaddr = Some(d_val),
+            DT_RELSZ => rel_size = d_val,
+            DT_RELENT => rel_entry_size = d_val,
+            DT_SYMTAB => symtab_vaddr = Some(d_val),
+            DT_RELA => return Err(ElfError::UnsupportedRelocation(0)),
+            _ => {}
+        }
+        offset += DYNAMIC_ENTRY_SIZE;
+    }
+
+    let rel_vaddr = match rel_vaddr {
+        Some(rel_vaddr) => rel_vaddr,
+        None => return Ok(())
+    };
+    if rel_entry_size as usize != REL_ENTRY_SIZE {
+        return Err(ElfError::BadDynamicSection);
+    }
+
+    for i in 0..(rel_size as usize / REL_ENTRY_SIZE) {
+        let rel = image_address(image, min_vaddr, rel_vaddr.wrapping_add((i * REL_ENTRY_SIZE) as u32), REL_ENTRY_SIZE)
+            .ok_or(ElfError::BadDynamicSection)?;
+
+        unsafe {
+            let r_offset = ptr::read_unaligned(rel as *const u32);
+            let r_info = ptr::read_unaligned(rel.add(4) as *const u32);
+            let r_type = (r_info & 0xff) as u8;
+            let r_sym = r_info >> 8;
+
+            if r_type == R_ARM_NONE {
+                continue;
+            }
+
+            let target = image_address(image, min_vaddr, r_offset, size_of::<u32>()).ok_or(ElfError::RelocationOutOfBounds)?;
+            let addend = ptr::read_unaligned(target as *const u32);
+
+            let value = match r_type {
+                R_ARM_RELATIVE => addend.wrapping_add(bias),
+                R_ARM_ABS32 => {
+                    let symtab_vaddr = symtab_vaddr.ok_or(ElfError::BadDynamicSection)?;
+                    let sym = image_address(image, min_vaddr, symtab_vaddr.wrapping_add(r_sym.wrapping_mul(SYM_ENTRY_SIZE as u32)), SYM_ENTRY_SIZE)
+                        .ok_or(ElfError::BadDynamicSection)?;
+                    let st_value = ptr::read_unaligned(sym.add(4) as *const u32);
+                    let st_shndx = ptr::read_unaligned(sym.add(14) as *const u16);
+
+                    // Undefined symbols can't be resolved, there is no shared library
+                    if st_shndx == 0 {
+                        return Err(ElfError::BadDynamicSection);
+                    }
+                    st_value.wrapping_add(bias).wrapping_add(addend)
+                }
+                _ => return Err(ElfError::UnsupportedRelocation(r_type))
+            };
+            ptr::write_unaligned(target as *mut u32, value);
+        }
+    }
+    Ok(())
+}
+
+/// Translate a virtual address of the image to its address in the loaded image, checking that `len` bytes fit in it
+fn image_address(image: &LoadedImage, min_vaddr: u32, vaddr: u32, len: usize) -> Option<*mut u8> {
+    let offset = vaddr.checked_sub(min_vaddr)? as usize;
+    if offset.checked_add(len)? > image.size {
+        return None;
+    }
+    Some(image.base.wrapping_add(offset))
+}
+
+fn read_program_header(elf: &[u8], offset: usize) -> Result<ProgramHeader, ElfError> {
+    let end = offset.checked_add(PROGRAM_HEADER_SIZE).ok_or(ElfError::Truncated)?;
+    let header = elf.get(offset..end).ok_or(ElfError::Truncated)?;
+    Ok(ProgramHeader {
+        p_type: read_u32(header, 0)?,
+        p_offset: read_u32(header, 4)?,
+        p_vaddr: read_u32(header, 8)?,
+        p_filesz: read_u32(header, 16)?,
+        p_memsz: read_u32(header, 20)?,
+        p_flags: read_u32(header, 24)?
+    })
+}
+
+fn read_u16(elf: &[u8], offset: usize) -> Result<u16, ElfError> {
+    let end = offset.checked_add(2).ok_or(ElfError::Truncated)?;
+    let bytes = elf.get(offset..end).ok_or(ElfError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(elf: &[u8], offset: usize) -> Result<u32, ElfError> {
+    let end = offset.checked_add(4).ok_or(ElfError::Truncated)?;
+    let bytes = elf.get(offset..end).ok_or(ElfError::Truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
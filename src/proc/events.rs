@@ -0,0 +1,87 @@
+//! Notification bits of the processes
+//!
+//! Each process has a 32-bit word of pending events, set by `notify` from the kernel, an interrupt handler
+//! or another process, and consumed by the process with `wait_events`. This is the lightest way to signal a
+//! process : no object to create, and nothing to copy.
+//!
+//! ```
+//! notify(pid, 0b0100) ----+
+//!                         v
+//! events : 0b0101    wait_events(0b0110, Any) -> 0b0100, events : 0b0001
+//! ```
+//!
+//! A process waits until any (`EventWait::Any`) or all (`EventWait::All`) of the bits of its mask are set.
+//! The bits of the mask which were set are returned and cleared, the other pending bits are kept.
+
+use super::{Process, ProcStatus, SystemProcess, WaitObject};
+use super::wait_queue::{IpcError, Timeout};
+
+/// Condition on the bits of the mask a process waits for
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum EventWait {
+    /// At least one bit of the mask is set
+    Any,
+    /// All the bits of the mask are set
+    All
+}
+
+impl Process {
+    /// Clears and returns the pending events of the mask, if they satisfy the condition
+    fn take_events(&mut self, mask: u32, wait: EventWait) -> Option<u32> {
+        let events = self.events & mask;
+        let satisfied = match wait {
+            EventWait::Any => events != 0,
+            EventWait::All => events == mask
+        };
+        if !satisfied {
+            return None;
+        }
+        self.events &= !events;
+        Some(events)
+    }
+}
+
+impl SystemProcess {
+    /// Waits for events of `mask` to be notified to the running process, returns the events received
+    ///
+    /// Returns `Ok(None)` if the process waits : the events are delivered to the process when it resumes.
+    pub fn wait_events(&mut self, mask: u32, wait: EventWait, timeout: Timeout) -> Result<Option<u32>, IpcError> {
+        let process = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        if mask == 0 {
+            return Err(IpcError::InvalidArgument);
+        }
+
+        if let Some(events) = unsafe { (*process).take_events(mask, wait) } {
+            return Ok(Some(events));
+        }
+        if timeout == Timeout::NoWait {
+            return Err(IpcError::WouldBlock);
+        }
+
+        self.block_current_process(WaitObject::Events { mask, wait }, timeout);
+        Ok(None)
+    }
+
+    /// Sets events of a process, and wakes it up if it waits for them
+    ///
+    /// Nothing is copied, and waking the process up only takes a run queue node from the kernel pools, in constant
+    /// time : it can be called from an interrupt handler, within a critical section.
+    pub fn notify(&mut self, proc_id: u16, events: u32) -> Result<(), IpcError> {
+        let process = self.process_list.iter_mut()
+            .find(|process| process.proc_id == proc_id && process.status != ProcStatus::Zombie)
+            .map(|process| process as *mut Process)
+            .ok_or(IpcError::NoSuchProcess)?;
+
+        let received = unsafe {
+            (*process).events |= events;
+            match (*process).wait_object {
+                Some(WaitObject::Events { mask, wait }) => (*process).take_events(mask, wait),
+                _ => None
+            }
+        };
+        if let Some(received) = received {
+            self.wake_process(process, Ok(received));
+        }
+        Ok(())
+    }
+}
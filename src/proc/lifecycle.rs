@@ -0,0 +1,214 @@
+//! Process lifecycle : programs, spawn, wait and kill
+//!
+//! The kernel registers the program images processes may start (`register_program`). A process started by
+//! another one with `spawn` is its child : when it terminates, its resources are released but its process
+//! control block is kept in the `ProcStatus::Zombie` state, until the parent collects its exit status with
+//! `wait_child`.
+//!
+//! ```
+//!  parent                  child
+//!    | spawn ---------------> Idle / Running / Waiting
+//!    |                          | exit, fault, kill
+//!    | wait_child  <--------- Zombie (exit status)
+//!    v                          x reaped
+//! ```
+//!
+//! The children of a terminated process are adopted by the kernel : its zombie children are reaped, the
+//! others are reaped as soon as they terminate.
+
+use crate::log_debug;
+use crate::memory_management::heap::KERNEL_PID;
+use crate::memory_management::mpu::Access;
+use crate::syscall::user;
+use super::elf::ElfError;
+use super::{ExitStatus, Process, ProcStatus, SystemProcess, WaitObject};
+use super::wait_queue::{IpcError, Timeout};
+
+/// Maximum number of programs registered in the kernel
+pub const MAX_PROGRAMS: usize = 8;
+/// Maximum length of the name given to `SYS_SPAWN`
+pub const PROGRAM_NAME_LEN: usize = 16;
+/// Size of the exit status written by `wait_child` : kind, then value (see `ExitStatus::encode`)
+const EXIT_STATUS_SIZE: usize = 8;
+
+/// A program image processes may start
+#[derive(Clone,Copy)]
+pub struct Program {
+    pub name: &'static str,
+    elf: &'static [u8],
+    priority: u8
+}
+
+impl ExitStatus {
+    /// Encodes the exit status for a process : its kind (0 exited, 1 faulted, 2 killed, 3 stack overflow),
+    /// then the exit code or the CFSR of the fault
+    pub fn encode(&self) -> [u32; 2] {
+        match self {
+            ExitStatus::Exited(code) => [0, *code],
+            ExitStatus::Faulted(fault) => [1, fault.cfsr],
+            ExitStatus::Killed => [2, 0],
+            ExitStatus::StackOverflow => [3, 0]
+        }
+    }
+
+    fn to_bytes(self) -> [u8; EXIT_STATUS_SIZE] {
+        let [kind, value] = self.encode();
+        let mut bytes = [0u8; EXIT_STATUS_SIZE];
+        bytes[..4].copy_from_slice(&kind.to_le_bytes());
+        bytes[4..].copy_from_slice(&value.to_le_bytes());
+        bytes
+    }
+}
+
+impl SystemProcess {
+    /// Registers a program image, started by `spawn` with the returned ID or with its name
+    pub fn register_program(&mut self, name: &'static str, elf: &'static [u8], priority: u8) -> Option<usize> {
+        let id = self.programs.iter().position(|program| program.is_none())?;
+        self.programs[id] = Some(Program { name, elf, priority });
+        Some(id)
+    }
+
+    /// Get the ID of the program registered with `name`
+    pub fn find_program(&self, name: &[u8]) -> Option<usize> {
+        self.programs.iter().position(|program| program.is_some_and(|program| program.name.as_bytes() == name))
+    }
+
+    /// Get the PID of the running process, 0 for the idle process
+    pub fn getpid(&self) -> Result<u16, IpcError> {
+        self.running_user_process().map(|process| unsafe { (*process).proc_id }).ok_or(IpcError::NoProcess)
+    }
+
+    /// Gives up the rest of the time slice of the running process, to the next ready process of its priority
+    pub fn yield_current_process(&mut self) {
+        if let Some(process) = self.running_user_process() {
+            unsafe { (*process).time_slice = 0 };
+            self.need_resched = true;
+        }
+    }
+
+    /// Starts a registered program as a child of the running process, returns its PID
+    pub fn spawn(&mut self, program_id: usize) -> Result<u16, IpcError> {
+        let parent = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let program = self.programs.get(program_id).copied().flatten().ok_or(IpcError::NotFound)?;
+
+        let pid = self.create_process_from_elf(program.name, program.elf, program.priority).map_err(|error| match error {
+            ElfError::OutOfMemory => IpcError::OutOfMemory,
+            _ => IpcError::InvalidImage
+        })?;
+        let parent_id = unsafe { (*parent).proc_id };
+        if let Some(child) = self.find_process(pid) {
+            unsafe { (*child).parent_id = parent_id };
+        }
+        log_debug!("> PID {} : spawned {} (PID {})", parent_id, program.name, pid);
+        Ok(pid)
+    }
+
+    /// Waits for the child `pid` of the running process to terminate, and writes its exit status at `status`
+    /// (unless it is 0), returns the PID of the child
+    ///
+    /// Returns `Ok(None)` if the process waits : the result is delivered to the process when it resumes.
+    pub fn wait_child(&mut self, pid: u16, status: u32, timeout: Timeout) -> Result<Option<u32>, IpcError> {
+        let parent = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let child = self.find_process(pid).ok_or(IpcError::NotChild)?;
+        unsafe {
+            if (*child).parent_id != (*parent).proc_id {
+                return Err(IpcError::NotChild);
+            }
+            // Checked now, as the status may be written while the process waits
+            if status != 0 && !(*parent).proc_mpu.check_unprivileged_access(status, EXIT_STATUS_SIZE, Access::Write) {
+                return Err(IpcError::Fault);
+            }
+        }
+
+        if unsafe { (*child).status } == ProcStatus::Zombie {
+            return self.collect_zombie(parent, child, status).map(Some);
+        }
+        if timeout == Timeout::NoWait {
+            return Err(IpcError::WouldBlock);
+        }
+        self.block_current_process(WaitObject::Child { pid, status }, timeout);
+        Ok(None)
+    }
+
+    /// Kills the process `pid` on behalf of the running process, which may only kill itself and its children
+    pub fn kill_from_process(&mut self, pid: u16) -> Result<u32, IpcError> {
+        let caller = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let target = self.find_process(pid).ok_or(IpcError::NoSuchProcess)?;
+        unsafe {
+            if target != caller && (*target).parent_id != (*caller).proc_id {
+                return Err(IpcError::NotPermitted);
+            }
+            match (*target).status {
+                ProcStatus::Zombie => {}
+                // Its memory is in use until the next context switch
+                _ if target == caller => self.exit_current_process(ExitStatus::Killed),
+                _ => {
+                    (*target).exit_status = Some(ExitStatus::Killed);
+                    self.kill_process(pid);
+                }
+            }
+        }
+        Ok(0)
+    }
+
+    /// Get a process of the Process List from its PID
+    pub(super) fn find_process(&mut self, pid: u16) -> Option<*mut Process> {
+        self.process_list.iter_mut()
+            .find(|process| process.proc_id == pid)
+            .map(|process| process as *mut Process)
+    }
+
+    /// Keeps a terminated process as a zombie if its parent is alive, returns `false` if it has to be reaped
+    ///
+    /// A parent waiting for the process collects its exit status, and the process is reaped.
+    pub(super) fn make_zombie(&mut self, process: *mut Process) -> bool {
+        let (pid, parent_id) = unsafe { ((*process).proc_id, (*process).parent_id) };
+        let parent = match self.find_process(parent_id) {
+            Some(parent) if parent_id != KERNEL_PID && unsafe { (*parent).status } != ProcStatus::Zombie => parent,
+            _ => return false
+        };
+
+        unsafe {
+            (*process).status = ProcStatus::Zombie;
+            (*process).stack = core::ptr::null_mut();
+            (*process).image = core::ptr::null_mut();
+            if let Some(WaitObject::Child { pid: waited, status }) = (*parent).wait_object && waited == pid {
+                let result = self.collect_zombie(parent, process, status);
+                self.wake_process(parent, result);
+            }
+        }
+        true
+    }
+
+    /// The children of a terminated process are adopted by the kernel, its zombie children are reaped
+    pub(super) fn orphan_children(&mut self, pid: u16) {
+        while let Some(zombie) = self.process_list.iter_mut()
+            .find(|process| process.parent_id == pid && process.status == ProcStatus::Zombie)
+            .map(|process| process as *mut Process) {
+            self.reap(zombie);
+        }
+        for child in self.process_list.iter_mut().filter(|process| process.parent_id == pid) {
+            child.parent_id = KERNEL_PID;
+        }
+    }
+
+    /// Writes the exit status of a zombie child to its parent, and reaps it
+    fn collect_zombie(&mut self, parent: *mut Process, child: *mut Process, status: u32) -> Result<u32, IpcError> {
+        let (pid, exit_status) = unsafe { ((*child).proc_id, (*child).exit_status.unwrap_or(ExitStatus::Killed)) };
+        let result = if status == 0 {
+            Ok(pid as u32)
+        } else {
+            user::copy_to(unsafe { &(*parent).proc_mpu }, status, &exit_status.to_bytes())
+                .map(|_| pid as u32)
+                .map_err(|_| IpcError::Fault)
+        };
+        self.reap(child);
+        result
+    }
+
+    /// Removes the process control block of a zombie from the Process List
+    fn reap(&mut self, process: *mut Process) {
+        log_debug!("> PID {} reaped", unsafe { (*process).proc_id });
+        self.process_list.delete(unsafe { core::ptr::read(process) });
+    }
+}
@@ -0,0 +1,237 @@
+//! Message queues between processes
+//!
+//! A message queue holds up to `capacity` messages of `message_size` bytes, in a ring buffer allocated
+//! from the heap and charged to the process which created the queue. Messages are copied from the memory
+//! of the sender to the ring buffer, then to the memory of the receiver : each copy is checked against
+//! the MPU regions of the process, as for any syscall buffer.
+//!
+//! ```
+//!             head         head + len
+//!              v            v
+//! +-----+-----+-----+-----+-----+
+//! |     |     | M1  | M2  |     |  <- mq_send copies at the tail
+//! +-----+-----+-----+-----+-----+
+//!               mq_receive copies from the head ->
+//! ```
+//!
+//! A sender finding the queue full, or a receiver finding it empty, either fails with `IpcError::WouldBlock`
+//! (`Timeout::NoWait`) or waits in the `senders` or `receivers` wait queue of the queue. A message is handed
+//! over directly to a waiting receiver, and a waiting sender fills the slot freed by a receiver.
+//!
+//! The queues of a process are destroyed when it is killed : the processes waiting on them are woken up
+//! with `IpcError::Removed`.
+
+use core::ptr;
+
+use crate::log_debug;
+use crate::memory_management::heap::{self, Owner};
+use crate::memory_management::mpu::Access;
+use crate::syscall::user;
+use super::{Process, SystemProcess, WaitObject};
+use super::wait_queue::{IpcError, Timeout, WaitQueue};
+
+/// Maximum number of message queues in the system
+pub const MAX_MESSAGE_QUEUES: usize = 8;
+/// Maximum size of a message, copied through a kernel buffer
+pub const MAX_MESSAGE_SIZE: usize = 64;
+/// Maximum number of messages held by a queue
+pub const MAX_QUEUE_CAPACITY: usize = 32;
+
+/// A bounded queue of fixed-size messages
+pub struct MessageQueue {
+    pub id: u32,
+    /// PID of the process which created the queue, and holds its memory
+    pub owner: u16,
+    message_size: usize,
+    capacity: usize,
+    buffer: *mut u8,
+    head: usize,
+    len: usize,
+    pub senders: WaitQueue,
+    pub receivers: WaitQueue
+}
+
+impl MessageQueue {
+    /// Creates an empty queue, whose ring buffer is owned by `owner`
+    fn new(id: u32, owner: Owner, message_size: usize, capacity: usize) -> Result<MessageQueue, IpcError> {
+        let buffer = unsafe { heap::allocate_for(owner, message_size * capacity) };
+        if buffer.is_null() {
+            return Err(IpcError::OutOfMemory);
+        }
+
+        Ok(MessageQueue {
+            id,
+            owner: owner.pid,
+            message_size,
+            capacity,
+            buffer,
+            head: 0,
+            len: 0,
+            senders: WaitQueue::new(),
+            receivers: WaitQueue::new()
+        })
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == self.capacity
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies a message at the tail of the queue, which must not be full
+    fn push(&mut self, message: &[u8]) {
+        let slot = (self.head + self.len) % self.capacity;
+        unsafe {
+            ptr::copy_nonoverlapping(message.as_ptr(), self.buffer.add(slot * self.message_size), self.message_size);
+        }
+        self.len += 1;
+    }
+
+    /// Copies the message at the head of the queue, which must not be empty, and removes it
+    fn pop(&mut self, message: &mut [u8]) {
+        unsafe {
+            ptr::copy_nonoverlapping(self.buffer.add(self.head * self.message_size), message.as_mut_ptr(), self.message_size);
+        }
+        self.head = (self.head + 1) % self.capacity;
+        self.len -= 1;
+    }
+}
+
+impl Drop for MessageQueue {
+    fn drop(&mut self) {
+        unsafe {
+            heap::deallocate(self.buffer);
+        }
+    }
+}
+
+impl SystemProcess {
+    /// Creates a message queue owned by the running process, returns its handle
+    ///
+    /// The ring buffer of `capacity` messages of `message_size` bytes is charged to the memory quota of the process.
+    pub fn mq_create(&mut self, message_size: usize, capacity: usize) -> Result<u32, IpcError> {
+        let process = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        if !(1..=MAX_MESSAGE_SIZE).contains(&message_size) || !(1..=MAX_QUEUE_CAPACITY).contains(&capacity) {
+            return Err(IpcError::InvalidArgument);
+        }
+        let slot = self.message_queues.iter().position(|queue| queue.is_none()).ok_or(IpcError::NoFreeSlot)?;
+
+        let owner = unsafe { Owner { pid: (*process).proc_id, quota: (*process).memory_quota } };
+        let id = self.new_object_id();
+        self.message_queues[slot] = Some(MessageQueue::new(id, owner, message_size, capacity)?);
+        log_debug!("> PID {} : message queue {} created ({} x {} bytes)", owner.pid, id, capacity, message_size);
+        Ok(id)
+    }
+
+    /// Sends the message at `buffer` (`message_size` bytes) in the memory of the running process to a queue
+    ///
+    /// Returns `Ok(Some(0))` once the message is queued or handed over to a waiting receiver, or `Ok(None)` if
+    /// the process waits for a free slot : the result is then delivered to the process when it resumes.
+    pub fn mq_send(&mut self, id: u32, buffer: u32, timeout: Timeout) -> Result<Option<u32>, IpcError> {
+        let sender = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let queue = unsafe { &mut *self.find_message_queue(id)? };
+        let message_size = queue.message_size;
+        let mut message = [0u8; MAX_MESSAGE_SIZE];
+        user::copy_from(unsafe { &(*sender).proc_mpu }, &mut message[..message_size], buffer).map_err(|_| IpcError::Fault)?;
+
+        // A receiver whose buffer can't be written is woken up with the error, the message goes to the next one
+        while let Some(receiver) = queue.receivers.pop() {
+            let result = Self::copy_to_waiting(receiver, &message[..message_size]);
+            let delivered = result.is_ok();
+            self.wake_process(receiver, result.map(|_| message_size as u32));
+            if delivered {
+                return Ok(Some(0));
+            }
+        }
+        if !queue.is_full() {
+            queue.push(&message[..message_size]);
+            return Ok(Some(0));
+        }
+        if timeout == Timeout::NoWait {
+            return Err(IpcError::WouldBlock);
+        }
+
+        queue.senders.push(sender);
+        self.block_current_process(WaitObject::MessageSend { queue: id, buffer }, timeout);
+        Ok(None)
+    }
+
+    /// Receives the message at the head of a queue in `buffer` (`message_size` bytes), in the memory of the running process
+    ///
+    /// Returns `Ok(Some(message_size))` once a message is received, or `Ok(None)` if the process waits for a message :
+    /// the result is then delivered to the process when it resumes.
+    pub fn mq_receive(&mut self, id: u32, buffer: u32, timeout: Timeout) -> Result<Option<u32>, IpcError> {
+        let receiver = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let queue = unsafe { &mut *self.find_message_queue(id)? };
+        let message_size = queue.message_size;
+        // Checked now, as the message may be copied while the process waits
+        if !unsafe { (*receiver).proc_mpu.check_unprivileged_access(buffer, message_size, Access::Write) } {
+            return Err(IpcError::Fault);
+        }
+
+        if queue.is_empty() {
+            if timeout == Timeout::NoWait {
+                return Err(IpcError::WouldBlock);
+            }
+            queue.receivers.push(receiver);
+            self.block_current_process(WaitObject::MessageReceive { queue: id, buffer }, timeout);
+            return Ok(None);
+        }
+
+        let mut message = [0u8; MAX_MESSAGE_SIZE];
+        queue.pop(&mut message[..message_size]);
+        user::copy_to(unsafe { &(*receiver).proc_mpu }, buffer, &message[..message_size]).map_err(|_| IpcError::Fault)?;
+
+        // The freed slot is filled with the message of the first waiting sender
+        if let Some(sender) = queue.senders.pop() {
+            let result = match unsafe { (*sender).wait_object } {
+                Some(WaitObject::MessageSend { buffer, .. }) => {
+                    user::copy_from(unsafe { &(*sender).proc_mpu }, &mut message[..message_size], buffer).map_err(|_| IpcError::Fault)
+                }
+                _ => Err(IpcError::Fault)
+            };
+            if result.is_ok() {
+                queue.push(&message[..message_size]);
+            }
+            self.wake_process(sender, result.map(|_| 0));
+        }
+        Ok(Some(message_size as u32))
+    }
+
+    /// Get the queue of a handle
+    ///
+    /// A pointer is returned, as the process lists are updated while the queue is in use.
+    pub(super) fn find_message_queue(&mut self, id: u32) -> Result<*mut MessageQueue, IpcError> {
+        self.message_queues.iter_mut().flatten()
+            .find(|queue| queue.id == id)
+            .map(|queue| queue as *mut MessageQueue)
+            .ok_or(IpcError::InvalidHandle)
+    }
+
+    /// Copies a message to the buffer of a receiver waiting on a queue
+    fn copy_to_waiting(receiver: *mut Process, message: &[u8]) -> Result<(), IpcError> {
+        match unsafe { (*receiver).wait_object } {
+            Some(WaitObject::MessageReceive { buffer, .. }) => {
+                user::copy_to(unsafe { &(*receiver).proc_mpu }, buffer, message).map_err(|_| IpcError::Fault)
+            }
+            _ => Err(IpcError::Fault)
+        }
+    }
+
+    /// Destroys the message queues created by a process, the processes waiting on them are woken up with `IpcError::Removed`
+    pub(super) fn destroy_message_queues(&mut self, pid: u16) {
+        for slot in 0..MAX_MESSAGE_QUEUES {
+            if !self.message_queues[slot].as_ref().is_some_and(|queue| queue.owner == pid) {
+                continue;
+            }
+            if let Some(mut queue) = self.message_queues[slot].take() {
+                while let Some(process) = queue.senders.pop().or_else(|| queue.receivers.pop()) {
+                    self.wake_process(process, Err(IpcError::Removed));
+                }
+                log_debug!("> PID {} : message queue {} destroyed", pid, queue.id);
+            }
+        }
+    }
+}
@@ -1,32 +1,234 @@
-use core::{u8, ptr};
+use core::{u8, ptr, fmt};
 
-use alloc::vec::Vec;
+use alloc::boxed::Box;
 
-use crate::log_debug;
-use crate::memory_management::{heap, mpu::{self, Mpu, mpu_type, mpu_perm}};
+use crate::{log_debug, log_info};
+use crate::entropy;
+use crate::memory_management::heap::{self, HeapError, HeapReport, Owner};
+use crate::memory_management::pool::{self, PoolAllocator};
+use crate::memory_management::{mpu::{self, Access, AccessPermission, Execute, Mpu, RegionBuilder}};
 use crate::utils::LinkedList;
 use crate::init::{CURRENT_PROCESS_SP, NEXT_PROCESS_SP};
 
-#[derive(Default,PartialEq,Clone,Copy)]
-#[allow(dead_code)]
+pub mod elf;
+mod events;
+mod lifecycle;
+mod message_queue;
+mod ready_queue;
+mod shared_memory;
+mod sync;
+mod timer_wheel;
+mod wait_queue;
+pub use ready_queue::PRIORITY_LEVELS;
+use elf::{ElfError, LoadedImage};
+use lifecycle::{Program, MAX_PROGRAMS};
+use message_queue::{MessageQueue, MAX_MESSAGE_QUEUES};
+use ready_queue::ReadyQueue;
+use shared_memory::{SharedMemory, MAX_SHARED_MEMORIES};
+use sync::{KernelMutex, Semaphore, MAX_MUTEXES, MAX_SEMAPHORES};
+use timer_wheel::TimerWheel;
+use wait_queue::WaitQueue;
+
+pub use events::EventWait;
+pub use lifecycle::PROGRAM_NAME_LEN;
+pub use shared_memory::{ShmAccess, SHM_NAME_LEN};
+pub use wait_queue::{IpcError, Timeout};
+
+#[derive(Default,PartialEq,Clone,Copy,Debug)]
 pub enum ProcStatus{
     #[default] Idle,
     Running,
     Waiting,
-    Finished
+    Finished,
+    /// Terminated, its resources are released but its exit status is kept until its parent waits for it
+    Zombie
 }
 
 const DEFAULT_STACK_SIZE: usize = 1024; 
+/// Default maximum heap memory held by a process : image, stack, and memory allocated for the process
+const DEFAULT_MEMORY_QUOTA: usize = 16 * 1024;
+/// MPU region making the kernel heap execute-never, with the `xn-heap` feature
+#[cfg(feature = "xn-heap")]
+const KERNEL_HEAP_REGION: u8 = 0;
+/// MPU region of the image of a process, the following regions hold its writable segments and its stack
+const IMAGE_REGION: u8 = 1;
+/// First MPU region that may hold memory mapped by `map_memory`, lower regions hold the image. Regions
+/// already used by the writable segments or the stack are skipped.
+const FIRST_MAPPING_REGION: u8 = 2;
+/// Maximum size of a memory area mapped by `map_memory`, larger than the heap
+const MAX_MAPPING_SIZE: usize = 1 << 20;
 const INIT_STACK_FRAME_SIZE: usize = size_of::<usize>() * 16;
+/// Size of R4-R11, saved by PendSV below the exception frame of a process
+pub const CALLEE_SAVED_SIZE: usize = size_of::<[usize; 8]>();
+/// Default time slice (in SysTick periods) given to a process before rotating with its peers
+const DEFAULT_TIME_SLICE: u32 = 1;
+
+const IDLE_STACK_SIZE: usize = 256;
+/// PID of the idle process, not used by any user process
+const IDLE_PROC_ID: u16 = 0;
+/// Priority of the idle process, lower than any user process priority
+const IDLE_PRIORITY: u8 = PRIORITY_LEVELS as u8;
+/// Number of SysTick periods over which the CPU load is measured
+const CPU_LOAD_WINDOW: u32 = 10;
+/// Number of terminated processes whose exit status is kept
+const EXIT_HISTORY_SIZE: usize = 4;
+
+/// Fault exception which killed a process
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum FaultKind {
+    HardFault,
+    MemManage,
+    BusFault,
+    UsageFault
+}
+
+/// Fault reason recorded when a process is killed by a fault
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub struct FaultInfo {
+    pub kind: FaultKind,
+    /// Configurable Fault Status Register
+    pub cfsr: u32,
+    /// Faulting PC, unknown if the exception frame could not be stacked
+    pub pc: Option<u32>,
+    /// Faulting address (MMFAR or BFAR) if valid, or the fetched address of an instruction access violation
+    pub address: Option<u32>
+}
+
+impl FaultInfo {
+    /// Check if the fault is an instruction fetch from execute-never or inaccessible memory (MMFSR.IACCVIOL)
+    pub fn is_instruction_access_violation(&self) -> bool {
+        const IACCVIOL: u32 = 1 << 0;
+
+        self.kind == FaultKind::MemManage && self.cfsr & IACCVIOL != 0
+    }
+}
+
+/// How a process terminated
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum ExitStatus {
+    /// SYS_EXIT with an exit code
+    Exited(u32),
+    /// Killed by a fault
+    Faulted(FaultInfo),
+    /// Killed by the kernel
+    Killed,
+    /// Killed because the canary at the bottom of its stack was overwritten
+    StackOverflow
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExitStatus::Exited(code) => write!(f, "exited with code {:#x}", code),
+            ExitStatus::Faulted(fault) => {
+                write!(f, "{:?} (CFSR {:#010x}", fault.kind, fault.cfsr)?;
+                if let Some(pc) = fault.pc {
+                    write!(f, ", PC {:#010x}", pc)?;
+                }
+                if let Some(address) = fault.address {
+                    write!(f, ", address {:#010x}", address)?;
+                }
+                if fault.is_instruction_access_violation() {
+                    write!(f, ", instruction fetch")?;
+                }
+                write!(f, ")")
+            }
+            ExitStatus::Killed => write!(f, "killed"),
+            ExitStatus::StackOverflow => write!(f, "stack overflow (canary overwritten)")
+        }
+    }
+}
+
+/// Error of `SystemProcess::map_memory`, `SystemProcess::unmap_memory` and of the shared memory operations
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum MapError {
+    /// No process running, or the idle process
+    NoProcess,
+    /// Null length, or larger than `MAX_MAPPING_SIZE`
+    InvalidLength,
+    /// The address is not the start of a memory area mapped by `map_memory`, or of a shared memory
+    InvalidAddress,
+    /// All the MPU regions of the process are in use
+    NoFreeRegion,
+    /// Not enough heap memory, or the process would exceed its memory quota
+    OutOfMemory,
+    /// Empty shared memory name, or longer than `SHM_NAME_LEN`
+    InvalidName,
+    /// No shared memory with this name
+    NotFound,
+    /// A shared memory with this name already exists
+    AlreadyExists,
+    /// All the shared memory objects are in use
+    NoFreeSlot
+}
+
+/// Kernel object a Waiting process is blocked on, with the syscall arguments needed to complete the operation
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum WaitObject {
+    /// Waits for a free slot in a message queue, to send the message at `buffer`
+    MessageSend { queue: u32, buffer: u32 },
+    /// Waits for a message, to be copied at `buffer`
+    MessageReceive { queue: u32, buffer: u32 },
+    /// Waits for a mutex to be released
+    Mutex { mutex: u32 },
+    /// Waits for a semaphore to be posted
+    Semaphore { semaphore: u32 },
+    /// Waits for events of `mask` to be notified
+    Events { mask: u32, wait: EventWait },
+    /// Waits for the child `pid` to terminate, to write its exit status at `status`
+    Child { pid: u16, status: u32 }
+}
+
+/// Exit status of a terminated process
+#[derive(Clone,Copy)]
+struct ExitRecord {
+    proc_id: u16,
+    proc_name: &'static str,
+    status: ExitStatus
+}
 
 /// This struct hold reference to the Process List of the system, and the PID of the running process
 /// 
 /// All operation performed on process are implemented here (create, kill, schedule, ...) 
+/// 
+/// Processes ready to run are also referenced in `ready_queue`, one FIFO per priority level, so that
+/// the scheduler picks the next process without walking the Process List.
+/// Sleeping processes are referenced in `timer_wheel` until their deadline. Processes blocked on a kernel
+/// object (`WaitObject`) are referenced in the wait queue of the object, and in `timer_wheel` if they have a timeout.
+/// 
+/// When no process is ready, the kernel-owned `idle_process` runs. It is not part of the Process List.
 pub struct SystemProcess {
     last_proc_id: u16,
-    process_list: LinkedList<Process>,
+    process_list: LinkedList<Process, PoolAllocator>,
+    ready_queue: ReadyQueue,
+    timer_wheel: TimerWheel,
+    current_tick: u64,
+    current_process: *mut Process,
     current_process_id: u16,
-    current_mpu_conf: Option<Mpu>
+    current_mpu_conf: Option<Mpu>,
+    time_slices: [u32; PRIORITY_LEVELS],
+    need_resched: bool,
+    idle_process: *mut Process,
+    cpu_load: CpuLoad,
+    exit_history: [Option<ExitRecord>; EXIT_HISTORY_SIZE],
+    exit_history_next: usize,
+    message_queues: [Option<MessageQueue>; MAX_MESSAGE_QUEUES],
+    mutexes: [Option<KernelMutex>; MAX_MUTEXES],
+    semaphores: [Option<Semaphore>; MAX_SEMAPHORES],
+    shared_memories: [Option<SharedMemory>; MAX_SHARED_MEMORIES],
+    /// Program images processes may start with `spawn`
+    programs: [Option<Program>; MAX_PROGRAMS],
+    /// Last handle given to a kernel object (message queue, mutex, semaphore)
+    last_object_id: u32
+}
+
+/// Idle time accounting, used to compute the CPU load over the last `CPU_LOAD_WINDOW` ticks
+#[derive(Default)]
+struct CpuLoad {
+    window_ticks: u32,
+    window_idle_ticks: u32,
+    total_idle_ticks: u64,
+    last_load: u8
 }
 
 unsafe impl Send for SystemProcess {}
@@ -36,12 +238,64 @@ impl SystemProcess {
     pub fn new() -> SystemProcess {
         SystemProcess {
             last_proc_id: 0,
-            process_list: LinkedList::new(),
+            process_list: LinkedList::new_in(PoolAllocator),
+            ready_queue: ReadyQueue::new(),
+            timer_wheel: TimerWheel::new(),
+            current_tick: 0,
+            current_process: ptr::null_mut(),
             current_process_id: 0,
-            current_mpu_conf: None
+            current_mpu_conf: None,
+            time_slices: [DEFAULT_TIME_SLICE; PRIORITY_LEVELS],
+            need_resched: false,
+            idle_process: ptr::null_mut(),
+            cpu_load: CpuLoad::default(),
+            exit_history: [None; EXIT_HISTORY_SIZE],
+            exit_history_next: 0,
+            message_queues: core::array::from_fn(|_| None),
+            mutexes: core::array::from_fn(|_| None),
+            semaphores: core::array::from_fn(|_| None),
+            shared_memories: core::array::from_fn(|_| None),
+            programs: [None; MAX_PROGRAMS],
+            last_object_id: 0
         }
     }
 
+    /// Creates the idle process, run by the scheduler whenever no other process is ready.
+    /// 
+    /// The idle process runs `idle_task` with the lowest priority. It is owned by the kernel :
+    /// it is not part of the Process List, so it can't be killed and is not listed.
+    pub fn create_idle_process(&mut self) {
+        if !self.idle_process.is_null() {
+            return;
+        }
+
+        let stack: *mut u8;
+        unsafe {
+            stack = heap::allocate(IDLE_STACK_SIZE);
+        }
+        let sp = stack as usize + IDLE_STACK_SIZE - INIT_STACK_FRAME_SIZE;
+        // Clear the Thumb bit of the function address, the stacked PC must be halfword aligned
+        let entry_point = (idle_task as fn() -> ! as usize & !1) as *mut u8;
+        self.create_init_stack_frame(sp as *mut u8, entry_point);
+
+        // The idle process runs kernel code, with the privileges of the kernel
+        let mut idle = Process::new("idle", IDLE_PROC_ID, stack, sp as u32, ptr::null_mut(), entry_point, IDLE_PRIORITY);
+        idle.privileged = true;
+        Self::configure_kernel_regions(&mut idle);
+        self.idle_process = Box::into_raw_with_allocator(Box::new_in(idle, PoolAllocator)).0;
+    }
+
+    /// Get the CPU load, in percent, measured over the last `CPU_LOAD_WINDOW` ticks
+    pub fn get_cpu_load(&self) -> u8 {
+        self.cpu_load.last_load
+    }
+
+    /// Get the number of ticks spent in the idle process since boot
+    #[allow(dead_code)]
+    pub fn get_idle_ticks(&self) -> u64 {
+        self.cpu_load.total_idle_ticks
+    }
+
     /// Get free PID for new process
     fn get_new_proc_id(&mut self) -> u16 {
         self.last_proc_id += 1;
@@ -63,14 +317,46 @@ impl SystemProcess {
         self.get_process_by_id(self.current_process_id)
     }
 
+    /// Get name of the running process, None if no process is running
+    pub fn get_current_process_name(&self) -> Option<&'static str> {
+        if self.current_process.is_null() {
+            return None;
+        }
+        unsafe { Some((*self.current_process).proc_name) }
+    }
+
+    /// Get PID of the running process, 0 if no process is running
+    pub fn get_current_process_id(&self) -> u16 {
+        self.current_process_id
+    }
+
+    /// Get MPU configuration of the running process, None if no process is running
+    pub fn get_current_process_mpu(&self) -> Option<Mpu> {
+        if self.current_process.is_null() {
+            return None;
+        }
+        unsafe { Some((*self.current_process).proc_mpu) }
+    }
+
+    /// Check if the running process runs privileged (kernel process), processes run unprivileged otherwise
+    pub fn is_current_process_privileged(&self) -> bool {
+        !self.current_process.is_null() && unsafe { (*self.current_process).privileged }
+    }
+
     /// Get priority of the running process
     pub fn get_current_priority_process(&mut self) -> u8 {
-        for process in self.process_list.iter() {
-            if process.proc_id == self.current_process_id {
-                return process.priority;
-            }
+        if self.current_process.is_null() {
+            return 255;
+        }
+        unsafe { (*self.current_process).priority }
+    }
+
+    /// Set the time slice, in SysTick periods, given to processes of a priority level
+    /// before the scheduler rotates to the next process of the same level
+    pub fn set_time_slice(&mut self, priority: u8, ticks: u32) {
+        if (priority as usize) < PRIORITY_LEVELS {
+            self.time_slices[priority as usize] = ticks.max(1);
         }
-        return 255;
     }
 
 
@@ -81,34 +367,132 @@ impl SystemProcess {
     /// * `name` - A string representing the name of the new process.
     /// * `code_ptr` - A byte slice containing the code to be loaded into the process.
     /// * `code_len` - The length of the code to be loaded.
+    /// * `priority` - Priority of the process, from 0 (highest) to `PRIORITY_LEVELS - 1` (lowest).
+    ///   Higher values are clamped to the lowest priority.
     /// 
     /// # Returns
     /// * A unique process ID (PID) for the newly created process.
+    /// * `None` if there is not enough heap memory, or if the code and the stack exceed the memory quota of a process.
     ///
     /// # IMPORTANT
     /// Process code MUST end with a SYS_EXIT then an infinite loop
-    pub fn create_process(&mut self, name: &'static str, code_ptr: &[u8], code_len: usize, priority: u8) -> u16 {
-        let pid = self.get_new_proc_id();
+    pub fn create_process(&mut self, name: &'static str, code_ptr: &[u8], code_len: usize, priority: u8) -> Option<u16> {
+        let code = self.load_process_code(code_ptr, code_len)?;
 
-        let entry_point = self.load_process_code(code_ptr, code_len);
+        self.spawn_process(name, LoadedImage::from_raw(code, code_len), priority)
+    }
 
-        let stack: *mut u8;
-        unsafe { 
-            stack = heap::allocate(DEFAULT_STACK_SIZE);
+    /// Creates a new process from an ELF32 ARM image.
+    /// 
+    /// The `PT_LOAD` segments of the image are loaded and relocated by the ELF loader (see `elf::load`),
+    /// the process starts at `e_entry`, and gets one MPU region per segment with the segment permissions.
+    /// 
+    /// # Arguments
+    /// * `name` - A string representing the name of the new process.
+    /// * `elf` - A byte slice containing the ELF image.
+    /// * `priority` - Priority of the process, from 0 (highest) to `PRIORITY_LEVELS - 1` (lowest).
+    /// 
+    /// # Returns
+    /// * A unique process ID (PID) for the newly created process.
+    /// * An `ElfError` if the image is malformed or unsupported, `ElfError::OutOfMemory` if there is not enough
+    ///   heap memory or if the image and the stack exceed the memory quota of a process.
+    pub fn create_process_from_elf(&mut self, name: &'static str, elf: &[u8], priority: u8) -> Result<u16, ElfError> {
+        let image = elf::load(elf)?;
+
+        self.spawn_process(name, image, priority).ok_or(ElfError::OutOfMemory)
+    }
+
+    /// Allocates the stack of a process whose code is loaded in memory, sets up its MPU regions
+    /// and adds it to the process list.
+    ///
+    /// Returns `None` if the stack can't be allocated, the image is then freed.
+    fn spawn_process(&mut self, name: &'static str, image: LoadedImage, priority: u8) -> Option<u16> {
+        // The image and the stack are owned by the process : both are counted in its quota
+        if unsafe { heap::block_size(image.base) } + heap::block_size_for(DEFAULT_STACK_SIZE) > DEFAULT_MEMORY_QUOTA {
+            log_info!("> {} : image and stack exceed the memory quota", name);
+            unsafe { heap::deallocate(image.base) };
+            return None;
+        }
+
+        // The stack is an MPU region, aligned on its size
+        let stack = unsafe { heap::allocate_aligned(DEFAULT_STACK_SIZE, DEFAULT_STACK_SIZE) };
+        if stack.is_null() {
+            log_info!("> {} : not enough memory for the stack", name);
+            unsafe { heap::deallocate(image.base) };
+            return None;
+        }
+        let pid = self.get_new_proc_id();
+        if unsafe { !heap::set_owner(image.base, pid) || !heap::set_owner(stack, pid) } {
+            log_info!("> {} : too many processes own heap memory", name);
+            unsafe {
+                heap::deallocate(stack);
+                heap::deallocate(image.base);
+            }
+            return None;
         }
         let sp = stack as usize + DEFAULT_STACK_SIZE - INIT_STACK_FRAME_SIZE; // Calculate initial SP
-        self.create_init_stack_frame(sp as *mut u8,entry_point);
+        self.create_init_stack_frame(sp as *mut u8,image.entry_point);
 
-        let mut new_proc = Process::new(name, pid,stack, sp as u32, entry_point, priority);
+        let priority = priority.min(PRIORITY_LEVELS as u8 - 1);
+        let mut new_proc = Process::new(name, pid,stack, sp as u32, image.base, image.entry_point, priority);
 
-        // setup MPU region for code and stack 
-        let base_attr_region = mpu::MPU_REGION_ENABLE | mpu_type::TYPE_NORMAL;
-        let _ = new_proc.proc_mpu.configure_region(0, code_ptr.as_ptr() as u32,self.mpu_region_size_from_memory_len(code_len), base_attr_region | mpu_perm::PRIVILEGED_RW_UNPRIVILEGED_RO);
-        let _ = new_proc.proc_mpu.configure_region(1, stack as u32,self.mpu_region_size_from_memory_len(DEFAULT_STACK_SIZE), base_attr_region | mpu_perm::FULL_ACCESS);
+        // Setup MPU regions (W^X) : the whole image is read-only and executable, then the writable
+        // segments are made read-write and execute-never by higher (overriding) regions, then the stack.
+        // The process runs unprivileged, any access outside of these regions faults.
+        Self::configure_kernel_regions(&mut new_proc);
+        let mut region_number = IMAGE_REGION;
+        let image_region = RegionBuilder::new(region_number, image.base as u32, mpu::region_size(image.size))
+            .access(AccessPermission::ReadOnly)
+            .execute(Execute::Allowed);
+        Self::configure_process_region(&mut new_proc, image_region);
+        region_number += 1;
+        for segment in image.segments.iter().flatten().filter(|segment| segment.flags & elf::PF_W != 0) {
+            // Regions are aligned on their size : the sub-regions outside of the segment are disabled, the
+            // region may still cover the start of the next segment
+            let segment_region = RegionBuilder::covering(region_number, segment.start as u32, segment.size)
+                .access(AccessPermission::FullAccess);
+            Self::configure_process_region(&mut new_proc, segment_region);
+            region_number += 1;
+        }
+        let stack_region = RegionBuilder::new(region_number, stack as u32, DEFAULT_STACK_SIZE)
+            .access(AccessPermission::FullAccess);
+        Self::configure_process_region(&mut new_proc, stack_region);
+
+        // A writable segment, or a code segment sharing an MPU sub-region with one, is execute-never
+        for segment in image.segments.iter().flatten().filter(|segment| segment.flags & elf::PF_X != 0) {
+            if !new_proc.proc_mpu.check_unprivileged_access(segment.start as u32, segment.size, Access::Execute) {
+                log_info!("PID {} : code segment at {:p} is not executable (W^X)", pid, segment.start);
+            }
+        }
 
         self.process_list.add(new_proc);
-        return pid;
+        if let Some(process) = self.process_list.last_mut().map(|process| process as *mut Process) {
+            self.make_ready(process);
+        }
+        Some(pid)
+    }
+
+    /// Put a process in the run queue of its priority
+    /// 
+    /// If the process has a higher priority than the running one, a reschedule is requested
+    /// so that the running process gets preempted.
+    ///
+    /// A process blocked by a syscall runs until PendSV switches it out : SysTick or an interrupt handler may
+    /// wake it up before. It is then still the running process, and keeps running instead of being queued.
+    fn make_ready(&mut self, process: *mut Process) {
+        if ptr::eq(process, self.current_process) {
+            unsafe { (*process).status = ProcStatus::Running };
+            return;
+        }
+
+        unsafe {
+            (*process).status = ProcStatus::Idle;
+            self.ready_queue.push_back(process);
 
+            if !self.current_process.is_null() && (*process).priority < (*self.current_process).priority {
+                self.need_resched = true;
+            }
+        }
     }
 
     /// Loads the code for a new process into heap memory and returns the pointer to the allocated space.
@@ -119,13 +503,17 @@ impl SystemProcess {
     ///
     /// # Returns
     /// * A pointer to the allocated memory containing the process code.
-    fn load_process_code(&mut self, code_ptr: &[u8], code_len: usize) -> *mut u8{
-        let heap_ptr: *mut u8;
-        unsafe { 
-            heap_ptr = heap::allocate(code_len);
+    /// * `None` if there is not enough heap memory.
+    fn load_process_code(&mut self, code_ptr: &[u8], code_len: usize) -> Option<*mut u8> {
+        let region_size = mpu::region_size(code_len);
+        let heap_ptr = unsafe { heap::allocate_aligned(region_size, region_size) };
+        if heap_ptr.is_null() {
+            return None;
+        }
+        unsafe {
             ptr::copy_nonoverlapping(code_ptr.as_ptr(), heap_ptr, code_len);
         }
-        return heap_ptr;
+        Some(heap_ptr)
     }
 
     /// Initializes the stack frame for a new process. This function sets up the initial values 
@@ -160,35 +548,368 @@ impl SystemProcess {
     /// Kill a specific process based on a PID
     pub fn kill_process(&mut self, proc_id: u16) {
         log_debug!("> KILL PID {}",proc_id);
-        for process in self.process_list.iter() {
-            if process.proc_id == proc_id {                
-                unsafe { 
-                    heap::deallocate(process.entry_point);
-                    heap::deallocate(process.stack); 
+        let process_ptr = match self.process_list.iter_mut().find(|process| process.proc_id == proc_id) {
+            Some(process) => process as *mut Process,
+            None => return
+        };
+
+        unsafe {
+            match (*process_ptr).status {
+                // Already killed, waiting for its parent
+                ProcStatus::Zombie => return,
+                ProcStatus::Idle => { self.ready_queue.remove(process_ptr); }
+                ProcStatus::Waiting => {
+                    self.timer_wheel.remove(process_ptr);
+                    if let Some(object) = (*process_ptr).wait_object {
+                        self.cancel_wait(process_ptr, object);
+                    }
                 }
+                _ => {}
+            }
+            if self.current_process == process_ptr {
+                self.current_process = ptr::null_mut();
+                self.current_process_id = 0;
+                // The regions of the process are still in the MPU : its read-only image would prevent the
+                // kernel from reusing the memory released below, until the next process is scheduled
+                (*process_ptr).proc_mpu.disable();
+            }
+
+            self.record_exit(&*process_ptr);
+            self.destroy_message_queues(proc_id);
+            self.release_sync_objects(process_ptr);
+            self.unmap_shared_memories(process_ptr);
+
+            // Release the image, the stack, and any other block owned by the process
+            let reclaimed = heap::reclaim(proc_id);
+            log_debug!("> PID {} : {} bytes reclaimed", proc_id, reclaimed);
 
-                self.process_list.delete(process);
-                break;
+            self.orphan_children(proc_id);
+            if !self.make_zombie(process_ptr) {
+                self.process_list.delete(ptr::read(process_ptr));
             }
         }
     }
 
     /// Mark the running process as Finished, so that this process get killed on next scheduler call
-    pub fn exit_current_process(&mut self) {
-        for process in self.process_list.iter_mut() {
-            if process.proc_id == self.current_process_id {
-                process.status = ProcStatus::Finished;
-                break;
+    /// 
+    /// # Arguments
+    /// * `status` - How the process terminated (exit code, or fault reason), kept once the process is killed
+    pub fn exit_current_process(&mut self, status: ExitStatus) {
+        if !self.current_process.is_null() {
+            unsafe {
+                (*self.current_process).status = ProcStatus::Finished;
+                (*self.current_process).exit_status = Some(status);
             }
+            self.need_resched = true;
         }
     }
 
+    /// Keep the exit status of a process being killed, the oldest status is dropped
+    fn record_exit(&mut self, process: &Process) {
+        let status = process.exit_status.unwrap_or(ExitStatus::Killed);
+        log_debug!("> PID {} ({}) {}", process.proc_id, process.proc_name, status);
+
+        self.exit_history[self.exit_history_next] = Some(ExitRecord {
+            proc_id: process.proc_id,
+            proc_name: process.proc_name,
+            status
+        });
+        self.exit_history_next = (self.exit_history_next + 1) % EXIT_HISTORY_SIZE;
+    }
+
+    /// Get the exit status of a terminated process, if it is one of the last `EXIT_HISTORY_SIZE` terminated processes
+    #[allow(dead_code)]
+    pub fn get_exit_status(&self, proc_id: u16) -> Option<ExitStatus> {
+        self.exit_history.iter().flatten()
+            .find(|record| record.proc_id == proc_id)
+            .map(|record| record.status)
+    }
+
+    /// Account the elapsed tick as busy or idle time, and update the CPU load at the end of each window
+    fn account_cpu_load(&mut self) {
+        let cpu_load = &mut self.cpu_load;
+
+        cpu_load.window_ticks += 1;
+        if !self.current_process.is_null() && self.current_process == self.idle_process {
+            cpu_load.window_idle_ticks += 1;
+            cpu_load.total_idle_ticks += 1;
+        }
+
+        if cpu_load.window_ticks >= CPU_LOAD_WINDOW {
+            let busy_ticks = cpu_load.window_ticks - cpu_load.window_idle_ticks;
+            cpu_load.last_load = (busy_ticks * 100 / cpu_load.window_ticks) as u8;
+            cpu_load.window_ticks = 0;
+            cpu_load.window_idle_ticks = 0;
+        }
+    }
+
+    /// Put the running process to sleep for `ticks` SysTick periods
+    /// 
+    /// The process is marked as Waiting and parked in the timer wheel until its deadline,
+    /// a reschedule is requested so that another process gets the CPU.
+    pub fn sleep_current_process(&mut self, ticks: u64) {
+        if self.current_process.is_null() {
+            return;
+        }
+
+        unsafe {
+            (*self.current_process).status = ProcStatus::Waiting;
+            (*self.current_process).wake_tick = self.current_tick + ticks.max(1);
+            self.timer_wheel.insert(self.current_process);
+        }
+        self.need_resched = true;
+    }
+
+    /// Block the running process on a kernel object, until the object wakes it up with `wake_process`
+    ///
+    /// With `Timeout::Ticks`, the process is also parked in the timer wheel : `tick` wakes it up with
+    /// `IpcError::TimedOut` if the object did not wake it up before its deadline.
+    fn block_current_process(&mut self, object: WaitObject, timeout: Timeout) {
+        if self.current_process.is_null() {
+            return;
+        }
+
+        unsafe {
+            (*self.current_process).status = ProcStatus::Waiting;
+            (*self.current_process).wait_object = Some(object);
+            if let Timeout::Ticks(ticks) = timeout {
+                (*self.current_process).wake_tick = self.current_tick + ticks.max(1);
+                self.timer_wheel.insert(self.current_process);
+            }
+        }
+        self.need_resched = true;
+    }
+
+    /// Make a process blocked on a kernel object ready, with the result of the syscall it was blocked in
+    ///
+    /// The process must have been removed from the wait queue of the object. The result is written in its
+    /// stacked R0 when it resumes (`deliver_wait_result`).
+    fn wake_process(&mut self, process: *mut Process, result: Result<u32, IpcError>) {
+        unsafe {
+            self.timer_wheel.remove(process);
+            (*process).wait_object = None;
+            (*process).wait_result = Some(match result {
+                Ok(value) => value,
+                Err(error) => (-error.errno()) as u32
+            });
+        }
+        self.make_ready(process);
+    }
+
+    /// Get the wait queue of the object a process is blocked on, `None` if the object was destroyed
+    fn wait_queue_of(&mut self, object: WaitObject) -> Option<*mut WaitQueue> {
+        unsafe {
+            match object {
+                WaitObject::MessageSend { queue, .. } => self.find_message_queue(queue).ok().map(|queue| &raw mut (*queue).senders),
+                WaitObject::MessageReceive { queue, .. } => self.find_message_queue(queue).ok().map(|queue| &raw mut (*queue).receivers),
+                WaitObject::Mutex { mutex } => self.find_mutex(mutex).ok().map(|mutex| &raw mut (*mutex).waiters),
+                WaitObject::Semaphore { semaphore } => self.find_semaphore(semaphore).ok().map(|semaphore| &raw mut (*semaphore).waiters),
+                WaitObject::Events { .. } | WaitObject::Child { .. } => None
+            }
+        }
+    }
+
+    /// Remove a process from the wait queue of the object it is blocked on, on timeout or when it is killed
+    ///
+    /// The holder of a mutex loses the priority it inherited from the process.
+    fn cancel_wait(&mut self, process: *mut Process, object: WaitObject) {
+        if let Some(wait_queue) = self.wait_queue_of(object) {
+            unsafe { (*wait_queue).remove(process) };
+        }
+        if let WaitObject::Mutex { mutex } = object
+            && let Ok(mutex) = self.find_mutex(mutex) {
+            self.update_priority(unsafe { (*mutex).holder });
+        }
+    }
+
+    /// Write the result of the syscall a process was blocked in to the R0 of its exception frame
+    fn deliver_wait_result(process: &mut Process) {
+        if let Some(result) = process.wait_result.take() {
+            let stacked_r0 = (process.stored_sp as usize + CALLEE_SAVED_SIZE) as *mut u32;
+            unsafe {
+                ptr::write_volatile(stacked_r0, result);
+            }
+        }
+    }
+
+    /// Get the running process if it is a user process, i.e. neither the idle process nor no process
+    fn running_user_process(&self) -> Option<*mut Process> {
+        if self.current_process.is_null() || self.current_process == self.idle_process {
+            return None;
+        }
+        Some(self.current_process)
+    }
+
+    /// Get a new handle for a kernel object
+    fn new_object_id(&mut self) -> u32 {
+        self.last_object_id += 1;
+        self.last_object_id
+    }
+
+    /// Check if the scheduler has to be called
+    pub fn need_resched(&self) -> bool {
+        self.need_resched
+    }
+
+    /// Account one SysTick period : wake up sleeping processes whose deadline is reached, and
+    /// update the time slice of the running process
+    /// 
+    /// # Arguments
+    /// * `now` - Value of the monotonic tick counter
+    /// 
+    /// # Returns
+    /// * `true` if the scheduler has to be called : the running process used its whole time slice while
+    ///   another process of the same priority is ready, a higher priority process became ready, the running
+    ///   process finished or is waiting, or no process is running yet.
+    pub fn tick(&mut self, now: u64) -> bool {
+        self.current_tick = now;
+        self.account_cpu_load();
+
+        while let Some(process) = self.timer_wheel.pop_expired(now) {
+            match unsafe { (*process).wait_object } {
+                Some(object) => {
+                    self.cancel_wait(process, object);
+                    self.wake_process(process, Err(IpcError::TimedOut));
+                }
+                None => self.make_ready(process)
+            }
+        }
+
+        if self.current_process.is_null() {
+            return true;
+        }
+
+        let current = unsafe { &mut *self.current_process };
+        if current.time_slice > 0 {
+            current.time_slice -= 1;
+        }
+        if current.time_slice == 0 && self.ready_queue.has_ready(current.priority) {
+            self.need_resched = true;
+        }
+        if self.ready_queue.highest_priority().is_some_and(|priority| priority < current.priority) {
+            self.need_resched = true;
+        }
+        self.need_resched
+    }
+
     /// List process in the Process List with the following format : 
-    /// \[PID\] PROC_NAME (PROC_STATUS)
+    /// \[PID\] PROC_NAME (PROC_STATUS) MEMORY_USED/MEMORY_QUOTA bytes
+    /// 
+    /// followed by the exit status of the last terminated processes, the CPU load, and the usage of the kernel pools
     pub fn list_proc(&mut self) {
         for process in self.process_list.iter_mut() {
-            log_debug!("> [{}] {} ({})",process.proc_id,process.proc_name,process.status as u8);
+            log_debug!("> [{}] {} ({}) {}/{} bytes",process.proc_id,process.proc_name,process.status as u8,heap::usage(process.proc_id),process.memory_quota);
+        }
+        for record in self.exit_history.iter().flatten() {
+            log_debug!("> [{}] {} : {}",record.proc_id,record.proc_name,record.status);
+        }
+        log_debug!("> CPU load : {}%",self.cpu_load.last_load);
+        for stats in pool::kernel_pool_stats() {
+            log_debug!("> Pool {} ({} bytes) : {}/{} used, peak {}",stats.name,stats.block_size,stats.used,stats.capacity,stats.peak_used);
+        }
+    }
+
+    /// Get the heap memory held by a process, headers included
+    #[allow(dead_code)]
+    pub fn get_memory_usage(&self, proc_id: u16) -> usize {
+        heap::usage(proc_id)
+    }
+
+    /// Get the owner to give to `heap::allocate_for` for memory allocated on behalf of a process,
+    /// with the quota of the process
+    #[allow(dead_code)]
+    pub fn get_heap_owner(&mut self, proc_id: u16) -> Option<Owner> {
+        self.process_list.iter()
+            .find(|process| process.proc_id == proc_id)
+            .map(|process| Owner { pid: process.proc_id, quota: process.memory_quota })
+    }
+
+    /// Set the maximum heap memory a process may hold, returns `false` if there is no process with this PID
+    ///
+    /// The quota is checked by the allocations made for the process, memory already held is kept.
+    #[allow(dead_code)]
+    pub fn set_memory_quota(&mut self, proc_id: u16, quota: usize) -> bool {
+        match self.process_list.iter_mut().find(|process| process.proc_id == proc_id) {
+            Some(process) => {
+                process.memory_quota = quota;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Maps a memory area of at least `len` bytes in the running process, returns its address
+    ///
+    /// The area is a zeroed heap block of `mpu::region_size(len)` bytes, aligned on its size and owned by
+    /// the process, installed in a free MPU region of the process (from `FIRST_MAPPING_REGION`) with full access.
+    /// The new MPU configuration is used from the next context switch, or `enable_current_mpu`.
+    pub fn map_memory(&mut self, len: usize) -> Result<u32, MapError> {
+        if self.current_process.is_null() || self.current_process == self.idle_process {
+            return Err(MapError::NoProcess);
+        }
+        if len == 0 || len > MAX_MAPPING_SIZE {
+            return Err(MapError::InvalidLength);
+        }
+        let process = unsafe { &mut *self.current_process };
+
+        let number = process.proc_mpu.find_free_region(FIRST_MAPPING_REGION).ok_or(MapError::NoFreeRegion)?;
+        let size = mpu::region_size(len);
+        let owner = Owner { pid: process.proc_id, quota: process.memory_quota };
+        let memory = unsafe { heap::allocate_aligned_for(owner, size, size) };
+        if memory.is_null() {
+            return Err(MapError::OutOfMemory);
+        }
+        // The block may hold data of the kernel or of a terminated process
+        unsafe {
+            ptr::write_bytes(memory, 0, size);
         }
+
+        let region = RegionBuilder::new(number, memory as u32, size).access(AccessPermission::FullAccess);
+        if process.proc_mpu.configure(region).is_err() {
+            // Not expected, the block is aligned on its size
+            unsafe { heap::deallocate(memory) };
+            return Err(MapError::InvalidAddress);
+        }
+        process.mapped_regions |= 1 << number;
+        self.current_mpu_conf = Some(process.proc_mpu);
+        Ok(memory as u32)
+    }
+
+    /// Unmaps a memory area mapped by `map_memory` in the running process : removes its MPU region and frees it
+    pub fn unmap_memory(&mut self, address: u32) -> Result<(), MapError> {
+        if self.current_process.is_null() || self.current_process == self.idle_process {
+            return Err(MapError::NoProcess);
+        }
+        let process = unsafe { &mut *self.current_process };
+
+        let number = (FIRST_MAPPING_REGION..8)
+            .find(|number| process.mapped_regions & (1 << number) != 0 && process.proc_mpu.region_base(*number) == Some(address))
+            .ok_or(MapError::InvalidAddress)?;
+
+        process.proc_mpu.clear_region(number);
+        process.mapped_regions &= !(1 << number);
+        self.current_mpu_conf = Some(process.proc_mpu);
+        unsafe {
+            heap::deallocate(address as *mut u8);
+        }
+        Ok(())
+    }
+
+    /// Check the integrity of the heap with `heap::verify`, and log the first corrupted block with its owner
+    pub fn verify_heap(&mut self) -> Result<HeapReport, HeapError> {
+        let result = heap::verify();
+        if let Err(error) = result {
+            log_info!("Heap corruption : {:?} in block {:#x}", error.corruption, error.block);
+            match error.owner {
+                Some(heap::KERNEL_PID) => { log_info!("> Owned by the kernel"); }
+                Some(owner) => {
+                    let name = self.process_list.iter().find(|process| process.proc_id == owner).map_or("?", |process| process.proc_name);
+                    log_info!("> Owned by PID {} ({})", owner, name);
+                }
+                None => {}
+            }
+        }
+        result
     }
 
     pub fn enable_current_mpu(&self) {
@@ -199,120 +920,152 @@ impl SystemProcess {
         self.current_mpu_conf.unwrap().disable();
     }
 
-    fn mpu_region_size_from_memory_len(&self, size: usize) -> u32 {
-        (self.next_power_of_two_exponent(size) + 1) as u32
+    /// Installs the MPU regions protecting the kernel memory while a process runs
+    ///
+    /// With the `xn-heap` feature, the SRAM heap is execute-never, privileged read-write : only the image
+    /// regions of the processes, which override it, are executable.
+    fn configure_kernel_regions(_process: &mut Process) {
+        #[cfg(feature = "xn-heap")]
+        {
+            let (start, end) = heap::HeapRegion::Sram.bounds();
+            let heap_region = RegionBuilder::covering(KERNEL_HEAP_REGION, start as u32, end - start)
+                .access(AccessPermission::PrivilegedRw)
+                .execute(Execute::Never);
+            Self::configure_process_region(_process, heap_region);
+        }
     }
 
-    fn next_power_of_two_exponent(&self, n: usize) -> usize {
-        if n < 2 { return n; }
-
-        let mut p = 1;
-        let mut e = 0;
-        while p < n { 
-            p <<= 1; 
-            e += 1
+    /// Installs an MPU region of a new process, an invalid region is logged and left out : the process
+    /// then faults on the memory it should cover rather than accessing a wrong range
+    fn configure_process_region(process: &mut Process, region: RegionBuilder) {
+        if let Err(error) = process.proc_mpu.configure(region) {
+            log_info!("PID {} : MPU region {:?} rejected : {}", process.proc_id, region, error);
         }
-        e
     }
 
     /// Schedules the next process to run.
-    /// This function also handles killing processes that have finished execution.
+    /// This function also handles killing the running process if it has finished execution.
     ///
     /// It performs the following:
-    /// - Collects the processes that are finished and calls `kill_process` on them.
-    /// - Iterates over the list of processes to identify the next idle process and the currently running process based on priority.
-    /// - Marks the current running process as idle, saves its state, and schedules the next process.
+    /// - Saves the state of the running process, and kills it if it is Finished or if its stack canary has been
+    ///   overwritten. A Waiting process is left out of the run queues, it will be put back by the event it waits for.
+    /// - Keeps the running process if no process of higher priority is ready, and if its time slice is not
+    ///   over or no other process of the same priority is ready.
+    /// - Otherwise puts the running process back in its run queue : at the head if it has been preempted by a
+    ///   higher priority process, at the tail if its time slice is over (round-robin).
+    /// - Picks the first process of the highest priority run queue, or the idle process if no process is ready.
     ///
     /// # Panics
-    /// This function will panic with the message `"NOTHING TO DO"` if it can't find a next process to schedule.
+    /// This function will panic with the message `"NOTHING TO DO"` if no process is ready and the idle process
+    /// has not been created, and if the stack canary of the idle process has been overwritten.
     pub fn schedule_next_process(&mut self) {
 
         log_debug!("\n### CALL TO SCHED ###");
 
-        // Find the next idle process and the current running process
-        let mut next_process = None;
-        let mut current_process = None;
-        
-        
-        let to_kill: Vec<u16> = self.process_list.iter()
-        .filter_map(|process| {
-            if process.status == ProcStatus::Finished {
-                Some(process.proc_id)  // Collect the process ID
-            } else {
-                None
+        self.need_resched = false;
+
+        if !self.current_process.is_null() {
+            let current = unsafe { &mut *self.current_process };
+
+            // Save the current process state
+            unsafe {
+                current.stored_sp = CURRENT_PROCESS_SP;
             }
-        }).collect();
 
-        for proc_id in to_kill {
-            self.kill_process(proc_id);  // Call kill_process outside of the loop
-        }
+            if !current.is_stack_canary_intact() {
+                if self.current_process == self.idle_process {
+                    panic!("Idle process stack overflow");
+                }
+                if current.status != ProcStatus::Finished {
+                    current.status = ProcStatus::Finished;
+                    current.exit_status = Some(ExitStatus::StackOverflow);
+                }
+            }
 
-        // Filter processes by priority
-        let highest_priority = self.process_list.iter()
-            .filter(|process| process.status == ProcStatus::Idle)
-            .min_by_key(|process| process.priority)
-            .map(|process| process.priority);
-        
-        // Iterate over the LinkedList to find the idle and running processes
-        for process in self.process_list.iter_mut() {
-            if process.status == ProcStatus::Running {
-                log_debug!("Current Process: {} (Priority {})", process.proc_name, process.priority);
-                current_process = Some(process);
-            } else if process.status == ProcStatus::Idle {
-                if let Some(priority) = highest_priority {
-                    if process.priority == priority && next_process.is_none() {
-                        log_debug!("Next Process: {} (Priority {})", process.proc_name, process.priority);
-                        next_process = Some(process);
+            if self.current_process == self.idle_process {
+                if self.ready_queue.highest_priority().is_none() {
+                    // Nothing else to do, keep idling
+                    unsafe {
+                        NEXT_PROCESS_SP = current.stored_sp;
+                    }
+                    return;
+                }
+                current.status = ProcStatus::Idle;
+            } else if current.status == ProcStatus::Finished {
+                self.kill_process(current.proc_id);
+            } else if current.status == ProcStatus::Waiting {
+                log_debug!("Waiting Process: {} (Priority {})", current.proc_name, current.priority);
+            } else {
+                log_debug!("Current Process: {} (Priority {})", current.proc_name, current.priority);
+
+                match self.ready_queue.highest_priority() {
+                    Some(priority) if priority < current.priority => {
+                        // Preempted : resume first on its level with the rest of its time slice
+                        current.status = ProcStatus::Idle;
+                        self.ready_queue.push_front(current);
+                    }
+                    Some(priority) if priority == current.priority && current.time_slice == 0 => {
+                        // Time slice over : rotate with the other processes of the same level
+                        current.status = ProcStatus::Idle;
+                        self.ready_queue.push_back(current);
+                    }
+                    _ => {
+                        // Keep the current process running
+                        if current.time_slice == 0 {
+                            current.time_slice = self.time_slices[current.priority as usize];
+                        }
+                        Self::deliver_wait_result(current);
+                        unsafe {
+                            NEXT_PROCESS_SP = current.stored_sp;
+                        }
+                        return;
                     }
                 }
             }
         }
-        if let Some(ref mut current_process) = current_process {
-            // Mark the current process as Idle
-            current_process.status = ProcStatus::Idle;
 
-            // Save the current process state
-            unsafe {
-                current_process.stored_sp = CURRENT_PROCESS_SP;
+        match self.ready_queue.pop_highest() {
+            Some(next_process) => self.switch_to(next_process),
+            None if !self.idle_process.is_null() => self.switch_to(self.idle_process),
+            None => {
+                self.current_process = ptr::null_mut();
+                self.current_process_id = 0;
+                panic!("NOTHING TO DO");
             }
         }
+    }
 
-        // If we found both a running process and an idle process, schedule the next process
-        if let Some(ref mut next_process) = next_process {
-            if next_process.stored_sp != 0 {
-                // Mark the next process as Running
-                next_process.status = ProcStatus::Running;
-
-                self.current_mpu_conf = Some(next_process.proc_mpu);
+    /// Make `next_process` the running process, and set up its state to be restored by the context switch
+    fn switch_to(&mut self, next_process: *mut Process) {
+        let next_process = unsafe { &mut *next_process };
+        log_debug!("Next Process: {} (Priority {})", next_process.proc_name, next_process.priority);
 
-                // Restore the next process state
-                unsafe {
-                    NEXT_PROCESS_SP = next_process.stored_sp;
-                }
-                self.current_process_id = next_process.proc_id;
-            } else {
-                next_process.status = ProcStatus::Finished;
-                self.schedule_next_process();
-            }
-            
-        } else if let Some(ref mut current_process) = current_process {
-            // Set current_process as next_process
-            current_process.status = ProcStatus::Running;
+        // Mark the next process as Running
+        next_process.status = ProcStatus::Running;
+        if next_process.time_slice == 0 {
+            next_process.time_slice = self.time_slices.get(next_process.priority as usize).copied().unwrap_or(DEFAULT_TIME_SLICE);
+        }
 
-            self.current_mpu_conf = Some(current_process.proc_mpu);
+        self.current_mpu_conf = Some(next_process.proc_mpu);
+        Self::deliver_wait_result(next_process);
 
-            unsafe {
-                NEXT_PROCESS_SP = current_process.stored_sp;
-            }
-            self.current_process_id = current_process.proc_id;
-        } else {
-            self.current_process_id = 0;
-            panic!("NOTHING TO DO");
+        // Restore the next process state
+        unsafe {
+            NEXT_PROCESS_SP = next_process.stored_sp;
         }
+        self.current_process = next_process;
+        self.current_process_id = next_process.proc_id;
     }
 
 }
 
+/// Code of the idle process : wait for the next interrupt, in low-power mode
+fn idle_task() -> ! {
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
 /// This struct is the kernel representation of a process
 /// Using this struct, the scheduler can transfert the execution flow to the represented process
 #[derive(PartialEq)]
@@ -323,12 +1076,43 @@ pub struct Process {
     proc_mpu: Mpu,
     stack: *mut u8,
     stored_sp: u32,
+    image: *mut u8,
     entry_point: *mut u8,
-    priority: u8
+    priority: u8,
+    time_slice: u32,
+    wake_tick: u64,
+    privileged: bool,
+    exit_status: Option<ExitStatus>,
+    /// Random value stored in the lowest word of the stack, overwritten when the stack overflows
+    stack_canary: u32,
+    /// Maximum heap memory held by the process, headers included
+    memory_quota: usize,
+    /// Priority given at creation, `priority` being raised above it while the process holds a mutex a
+    /// higher priority process waits for
+    base_priority: u8,
+    /// MPU regions holding memory mapped by `SystemProcess::map_memory`, one bit per region
+    mapped_regions: u8,
+    /// MPU regions holding shared memory mapped by `SystemProcess::shm_create` and `SystemProcess::shm_map`
+    shared_regions: u8,
+    /// Kernel object the process is blocked on, while Waiting
+    wait_object: Option<WaitObject>,
+    /// Value of R0 to return from the syscall the process was blocked in, once it resumes
+    wait_result: Option<u32>,
+    /// Notified events, not yet consumed by `SystemProcess::wait_events`
+    events: u32,
+    /// PID of the process which spawned this one, `heap::KERNEL_PID` if it was created by the kernel
+    parent_id: u16
 }
 
 impl Process {
-    fn new(name: &'static str,proc_id: u16, stack_ptr: *mut u8, init_sp: u32, entry_point: *mut u8, priority: u8) -> Self {
+    fn new(name: &'static str,proc_id: u16, stack_ptr: *mut u8, init_sp: u32, image: *mut u8, entry_point: *mut u8, priority: u8) -> Self {
+        let stack_canary = entropy::random_u32();
+        if !stack_ptr.is_null() {
+            unsafe {
+                ptr::write_volatile(stack_ptr as *mut u32, stack_canary);
+            }
+        }
+
         Process {
             proc_name: name,
             proc_id,
@@ -336,11 +1120,30 @@ impl Process {
             proc_mpu: Mpu::new(),
             stack: stack_ptr,
             stored_sp: init_sp,
+            image,
             entry_point: entry_point,
-            priority: priority
+            priority: priority,
+            base_priority: priority,
+            time_slice: 0,
+            wake_tick: 0,
+            privileged: false,
+            exit_status: None,
+            stack_canary,
+            memory_quota: DEFAULT_MEMORY_QUOTA,
+            mapped_regions: 0,
+            shared_regions: 0,
+            wait_object: None,
+            wait_result: None,
+            events: 0,
+            parent_id: heap::KERNEL_PID
         }
     }
 
+    /// Check that the canary at the bottom of the stack has not been overwritten
+    fn is_stack_canary_intact(&self) -> bool {
+        self.stack.is_null() || unsafe { ptr::read_volatile(self.stack as *const u32) } == self.stack_canary
+    }
+
     pub fn get_stack_ptr(&self) -> u32 {
         self.stored_sp
     }
@@ -348,4 +1151,32 @@ impl Process {
     pub fn get_entry_point(&self) -> *mut u8 {
         self.entry_point
     }
-}
\ No newline at end of file
+
+    pub fn get_proc_id(&self) -> u16 {
+        self.proc_id
+    }
+
+    pub fn get_status(&self) -> ProcStatus {
+        self.status
+    }
+
+    /// Get the priority the process runs at, raised while it holds a mutex a higher priority process waits for
+    pub fn get_priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// Get the PID of the process which spawned this one, `heap::KERNEL_PID` if it was created by the kernel
+    pub fn get_parent_id(&self) -> u16 {
+        self.parent_id
+    }
+
+    pub fn get_mpu(&self) -> &Mpu {
+        &self.proc_mpu
+    }
+
+    /// Get the lowest address of the stack, where the stack canary is stored
+    #[allow(dead_code)]
+    pub fn get_stack_base(&self) -> *mut u8 {
+        self.stack
+    }
+}
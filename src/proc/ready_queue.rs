@@ -0,0 +1,84 @@
+use crate::memory_management::pool::PoolAllocator;
+use crate::utils::LinkedList;
+use super::Process;
+
+/// Number of priority levels handled by the scheduler, 0 being the highest priority
+pub const PRIORITY_LEVELS: usize = 8;
+
+/// Ready processes of the system, sorted by priority
+///
+/// Each priority level has its own FIFO run queue, and a bitmap keeps track of the non-empty levels
+/// so that the highest priority ready process is found in constant time.
+///
+/// ```
+/// ready_bitmap : 0b00000101
+///
+/// [0] -> P3 -> P1
+/// [1]
+/// [2] -> P2
+/// ...
+/// ```
+pub struct ReadyQueue {
+    levels: [LinkedList<*mut Process, PoolAllocator>; PRIORITY_LEVELS],
+    ready_bitmap: u32
+}
+
+impl ReadyQueue {
+    pub fn new() -> ReadyQueue {
+        ReadyQueue {
+            levels: core::array::from_fn(|_| LinkedList::new_in(PoolAllocator)),
+            ready_bitmap: 0
+        }
+    }
+
+    /// Add a process at the end of the run queue of its priority
+    pub fn push_back(&mut self, process: *mut Process) {
+        let priority = unsafe { (*process).priority } as usize;
+        self.levels[priority].add(process);
+        self.ready_bitmap |= 1 << priority;
+    }
+
+    /// Add a process at the head of the run queue of its priority, used when a process gets preempted
+    /// before the end of its time slice
+    pub fn push_front(&mut self, process: *mut Process) {
+        let priority = unsafe { (*process).priority } as usize;
+        self.levels[priority].push_front(process);
+        self.ready_bitmap |= 1 << priority;
+    }
+
+    /// Remove and return the first process of the highest priority non-empty run queue
+    pub fn pop_highest(&mut self) -> Option<*mut Process> {
+        let priority = self.highest_priority()? as usize;
+        let process = self.levels[priority].pop_front();
+
+        if self.levels[priority].is_empty() {
+            self.ready_bitmap &= !(1 << priority);
+        }
+        process
+    }
+
+    /// Remove a specific process from its run queue
+    pub fn remove(&mut self, process: *mut Process) -> bool {
+        let priority = unsafe { (*process).priority } as usize;
+        let removed = self.levels[priority].delete(process);
+
+        if self.levels[priority].is_empty() {
+            self.ready_bitmap &= !(1 << priority);
+        }
+        removed
+    }
+
+    /// Get the highest priority having at least one ready process
+    pub fn highest_priority(&self) -> Option<u8> {
+        if self.ready_bitmap == 0 {
+            None
+        } else {
+            Some(self.ready_bitmap.trailing_zeros() as u8)
+        }
+    }
+
+    /// Check if at least one process is ready at the given priority
+    pub fn has_ready(&self, priority: u8) -> bool {
+        self.ready_bitmap & (1 << priority) != 0
+    }
+}
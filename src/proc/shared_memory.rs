@@ -0,0 +1,182 @@
+//! Named shared memory between processes
+//!
+//! A shared memory object is a zeroed heap block of `mpu::region_size(size)` bytes, aligned on its size so that
+//! it fits in one MPU region. The process creating it maps it read-write, other processes map it by name,
+//! read-write or read-only, in a free MPU region of their own (from `FIRST_MAPPING_REGION`) :
+//!
+//! ```
+//!  Producer MPU                 Shared memory "frames"           Consumer MPU
+//! [6] FullAccess ------------> +-----------------------+ <------ [4] PrivilegedRwUnprivilegedRo
+//!                              | mappings : 2          |
+//!                              +-----------------------+
+//! ```
+//!
+//! The block is owned by the kernel, as it outlives the process which created it. The object counts its
+//! mappings, and is freed when the last one is removed, by `shm_unmap` or when the process is killed.
+
+use core::ptr;
+
+use crate::log_debug;
+use crate::memory_management::heap;
+use crate::memory_management::mpu::{self, AccessPermission, RegionBuilder};
+use super::{MapError, Process, SystemProcess, FIRST_MAPPING_REGION, MAX_MAPPING_SIZE};
+
+/// Maximum number of shared memory objects in the system
+pub const MAX_SHARED_MEMORIES: usize = 8;
+/// Maximum length of the name of a shared memory object
+pub const SHM_NAME_LEN: usize = 16;
+
+/// Access of a process to the shared memory it maps
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum ShmAccess {
+    ReadOnly,
+    ReadWrite
+}
+
+impl ShmAccess {
+    /// Permission of the MPU region mapping the shared memory, the kernel always has read-write access
+    fn permission(self) -> AccessPermission {
+        match self {
+            ShmAccess::ReadOnly => AccessPermission::PrivilegedRwUnprivilegedRo,
+            ShmAccess::ReadWrite => AccessPermission::FullAccess
+        }
+    }
+}
+
+/// A named block of memory mapped in several processes
+pub struct SharedMemory {
+    name: [u8; SHM_NAME_LEN],
+    name_len: usize,
+    memory: *mut u8,
+    size: usize,
+    /// Number of MPU regions mapping the memory, in all the processes
+    mappings: usize
+}
+
+impl SharedMemory {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        unsafe {
+            heap::deallocate(self.memory);
+        }
+    }
+}
+
+impl SystemProcess {
+    /// Creates a shared memory object of at least `size` bytes, and maps it read-write in the running process
+    ///
+    /// Returns the address of the memory, mapped from the next context switch or `enable_current_mpu`.
+    pub fn shm_create(&mut self, name: &[u8], size: usize) -> Result<u32, MapError> {
+        self.running_user_process().ok_or(MapError::NoProcess)?;
+        if name.is_empty() || name.len() > SHM_NAME_LEN {
+            return Err(MapError::InvalidName);
+        }
+        if size == 0 || size > MAX_MAPPING_SIZE {
+            return Err(MapError::InvalidLength);
+        }
+        if self.find_shared_memory(name).is_some() {
+            return Err(MapError::AlreadyExists);
+        }
+        let slot = self.shared_memories.iter().position(|shm| shm.is_none()).ok_or(MapError::NoFreeSlot)?;
+
+        let size = mpu::region_size(size);
+        let memory = unsafe { heap::allocate_aligned(size, size) };
+        if memory.is_null() {
+            return Err(MapError::OutOfMemory);
+        }
+        // The block may hold data of the kernel or of a terminated process
+        unsafe {
+            ptr::write_bytes(memory, 0, size);
+        }
+
+        let mut shm = SharedMemory { name: [0; SHM_NAME_LEN], name_len: name.len(), memory, size, mappings: 0 };
+        shm.name[..name.len()].copy_from_slice(name);
+        self.shared_memories[slot] = Some(shm);
+
+        match self.shm_map_slot(slot, ShmAccess::ReadWrite) {
+            Ok(address) => {
+                log_debug!("> Shared memory {} created ({} bytes)", core::str::from_utf8(name).unwrap_or("?"), size);
+                Ok(address)
+            }
+            Err(error) => {
+                // Dropping the object frees its memory
+                self.shared_memories[slot] = None;
+                Err(error)
+            }
+        }
+    }
+
+    /// Maps the shared memory object `name` in the running process, returns its address
+    pub fn shm_map(&mut self, name: &[u8], access: ShmAccess) -> Result<u32, MapError> {
+        self.running_user_process().ok_or(MapError::NoProcess)?;
+        let slot = self.find_shared_memory(name).ok_or(MapError::NotFound)?;
+        self.shm_map_slot(slot, access)
+    }
+
+    /// Unmaps the shared memory mapped at `address` in the running process, the object is freed with its last mapping
+    pub fn shm_unmap(&mut self, address: u32) -> Result<(), MapError> {
+        let process = unsafe { &mut *self.running_user_process().ok_or(MapError::NoProcess)? };
+        let number = (FIRST_MAPPING_REGION..mpu::REGION_COUNT)
+            .find(|number| process.shared_regions & (1 << number) != 0 && process.proc_mpu.region_base(*number) == Some(address))
+            .ok_or(MapError::InvalidAddress)?;
+
+        process.proc_mpu.clear_region(number);
+        process.shared_regions &= !(1 << number);
+        self.current_mpu_conf = Some(process.proc_mpu);
+        self.release_shared_memory(address);
+        Ok(())
+    }
+
+    /// Releases the shared memory mapped by a process, when it is killed
+    pub(super) fn unmap_shared_memories(&mut self, process: *mut Process) {
+        let process = unsafe { &mut *process };
+        for number in FIRST_MAPPING_REGION..mpu::REGION_COUNT {
+            if process.shared_regions & (1 << number) == 0 {
+                continue;
+            }
+            if let Some(address) = process.proc_mpu.region_base(number) {
+                process.proc_mpu.clear_region(number);
+                self.release_shared_memory(address);
+            }
+        }
+        process.shared_regions = 0;
+    }
+
+    /// Get the slot of the shared memory object `name`
+    fn find_shared_memory(&self, name: &[u8]) -> Option<usize> {
+        self.shared_memories.iter().position(|shm| shm.as_ref().is_some_and(|shm| shm.name() == name))
+    }
+
+    /// Maps a shared memory object in a free MPU region of the running process
+    fn shm_map_slot(&mut self, slot: usize, access: ShmAccess) -> Result<u32, MapError> {
+        let process = unsafe { &mut *self.running_user_process().ok_or(MapError::NoProcess)? };
+        let shm = self.shared_memories[slot].as_mut().ok_or(MapError::NotFound)?;
+
+        let number = process.proc_mpu.find_free_region(FIRST_MAPPING_REGION).ok_or(MapError::NoFreeRegion)?;
+        let region = RegionBuilder::new(number, shm.memory as u32, shm.size).access(access.permission());
+        process.proc_mpu.configure(region).map_err(|_| MapError::InvalidAddress)?;
+        process.shared_regions |= 1 << number;
+        shm.mappings += 1;
+        self.current_mpu_conf = Some(process.proc_mpu);
+        Ok(shm.memory as u32)
+    }
+
+    /// Removes a mapping of the shared memory object at `address`, and frees it if it was the last one
+    fn release_shared_memory(&mut self, address: u32) {
+        let Some(slot) = self.shared_memories.iter().position(|shm| shm.as_ref().is_some_and(|shm| shm.memory as u32 == address)) else {
+            return;
+        };
+        if let Some(shm) = self.shared_memories[slot].as_mut() {
+            shm.mappings -= 1;
+            if shm.mappings == 0 {
+                log_debug!("> Shared memory {} freed", core::str::from_utf8(shm.name()).unwrap_or("?"));
+                self.shared_memories[slot] = None;
+            }
+        }
+    }
+}
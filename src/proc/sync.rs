@@ -0,0 +1,285 @@
+//! Mutexes and counting semaphores shared between processes
+//!
+//! Both objects are referenced by a handle, returned when they are created. A process which cannot take
+//! the object either fails with `IpcError::WouldBlock` (`Timeout::NoWait`) or waits in the wait queue of
+//! the object, highest priority first.
+//!
+//! A mutex is held by one process at a time, and only this process can release it. To bound priority
+//! inversion, the holder of a mutex inherits the priority of the highest priority process waiting for it :
+//!
+//! ```
+//! L (5) holds M, H (0) waits for M  =>  L runs at priority 0 until it releases M
+//!
+//! H (0)  ----lock(M)--[ waits ]----------------------[ runs ]
+//! L (5)  [holds M]----[ runs at 0 ]--unlock(M)--[ back to 5 ]
+//! M' (3) ------------------[ preempted by L ]---------------
+//! ```
+//!
+//! Inheritance is transitive : if the holder is itself waiting for another mutex, the holder of that mutex
+//! is boosted too. The mutexes held by a process are released when it is killed, and handed over to the
+//! next waiting process.
+//!
+//! The objects created by a process are destroyed when it is killed : the processes waiting on them are
+//! woken up with `IpcError::Removed`.
+
+use core::ptr;
+
+use crate::log_debug;
+use super::{Process, ProcStatus, SystemProcess, WaitObject};
+use super::wait_queue::{IpcError, Timeout, WaitQueue};
+
+/// Maximum number of mutexes in the system
+pub const MAX_MUTEXES: usize = 8;
+/// Maximum number of semaphores in the system
+pub const MAX_SEMAPHORES: usize = 8;
+/// Maximum count of a semaphore
+pub const MAX_SEMAPHORE_COUNT: u32 = 0xffff;
+
+/// A mutex, held by at most one process
+pub struct KernelMutex {
+    pub id: u32,
+    /// PID of the process which created the mutex
+    pub owner: u16,
+    /// Process holding the mutex, null if it is free
+    pub holder: *mut Process,
+    pub waiters: WaitQueue
+}
+
+/// A counting semaphore
+pub struct Semaphore {
+    pub id: u32,
+    /// PID of the process which created the semaphore
+    pub owner: u16,
+    count: u32,
+    max_count: u32,
+    pub waiters: WaitQueue
+}
+
+impl SystemProcess {
+    /// Creates a free mutex, returns its handle
+    pub fn mutex_create(&mut self) -> Result<u32, IpcError> {
+        let process = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let slot = self.mutexes.iter().position(|mutex| mutex.is_none()).ok_or(IpcError::NoFreeSlot)?;
+
+        let owner = unsafe { (*process).proc_id };
+        let id = self.new_object_id();
+        self.mutexes[slot] = Some(KernelMutex { id, owner, holder: ptr::null_mut(), waiters: WaitQueue::new() });
+        log_debug!("> PID {} : mutex {} created", owner, id);
+        Ok(id)
+    }
+
+    /// Takes a mutex for the running process
+    ///
+    /// Returns `Ok(Some(0))` once the mutex is taken, or `Ok(None)` if the process waits for it : the holder
+    /// inherits the priority of the process, and the result is delivered to the process when it resumes.
+    pub fn mutex_lock(&mut self, id: u32, timeout: Timeout) -> Result<Option<u32>, IpcError> {
+        let process = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let mutex = unsafe { &mut *self.find_mutex(id)? };
+
+        if mutex.holder.is_null() {
+            mutex.holder = process;
+            return Ok(Some(0));
+        }
+        if ptr::eq(mutex.holder, process) {
+            return Err(IpcError::Deadlock);
+        }
+        if timeout == Timeout::NoWait {
+            return Err(IpcError::WouldBlock);
+        }
+
+        mutex.waiters.push(process);
+        self.block_current_process(WaitObject::Mutex { mutex: id }, timeout);
+        self.update_priority(mutex.holder);
+        Ok(None)
+    }
+
+    /// Releases a mutex held by the running process, and hands it over to the next waiting process
+    ///
+    /// The running process gets back the priority it had before inheriting the priority of the waiting processes.
+    pub fn mutex_unlock(&mut self, id: u32) -> Result<u32, IpcError> {
+        let process = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let mutex = self.find_mutex(id)?;
+        if !ptr::eq(unsafe { (*mutex).holder }, process) {
+            return Err(IpcError::NotOwner);
+        }
+
+        self.release_mutex(mutex);
+        self.update_priority(process);
+        Ok(0)
+    }
+
+    /// Creates a semaphore whose count starts at `initial_count`, and cannot exceed `max_count`
+    pub fn sem_create(&mut self, initial_count: u32, max_count: u32) -> Result<u32, IpcError> {
+        let process = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        if !(1..=MAX_SEMAPHORE_COUNT).contains(&max_count) || initial_count > max_count {
+            return Err(IpcError::InvalidArgument);
+        }
+        let slot = self.semaphores.iter().position(|semaphore| semaphore.is_none()).ok_or(IpcError::NoFreeSlot)?;
+
+        let owner = unsafe { (*process).proc_id };
+        let id = self.new_object_id();
+        self.semaphores[slot] = Some(Semaphore { id, owner, count: initial_count, max_count, waiters: WaitQueue::new() });
+        log_debug!("> PID {} : semaphore {} created ({}/{})", owner, id, initial_count, max_count);
+        Ok(id)
+    }
+
+    /// Decrements a semaphore
+    ///
+    /// Returns `Ok(Some(0))` once the semaphore is decremented, or `Ok(None)` if the process waits for a
+    /// `sem_post` : the result is then delivered to the process when it resumes.
+    pub fn sem_wait(&mut self, id: u32, timeout: Timeout) -> Result<Option<u32>, IpcError> {
+        let process = self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let semaphore = unsafe { &mut *self.find_semaphore(id)? };
+
+        if semaphore.count > 0 {
+            semaphore.count -= 1;
+            return Ok(Some(0));
+        }
+        if timeout == Timeout::NoWait {
+            return Err(IpcError::WouldBlock);
+        }
+
+        semaphore.waiters.push(process);
+        self.block_current_process(WaitObject::Semaphore { semaphore: id }, timeout);
+        Ok(None)
+    }
+
+    /// Increments a semaphore, or wakes up the highest priority process waiting on it
+    pub fn sem_post(&mut self, id: u32) -> Result<u32, IpcError> {
+        self.running_user_process().ok_or(IpcError::NoProcess)?;
+        let semaphore = unsafe { &mut *self.find_semaphore(id)? };
+
+        if let Some(process) = semaphore.waiters.pop() {
+            self.wake_process(process, Ok(0));
+        } else if semaphore.count == semaphore.max_count {
+            return Err(IpcError::Overflow);
+        } else {
+            semaphore.count += 1;
+        }
+        Ok(0)
+    }
+
+    /// Get the mutex of a handle
+    ///
+    /// A pointer is returned, as the process lists are updated while the mutex is in use.
+    pub(super) fn find_mutex(&mut self, id: u32) -> Result<*mut KernelMutex, IpcError> {
+        self.mutexes.iter_mut().flatten()
+            .find(|mutex| mutex.id == id)
+            .map(|mutex| mutex as *mut KernelMutex)
+            .ok_or(IpcError::InvalidHandle)
+    }
+
+    /// Get the semaphore of a handle
+    pub(super) fn find_semaphore(&mut self, id: u32) -> Result<*mut Semaphore, IpcError> {
+        self.semaphores.iter_mut().flatten()
+            .find(|semaphore| semaphore.id == id)
+            .map(|semaphore| semaphore as *mut Semaphore)
+            .ok_or(IpcError::InvalidHandle)
+    }
+
+    /// Hands a mutex over to the highest priority waiting process, or frees it
+    fn release_mutex(&mut self, mutex: *mut KernelMutex) {
+        let mutex = unsafe { &mut *mutex };
+        match mutex.waiters.pop() {
+            Some(next_holder) => {
+                mutex.holder = next_holder;
+                self.wake_process(next_holder, Ok(0));
+                // The new holder inherits the priority of the processes still waiting
+                self.update_priority(next_holder);
+            }
+            None => mutex.holder = ptr::null_mut()
+        }
+    }
+
+    /// Get the priority a process runs at : its own priority, or the priority of the highest priority process
+    /// waiting for a mutex it holds
+    fn inherited_priority(&self, process: *mut Process) -> u8 {
+        self.mutexes.iter().flatten()
+            .filter(|mutex| ptr::eq(mutex.holder, process))
+            .filter_map(|mutex| mutex.waiters.highest_priority())
+            .fold(unsafe { (*process).base_priority }, u8::min)
+    }
+
+    /// Update the priority of a process after the processes waiting for its mutexes changed
+    ///
+    /// A ready process moves to the run queue of its new priority, and a waiting process to its new place in
+    /// the wait queue. If it is waiting for a mutex, the priority of the holder is updated in turn.
+    pub(super) fn update_priority(&mut self, process: *mut Process) {
+        let priority = self.inherited_priority(process);
+        let process_ref = unsafe { &mut *process };
+        if process_ref.priority == priority {
+            return;
+        }
+        log_debug!("> PID {} : priority {} -> {}", process_ref.proc_id, process_ref.priority, priority);
+
+        match process_ref.status {
+            ProcStatus::Idle if !ptr::eq(process, self.idle_process) => {
+                self.ready_queue.remove(process);
+                process_ref.priority = priority;
+                self.make_ready(process);
+            }
+            ProcStatus::Waiting => {
+                let object = process_ref.wait_object;
+                let wait_queue = object.and_then(|object| self.wait_queue_of(object));
+                if let Some(wait_queue) = wait_queue {
+                    unsafe { (*wait_queue).remove(process) };
+                }
+                process_ref.priority = priority;
+                if let Some(wait_queue) = wait_queue {
+                    unsafe { (*wait_queue).push(process) };
+                }
+                if let Some(WaitObject::Mutex { mutex }) = object
+                    && let Ok(mutex) = self.find_mutex(mutex) {
+                    self.update_priority(unsafe { (*mutex).holder });
+                }
+            }
+            _ => {
+                process_ref.priority = priority;
+                if ptr::eq(process, self.current_process)
+                    && self.ready_queue.highest_priority().is_some_and(|highest| highest < priority) {
+                    self.need_resched = true;
+                }
+            }
+        }
+    }
+
+    /// Releases the mutexes held by a process, and destroys the objects created by it
+    pub(super) fn release_sync_objects(&mut self, process: *mut Process) {
+        let pid = unsafe { (*process).proc_id };
+        for slot in 0..MAX_MUTEXES {
+            if let Some(mutex) = self.mutexes[slot].as_mut()
+                && ptr::eq(mutex.holder, process) {
+                let mutex = mutex as *mut KernelMutex;
+                self.release_mutex(mutex);
+            }
+        }
+
+        for slot in 0..MAX_MUTEXES {
+            if !self.mutexes[slot].as_ref().is_some_and(|mutex| mutex.owner == pid) {
+                continue;
+            }
+            if let Some(mut mutex) = self.mutexes[slot].take() {
+                while let Some(waiting) = mutex.waiters.pop() {
+                    self.wake_process(waiting, Err(IpcError::Removed));
+                }
+                // The holder loses the priority inherited through this mutex
+                if !mutex.holder.is_null() {
+                    self.update_priority(mutex.holder);
+                }
+                log_debug!("> PID {} : mutex {} destroyed", pid, mutex.id);
+            }
+        }
+
+        for slot in 0..MAX_SEMAPHORES {
+            if !self.semaphores[slot].as_ref().is_some_and(|semaphore| semaphore.owner == pid) {
+                continue;
+            }
+            if let Some(mut semaphore) = self.semaphores[slot].take() {
+                while let Some(waiting) = semaphore.waiters.pop() {
+                    self.wake_process(waiting, Err(IpcError::Removed));
+                }
+                log_debug!("> PID {} : semaphore {} destroyed", pid, semaphore.id);
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::memory_management::pool::PoolAllocator;
+use crate::utils::LinkedList;
+use super::Process;
+
+/// Number of slots of the timer wheel
+const TIMER_WHEEL_SLOTS: usize = 32;
+
+/// Processes waiting for a deadline, hashed by deadline tick
+///
+/// A process waking up at tick `t` is stored in slot `t % TIMER_WHEEL_SLOTS`. On each SysTick, only the
+/// slot of the current tick is inspected : processes whose deadline is reached are returned, the others
+/// (deadline in a later round of the wheel) stay in the slot.
+///
+/// ```
+///        now
+///         v
+/// [0] [1] [2] [3] ... [31]
+///      |   |
+///      P1  P3 -> P2
+/// ```
+pub struct TimerWheel {
+    slots: [LinkedList<*mut Process, PoolAllocator>; TIMER_WHEEL_SLOTS]
+}
+
+impl TimerWheel {
+    pub fn new() -> TimerWheel {
+        TimerWheel {
+            slots: core::array::from_fn(|_| LinkedList::new_in(PoolAllocator))
+        }
+    }
+
+    /// Arm a timer for a process, its deadline being `process.wake_tick`
+    pub fn insert(&mut self, process: *mut Process) {
+        let wake_tick = unsafe { (*process).wake_tick };
+        self.slots[Self::slot(wake_tick)].add(process);
+    }
+
+    /// Cancel the timer of a process
+    pub fn remove(&mut self, process: *mut Process) -> bool {
+        let wake_tick = unsafe { (*process).wake_tick };
+        self.slots[Self::slot(wake_tick)].delete(process)
+    }
+
+    /// Remove and return a process whose deadline is reached at tick `now`
+    pub fn pop_expired(&mut self, now: u64) -> Option<*mut Process> {
+        let slot = &mut self.slots[Self::slot(now)];
+        let expired = slot.iter().find(|process| unsafe { (**process).wake_tick <= now })?;
+
+        slot.delete(expired);
+        Some(expired)
+    }
+
+    fn slot(tick: u64) -> usize {
+        (tick % TIMER_WHEEL_SLOTS as u64) as usize
+    }
+}
@@ -0,0 +1,121 @@
+use crate::memory_management::pool::PoolAllocator;
+use crate::syscall::errno;
+use crate::utils::LinkedList;
+use super::Process;
+
+/// Error of an IPC operation
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum IpcError {
+    /// No process running, or the idle process
+    NoProcess,
+    /// No process with this PID
+    NoSuchProcess,
+    /// No object with this handle
+    InvalidHandle,
+    /// Size or capacity out of bounds
+    InvalidArgument,
+    /// The buffer is not in the memory of the process
+    Fault,
+    /// The operation would block, and no timeout was given
+    WouldBlock,
+    /// The timeout expired before the operation could complete
+    TimedOut,
+    /// The object was destroyed while the process was waiting on it
+    Removed,
+    /// Not enough heap memory, or the process would exceed its memory quota
+    OutOfMemory,
+    /// All the objects of this type are in use
+    NoFreeSlot,
+    /// The mutex is not held by the process
+    NotOwner,
+    /// The process already holds the mutex
+    Deadlock,
+    /// The semaphore is at its maximum count
+    Overflow,
+    /// The process may not act on this process
+    NotPermitted,
+    /// No program with this ID or name
+    NotFound,
+    /// The program image is malformed or unsupported
+    InvalidImage,
+    /// The process is not a child of the running process
+    NotChild
+}
+
+impl IpcError {
+    /// Error number returned by the syscalls
+    pub fn errno(self) -> i32 {
+        match self {
+            IpcError::NoProcess => errno::EPERM,
+            IpcError::NoSuchProcess => errno::ESRCH,
+            IpcError::InvalidHandle => errno::EBADF,
+            IpcError::InvalidArgument => errno::EINVAL,
+            IpcError::Fault => errno::EFAULT,
+            IpcError::WouldBlock => errno::EAGAIN,
+            IpcError::TimedOut => errno::ETIMEDOUT,
+            IpcError::Removed => errno::EIDRM,
+            IpcError::OutOfMemory => errno::ENOMEM,
+            IpcError::NoFreeSlot => errno::ENOSPC,
+            IpcError::NotOwner => errno::EPERM,
+            IpcError::Deadlock => errno::EDEADLK,
+            IpcError::Overflow => errno::EOVERFLOW,
+            IpcError::NotPermitted => errno::EPERM,
+            IpcError::NotFound => errno::ENOENT,
+            IpcError::InvalidImage => errno::ENOEXEC,
+            IpcError::NotChild => errno::ECHILD
+        }
+    }
+}
+
+/// How long a blocking operation may wait
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum Timeout {
+    /// Fail with `IpcError::WouldBlock` instead of waiting
+    NoWait,
+    /// Fail with `IpcError::TimedOut` after this number of SysTick periods
+    Ticks(u64),
+    /// Wait until the operation completes
+    Forever
+}
+
+/// Processes blocked on a kernel object (message queue, mutex, semaphore), in the order they are woken up
+///
+/// The processes are sorted by priority, and in FIFO order within a priority level. They are in
+/// `ProcStatus::Waiting`, out of the run queues. A process leaves the wait queue when the object wakes
+/// it up, when its timeout expires, or when it is killed.
+///
+/// ```
+/// [P4 (0)] -> [P2 (3)] -> [P5 (3)] -> [P1 (6)]
+/// ```
+pub struct WaitQueue {
+    processes: LinkedList<*mut Process, PoolAllocator>
+}
+
+impl WaitQueue {
+    pub fn new() -> WaitQueue {
+        WaitQueue {
+            processes: LinkedList::new_in(PoolAllocator)
+        }
+    }
+
+    /// Add a process after the processes of the same or a higher priority
+    pub fn push(&mut self, process: *mut Process) {
+        let priority = unsafe { (*process).priority };
+        self.processes.insert_before(process, |waiting| unsafe { (**waiting).priority } > priority);
+    }
+
+    /// Remove and return the next process to wake up
+    pub fn pop(&mut self) -> Option<*mut Process> {
+        self.processes.pop_front()
+    }
+
+    /// Remove a specific process from the wait queue
+    pub fn remove(&mut self, process: *mut Process) -> bool {
+        self.processes.delete(process)
+    }
+
+    /// Get the priority of the next process to wake up
+    pub fn highest_priority(&self) -> Option<u8> {
+        self.processes.first().map(|process| unsafe { (**process).priority })
+    }
+}
@@ -0,0 +1,403 @@
+//! Syscall dispatcher
+//!
+//! `SVCallHandler` hands the exception frame stacked by the caller to `dispatch`, which reads the
+//! syscall number and arguments from it, calls the handler registered in `SYSCALL_TABLE`, and writes
+//! the result back into the stacked R0 :
+//! - `Ok(value)` : R0 <- value
+//! - `Err(errno)` : R0 <- -errno
+//!
+//! ```
+//! Process stack on SVC
+//! +--------+ < PSP
+//! | R0     | SYSCALL_ID -> Return value
+//! +--------+
+//! | R1     | ARG0
+//! +--------+
+//! | R2     | ARG1
+//! +--------+
+//! | R3     | ARG2
+//! +--------+
+//! | ...    |
+//! ```
+
+pub mod user;
+
+use cortex_m::interrupt;
+
+use crate::{log_debug, log_info};
+use crate::init::{systick, trigger_pendsv, ExceptionFrame};
+use crate::proc::{EventWait, ExitStatus, IpcError, MapError, ShmAccess, Timeout, PROGRAM_NAME_LEN, SHM_NAME_LEN};
+use crate::SYSTEM_PROCESS;
+
+/// Error numbers returned (negated) by syscalls
+#[allow(dead_code)]
+pub mod errno {
+    pub const EPERM: i32 = 1;
+    pub const ENOENT: i32 = 2;
+    pub const ESRCH: i32 = 3;
+    pub const EIO: i32 = 5;
+    pub const ENOEXEC: i32 = 8;
+    pub const EBADF: i32 = 9;
+    pub const ECHILD: i32 = 10;
+    pub const EAGAIN: i32 = 11;
+    pub const ENOMEM: i32 = 12;
+    pub const EFAULT: i32 = 14;
+    pub const EBUSY: i32 = 16;
+    pub const EEXIST: i32 = 17;
+    pub const EINVAL: i32 = 22;
+    pub const ENFILE: i32 = 23;
+    pub const ENOSPC: i32 = 28;
+    pub const EDEADLK: i32 = 35;
+    pub const ENOSYS: i32 = 38;
+    pub const EIDRM: i32 = 43;
+    pub const EOVERFLOW: i32 = 75;
+    pub const ETIMEDOUT: i32 = 110;
+}
+
+/// Result of a syscall handler : the value returned to the process, or an error number
+pub type SyscallResult = Result<u32, i32>;
+
+/// A syscall handler, called with ARG0, ARG1 and ARG2
+type SyscallHandler = fn(u32, u32, u32) -> SyscallResult;
+
+/// Syscall handlers, indexed by syscall number
+static SYSCALL_TABLE: [SyscallHandler; 26] = [
+    sys_exit,           // SYS_EXIT
+    sys_print,          // SYS_PRINT
+    sys_sleep,          // SYS_SLEEP
+    sys_write,          // SYS_WRITE
+    sys_heap_verify,    // SYS_HEAP_VERIFY
+    sys_mmap,           // SYS_MMAP
+    sys_munmap,         // SYS_MUNMAP
+    sys_mq_create,      // SYS_MQ_CREATE
+    sys_mq_send,        // SYS_MQ_SEND
+    sys_mq_recv,        // SYS_MQ_RECV
+    sys_mutex_create,   // SYS_MUTEX_CREATE
+    sys_mutex_lock,     // SYS_MUTEX_LOCK
+    sys_mutex_unlock,   // SYS_MUTEX_UNLOCK
+    sys_sem_create,     // SYS_SEM_CREATE
+    sys_sem_wait,       // SYS_SEM_WAIT
+    sys_sem_post,       // SYS_SEM_POST
+    sys_wait_events,    // SYS_WAIT_EVENTS
+    sys_notify,         // SYS_NOTIFY
+    sys_shm_create,     // SYS_SHM_CREATE
+    sys_shm_map,        // SYS_SHM_MAP
+    sys_shm_unmap,      // SYS_SHM_UNMAP
+    sys_getpid,         // SYS_GETPID
+    sys_yield,          // SYS_YIELD
+    sys_spawn,          // SYS_SPAWN
+    sys_wait,           // SYS_WAIT
+    sys_kill,           // SYS_KILL
+];
+
+/// Size of the kernel buffer used to copy the data of SYS_WRITE
+const WRITE_CHUNK_SIZE: usize = 64;
+
+/// Timeout argument of a blocking syscall waiting forever
+const WAIT_FOREVER: u32 = u32::MAX;
+
+/// Calls the handler of the syscall described by an exception frame, and writes its result in the frame
+///
+/// Once the syscall is handled, a context switch is triggered if the scheduler has to be called
+/// (the process exited, is waiting, or a higher priority process became ready).
+///
+/// # Syscalls
+/// - `0`: SYS_EXIT - Terminates the current process with exit code ARG0.
+/// - `1`: SYS_PRINT - Prints ARG0 in hex
+/// - `2`: SYS_SLEEP - Puts the current process in Waiting state for ARG0 milliseconds
+/// - `3`: SYS_WRITE - Prints the ARG1 bytes at address ARG0 on the console, returns the number of bytes written
+/// - `4`: SYS_HEAP_VERIFY - Checks the integrity of the kernel heap, returns the number of heap blocks, or `EIO` if it is corrupted
+/// - `5`: SYS_MMAP - Maps ARG0 bytes (rounded up to a power of two) of zeroed memory in the process, returns its address
+/// - `6`: SYS_MUNMAP - Unmaps the memory mapped by SYS_MMAP at address ARG0
+/// - `7`: SYS_MQ_CREATE - Creates a queue of ARG1 messages of ARG0 bytes, returns its handle
+/// - `8`: SYS_MQ_SEND - Sends the message at address ARG1 to queue ARG0, waiting up to ARG2 milliseconds for a free slot
+/// - `9`: SYS_MQ_RECV - Receives a message of queue ARG0 at address ARG1, waiting up to ARG2 milliseconds, returns its size
+/// - `10`: SYS_MUTEX_CREATE - Creates a mutex, returns its handle
+/// - `11`: SYS_MUTEX_LOCK - Takes mutex ARG0, waiting up to ARG1 milliseconds
+/// - `12`: SYS_MUTEX_UNLOCK - Releases mutex ARG0
+/// - `13`: SYS_SEM_CREATE - Creates a semaphore of count ARG0 and maximum count ARG1, returns its handle
+/// - `14`: SYS_SEM_WAIT - Decrements semaphore ARG0, waiting up to ARG1 milliseconds
+/// - `15`: SYS_SEM_POST - Increments semaphore ARG0
+/// - `16`: SYS_WAIT_EVENTS - Waits up to ARG2 milliseconds for any (ARG1 = 0) or all (ARG1 = 1) of the events of mask ARG0, returns the events received
+/// - `17`: SYS_NOTIFY - Notifies the events ARG1 to process ARG0
+/// - `18`: SYS_SHM_CREATE - Creates the shared memory of ARG2 bytes named by the ARG1 bytes at address ARG0, maps it read-write, returns its address
+/// - `19`: SYS_SHM_MAP - Maps the shared memory named by the ARG1 bytes at address ARG0, read-only (ARG2 = 0) or read-write (ARG2 = 1), returns its address
+/// - `20`: SYS_SHM_UNMAP - Unmaps the shared memory mapped at address ARG0
+/// - `21`: SYS_GETPID - Returns the PID of the current process
+/// - `22`: SYS_YIELD - Gives up the rest of the time slice of the current process
+/// - `23`: SYS_SPAWN - Starts the program named by the ARG1 bytes at address ARG0 (or with ID ARG0 if ARG1 = 0) as a child, returns its PID
+/// - `24`: SYS_WAIT - Waits up to ARG2 milliseconds for child ARG0 to terminate, writes its exit status (kind, value) at address ARG1 unless it is 0, returns its PID
+/// - `25`: SYS_KILL - Kills process ARG0, the current process itself or one of its children
+///
+/// A timeout of 0 fails with `EAGAIN` instead of waiting, and `u32::MAX` waits forever.
+pub extern "C" fn dispatch(frame: *mut ExceptionFrame) {
+    log_debug!("\n### SVCAll Handler ###");
+
+    let frame = unsafe { &mut *frame };
+
+    let result = match SYSCALL_TABLE.get(frame.r0 as usize) {
+        Some(handler) => handler(frame.r1, frame.r2, frame.r3),
+        None => {
+            log_debug!("Unknown syscall : {}", frame.r0);
+            Err(errno::ENOSYS)
+        }
+    };
+
+    frame.r0 = match result {
+        Ok(value) => value,
+        Err(errno) => (-errno) as u32
+    };
+
+    let need_resched = interrupt::free(|_cs| SYSTEM_PROCESS.lock().need_resched());
+    if need_resched {
+        trigger_pendsv();
+    }
+}
+
+fn sys_exit(exit_code: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_EXIT] Return code {:#x}",exit_code);
+    interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock();
+        system_process.exit_current_process(ExitStatus::Exited(exit_code));
+    });
+    Ok(0)
+}
+
+fn sys_print(value: u32, _: u32, _: u32) -> SyscallResult {
+    log_info!("[SYS_PRINT] {:#x}",value);
+    Ok(0)
+}
+
+fn sys_sleep(ms: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_SLEEP] {} ms",ms);
+    interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock();
+        system_process.sleep_current_process(systick::ms_to_ticks(ms));
+    });
+    Ok(0)
+}
+
+fn sys_write(buffer: u32, len: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_WRITE] {} bytes at {:#x}",len,buffer);
+    let mut chunk = [0u8; WRITE_CHUNK_SIZE];
+    let mut written = 0;
+    while written < len {
+        let chunk_len = (len - written).min(WRITE_CHUNK_SIZE as u32) as usize;
+        let chunk = &mut chunk[..chunk_len];
+        user::copy_from_user(chunk, buffer.wrapping_add(written))?;
+
+        // Non-ASCII bytes are replaced, so that the chunk is always valid UTF-8
+        for byte in chunk.iter_mut().filter(|byte| !byte.is_ascii()) {
+            *byte = b'?';
+        }
+        cortex_m_semihosting::hprint!("{}", core::str::from_utf8(chunk).unwrap_or_default()).ok();
+        written += chunk_len as u32;
+    }
+    Ok(written)
+}
+
+fn sys_heap_verify(_: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_HEAP_VERIFY]");
+    let report = interrupt::free(|_cs| SYSTEM_PROCESS.lock().verify_heap()).map_err(|_| errno::EIO)?;
+    Ok((report.allocated_blocks + report.free_blocks) as u32)
+}
+
+/// Error number of a failed SYS_MMAP, SYS_MUNMAP or shared memory syscall
+fn map_errno(error: MapError) -> i32 {
+    match error {
+        MapError::NoProcess => errno::EPERM,
+        MapError::InvalidLength | MapError::InvalidAddress | MapError::InvalidName => errno::EINVAL,
+        MapError::NoFreeRegion => errno::ENOSPC,
+        MapError::OutOfMemory => errno::ENOMEM,
+        MapError::NotFound => errno::ENOENT,
+        MapError::AlreadyExists => errno::EEXIST,
+        MapError::NoFreeSlot => errno::ENFILE
+    }
+}
+
+fn sys_mmap(len: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_MMAP] {} bytes",len);
+    interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock();
+        let address = system_process.map_memory(len as usize).map_err(map_errno)?;
+        // The process uses the memory as soon as the syscall returns
+        system_process.enable_current_mpu();
+        Ok(address)
+    })
+}
+
+fn sys_munmap(address: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_MUNMAP] {:#x}",address);
+    interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock();
+        system_process.unmap_memory(address).map_err(map_errno)?;
+        system_process.enable_current_mpu();
+        Ok(0)
+    })
+}
+
+/// Timeout of a blocking syscall, from its argument in milliseconds
+fn timeout_from_ms(ms: u32) -> Timeout {
+    match ms {
+        0 => Timeout::NoWait,
+        WAIT_FOREVER => Timeout::Forever,
+        ms => Timeout::Ticks(systick::ms_to_ticks(ms))
+    }
+}
+
+/// Value returned by a syscall which may block : when the process waits, R0 is written once it resumes
+fn blocking_result(result: Result<Option<u32>, IpcError>) -> SyscallResult {
+    result.map(|value| value.unwrap_or(0)).map_err(IpcError::errno)
+}
+
+fn sys_mq_create(message_size: u32, capacity: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_MQ_CREATE] {} x {} bytes",capacity,message_size);
+    interrupt::free(|_cs| {
+        SYSTEM_PROCESS.lock().mq_create(message_size as usize, capacity as usize).map_err(IpcError::errno)
+    })
+}
+
+fn sys_mq_send(handle: u32, buffer: u32, timeout_ms: u32) -> SyscallResult {
+    log_debug!("[SYS_MQ_SEND] queue {} from {:#x}",handle,buffer);
+    let timeout = timeout_from_ms(timeout_ms);
+    interrupt::free(|_cs| blocking_result(SYSTEM_PROCESS.lock().mq_send(handle, buffer, timeout)))
+}
+
+fn sys_mq_recv(handle: u32, buffer: u32, timeout_ms: u32) -> SyscallResult {
+    log_debug!("[SYS_MQ_RECV] queue {} to {:#x}",handle,buffer);
+    let timeout = timeout_from_ms(timeout_ms);
+    interrupt::free(|_cs| blocking_result(SYSTEM_PROCESS.lock().mq_receive(handle, buffer, timeout)))
+}
+
+fn sys_mutex_create(_: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_MUTEX_CREATE]");
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().mutex_create().map_err(IpcError::errno))
+}
+
+fn sys_mutex_lock(handle: u32, timeout_ms: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_MUTEX_LOCK] mutex {}",handle);
+    let timeout = timeout_from_ms(timeout_ms);
+    interrupt::free(|_cs| blocking_result(SYSTEM_PROCESS.lock().mutex_lock(handle, timeout)))
+}
+
+fn sys_mutex_unlock(handle: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_MUTEX_UNLOCK] mutex {}",handle);
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().mutex_unlock(handle).map_err(IpcError::errno))
+}
+
+fn sys_sem_create(initial_count: u32, max_count: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_SEM_CREATE] {}/{}",initial_count,max_count);
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().sem_create(initial_count, max_count).map_err(IpcError::errno))
+}
+
+fn sys_sem_wait(handle: u32, timeout_ms: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_SEM_WAIT] semaphore {}",handle);
+    let timeout = timeout_from_ms(timeout_ms);
+    interrupt::free(|_cs| blocking_result(SYSTEM_PROCESS.lock().sem_wait(handle, timeout)))
+}
+
+fn sys_sem_post(handle: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_SEM_POST] semaphore {}",handle);
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().sem_post(handle).map_err(IpcError::errno))
+}
+
+fn sys_wait_events(mask: u32, all: u32, timeout_ms: u32) -> SyscallResult {
+    log_debug!("[SYS_WAIT_EVENTS] mask {:#x}",mask);
+    let wait = match all {
+        0 => EventWait::Any,
+        1 => EventWait::All,
+        _ => return Err(errno::EINVAL)
+    };
+    let timeout = timeout_from_ms(timeout_ms);
+    interrupt::free(|_cs| blocking_result(SYSTEM_PROCESS.lock().wait_events(mask, wait, timeout)))
+}
+
+fn sys_notify(pid: u32, events: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_NOTIFY] PID {} : {:#x}",pid,events);
+    let pid = u16::try_from(pid).map_err(|_| errno::ESRCH)?;
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().notify(pid, events).map(|_| 0).map_err(IpcError::errno))
+}
+
+/// Copies the name of a shared memory from the running process
+fn copy_shm_name(name: &mut [u8; SHM_NAME_LEN], address: u32, len: u32) -> Result<&[u8], i32> {
+    let name = name.get_mut(..len as usize).filter(|name| !name.is_empty()).ok_or(errno::EINVAL)?;
+    user::copy_from_user(name, address)?;
+    Ok(name)
+}
+
+fn sys_shm_create(name: u32, name_len: u32, size: u32) -> SyscallResult {
+    let mut buffer = [0u8; SHM_NAME_LEN];
+    let name = copy_shm_name(&mut buffer, name, name_len)?;
+    log_debug!("[SYS_SHM_CREATE] {} bytes",size);
+    interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock();
+        let address = system_process.shm_create(name, size as usize).map_err(map_errno)?;
+        system_process.enable_current_mpu();
+        Ok(address)
+    })
+}
+
+fn sys_shm_map(name: u32, name_len: u32, access: u32) -> SyscallResult {
+    let mut buffer = [0u8; SHM_NAME_LEN];
+    let name = copy_shm_name(&mut buffer, name, name_len)?;
+    let access = match access {
+        0 => ShmAccess::ReadOnly,
+        1 => ShmAccess::ReadWrite,
+        _ => return Err(errno::EINVAL)
+    };
+    log_debug!("[SYS_SHM_MAP] {:?}",access);
+    interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock();
+        let address = system_process.shm_map(name, access).map_err(map_errno)?;
+        system_process.enable_current_mpu();
+        Ok(address)
+    })
+}
+
+fn sys_shm_unmap(address: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_SHM_UNMAP] {:#x}",address);
+    interrupt::free(|_cs| {
+        let mut system_process = SYSTEM_PROCESS.lock();
+        system_process.shm_unmap(address).map_err(map_errno)?;
+        system_process.enable_current_mpu();
+        Ok(0)
+    })
+}
+
+fn sys_getpid(_: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_GETPID]");
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().getpid().map(u32::from).map_err(IpcError::errno))
+}
+
+fn sys_yield(_: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_YIELD]");
+    // The context switch is triggered as soon as the syscall returns, not on the next SysTick
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().yield_current_process());
+    Ok(0)
+}
+
+fn sys_spawn(program: u32, name_len: u32, _: u32) -> SyscallResult {
+    let program_id = if name_len == 0 {
+        program as usize
+    } else {
+        let mut buffer = [0u8; PROGRAM_NAME_LEN];
+        let name = buffer.get_mut(..name_len as usize).ok_or(errno::ENOENT)?;
+        user::copy_from_user(name, program)?;
+        interrupt::free(|_cs| SYSTEM_PROCESS.lock().find_program(name)).ok_or(errno::ENOENT)?
+    };
+    log_debug!("[SYS_SPAWN] program {}",program_id);
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().spawn(program_id).map(u32::from).map_err(IpcError::errno))
+}
+
+fn sys_wait(pid: u32, status: u32, timeout_ms: u32) -> SyscallResult {
+    log_debug!("[SYS_WAIT] PID {}",pid);
+    let pid = u16::try_from(pid).map_err(|_| errno::ECHILD)?;
+    let timeout = timeout_from_ms(timeout_ms);
+    interrupt::free(|_cs| blocking_result(SYSTEM_PROCESS.lock().wait_child(pid, status, timeout)))
+}
+
+fn sys_kill(pid: u32, _: u32, _: u32) -> SyscallResult {
+    log_debug!("[SYS_KILL] PID {}",pid);
+    let pid = u16::try_from(pid).map_err(|_| errno::ESRCH)?;
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().kill_from_process(pid).map_err(IpcError::errno))
+}
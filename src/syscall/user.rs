@@ -0,0 +1,48 @@
+//! Access to the memory of the calling process
+//!
+//! Pointers passed to syscalls are addresses in the process memory, they are checked against the MPU
+//! regions of the process before being dereferenced by the kernel : a range is accepted only if the
+//! process itself could access it unprivileged. Otherwise the syscall fails with `EFAULT`.
+
+use cortex_m::interrupt;
+
+use crate::memory_management::mpu::{Access, Mpu};
+use crate::SYSTEM_PROCESS;
+use super::errno;
+
+/// Copies `dst.len()` bytes from the address `src` of the running process into a kernel buffer
+pub fn copy_from_user(dst: &mut [u8], src: u32) -> Result<(), i32> {
+    copy_from(&current_mpu()?, dst, src)
+}
+
+/// Copies a kernel buffer to the address `dst` of the running process
+#[allow(dead_code)]
+pub fn copy_to_user(dst: u32, src: &[u8]) -> Result<(), i32> {
+    copy_to(&current_mpu()?, dst, src)
+}
+
+/// Copies `dst.len()` bytes from the address `src` of a process with the MPU configuration `mpu`
+pub fn copy_from(mpu: &Mpu, dst: &mut [u8], src: u32) -> Result<(), i32> {
+    if !mpu.check_unprivileged_access(src, dst.len(), Access::Read) {
+        return Err(errno::EFAULT);
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(src as *const u8, dst.as_mut_ptr(), dst.len());
+    }
+    Ok(())
+}
+
+/// Copies a kernel buffer to the address `dst` of a process with the MPU configuration `mpu`
+pub fn copy_to(mpu: &Mpu, dst: u32, src: &[u8]) -> Result<(), i32> {
+    if !mpu.check_unprivileged_access(dst, src.len(), Access::Write) {
+        return Err(errno::EFAULT);
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(src.as_ptr(), dst as *mut u8, src.len());
+    }
+    Ok(())
+}
+
+fn current_mpu() -> Result<Mpu, i32> {
+    interrupt::free(|_cs| SYSTEM_PROCESS.lock().get_current_process_mpu()).ok_or(errno::EFAULT)
+}
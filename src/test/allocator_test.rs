@@ -0,0 +1,97 @@
+use alloc::alloc::{alloc, alloc_zeroed, dealloc, realloc, Layout};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::memory_management::heap;
+
+#[test_case]
+#[inline(never)]
+fn test_alloc_alignment() {
+    let free_before = heap::get_free_heap_size();
+
+    for align in [1, 4, 8, 32, 256, 1024] {
+        let layout = Layout::from_size_align(24, align).unwrap();
+        unsafe {
+            let ptr = alloc(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % align, 0, "Allocation should be aligned on {}", align);
+            ptr.write_bytes(0xa5, layout.size());
+            dealloc(ptr, layout);
+        }
+    }
+
+    // Layouts aligned on more than a word are aligned by the heap, without reserving `align` more bytes
+    let layout = Layout::from_size_align(4096, 4096).unwrap();
+    unsafe {
+        let free = heap::get_free_heap_size();
+        let ptr = alloc(layout);
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % 4096, 0);
+        assert!(free - heap::get_free_heap_size() < 4096 + 64);
+        let grown = realloc(ptr, layout, 4200);
+        assert_eq!(grown as usize % 4096, 0);
+        dealloc(grown, Layout::from_size_align(4200, 4096).unwrap());
+    }
+
+    // Over-aligned types through Box
+    #[repr(align(64))]
+    struct Aligned([u8; 8]);
+    let boxed = Box::new(Aligned([1; 8]));
+    assert_eq!(&*boxed as *const Aligned as usize % 64, 0);
+    assert_eq!(boxed.0, [1; 8]);
+    drop(boxed);
+
+    assert_eq!(heap::get_free_heap_size(), free_before);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_alloc_zeroed() {
+    let layout = Layout::from_size_align(40, 8).unwrap();
+    unsafe {
+        // Dirty the memory first
+        let ptr = alloc(layout);
+        ptr.write_bytes(0xff, layout.size());
+        dealloc(ptr, layout);
+
+        let ptr = alloc_zeroed(layout);
+        assert!(core::slice::from_raw_parts(ptr, layout.size()).iter().all(|byte| *byte == 0));
+        dealloc(ptr, layout);
+    }
+}
+
+#[test_case]
+#[inline(never)]
+fn test_realloc_in_place() {
+    // Large blocks, taken one after the other from the end of the heap
+    let layout = Layout::from_size_align(4096, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        let next = alloc(layout);
+        for i in 0..64 {
+            *ptr.add(i) = i as u8;
+        }
+
+        // The next block is free : the block grows without moving
+        dealloc(next, layout);
+        let grown = realloc(ptr, layout, 8192);
+        assert_eq!(grown, ptr);
+        assert!((0..64).all(|i| *grown.add(i) == i as u8));
+
+        // Shrinking gives the end of the block back
+        let free_before = heap::get_free_heap_size();
+        let shrunk = realloc(grown, Layout::from_size_align(8192, 8).unwrap(), 16);
+        assert_eq!(shrunk, ptr);
+        assert!(heap::get_free_heap_size() > free_before);
+        // Aligned by the heap : the pointer is the user memory of the heap block
+        assert!(heap::check_cookie(shrunk));
+
+        dealloc(shrunk, Layout::from_size_align(16, 8).unwrap());
+    }
+
+    // Vec growth keeps its content
+    let mut values = Vec::new();
+    for i in 0..100u32 {
+        values.push(i);
+    }
+    assert!(values.iter().enumerate().all(|(i, value)| *value == i as u32));
+}
@@ -0,0 +1,39 @@
+use crate::init::crash_dump::{self, KERNEL_PID};
+use crate::init::{CalleeSavedRegisters, ExceptionFrame};
+use crate::proc::FaultKind;
+
+/// EXC_RETURN of a fault in Handler mode, on the Main Stack, without FPU context
+const EXC_RETURN_HANDLER: u32 = 0xFFFF_FFF1;
+
+#[test_case]
+#[inline(never)]
+fn test_crash_report_stored() {
+    // Fake faulting stack : exception frame followed by some words
+    let mut stack = [0u32; 12];
+    for (i, word) in stack.iter_mut().enumerate() {
+        *word = 0x1000 + i as u32;
+    }
+    let frame = stack.as_ptr() as *const ExceptionFrame;
+    let callee_saved = CalleeSavedRegisters { r4: 4, r5: 5, r6: 6, r7: 7, r8: 8, r9: 9, r10: 10, r11: 11 };
+
+    let report = crash_dump::record(FaultKind::UsageFault, EXC_RETURN_HANDLER, frame, &callee_saved, false);
+    assert_eq!(report.kind(), FaultKind::UsageFault);
+    assert_eq!(report.proc_id, KERNEL_PID);
+    assert_eq!(report.proc_name(), "kernel");
+    assert_eq!(report.callee_saved.r11, 11);
+    assert!(report.is_frame_valid());
+    assert_eq!(report.frame.pc, 0x1006);
+
+    // Stack dump starts right after the exception frame
+    assert_eq!(report.stack_address, &stack[8] as *const u32 as u32);
+    assert_eq!(report.stack[..4], stack[8..]);
+
+    // The report is kept in .noinit
+    let stored = crash_dump::last_crash().expect("Crash report should be stored");
+    assert_eq!(stored.frame.pc, report.frame.pc);
+    assert_eq!(stored.callee_saved.r4, 4);
+
+    // Forgotten once cleared
+    crash_dump::clear();
+    assert!(crash_dump::last_crash().is_none());
+}
@@ -0,0 +1,122 @@
+use alloc::vec::Vec;
+use crate::memory_management::heap;
+use crate::proc::elf::{self, ElfError, PF_R, PF_W, PF_X};
+use crate::log_debug;
+
+const CODE_OFFSET: u32 = 116;
+const DATA_OFFSET: u32 = 120;
+const REL_OFFSET: u32 = 124;
+const DYNAMIC_OFFSET: u32 = 132;
+const IMAGE_SIZE: u32 = 156;
+const BSS_SIZE: u32 = 8;
+
+fn push_u16(image: &mut Vec<u8>, value: u16) {
+    image.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(image: &mut Vec<u8>, value: u32) {
+    image.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_program_header(image: &mut Vec<u8>, p_type: u32, offset: u32, filesz: u32, memsz: u32, flags: u32) {
+    for value in [p_type, offset, offset, offset, filesz, memsz, flags, 4] {
+        push_u32(image, value);
+    }
+}
+
+/// Build a minimal PIC image : one RWX PT_LOAD segment holding `B .` followed by a pointer to
+/// this code, relocated with R_ARM_RELATIVE, and a bss of `BSS_SIZE` bytes
+fn build_image(relocation_type: u32) -> Vec<u8> {
+    let mut image = Vec::new();
+
+    // ELF header
+    image.extend_from_slice(b"\x7fELF\x01\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+    push_u16(&mut image, 3);                    // e_type : ET_DYN
+    push_u16(&mut image, 40);                   // e_machine : EM_ARM
+    push_u32(&mut image, 1);                    // e_version
+    push_u32(&mut image, CODE_OFFSET | 1);      // e_entry (Thumb)
+    push_u32(&mut image, 52);                   // e_phoff
+    push_u32(&mut image, 0);                    // e_shoff
+    push_u32(&mut image, 0x05000400);           // e_flags
+    push_u16(&mut image, 52);                   // e_ehsize
+    push_u16(&mut image, 32);                   // e_phentsize
+    push_u16(&mut image, 2);                    // e_phnum
+    push_u16(&mut image, 40);                   // e_shentsize
+    push_u16(&mut image, 0);                    // e_shnum
+    push_u16(&mut image, 0);                    // e_shstrndx
+
+    // Program headers
+    push_program_header(&mut image, 1, 0, IMAGE_SIZE, IMAGE_SIZE + BSS_SIZE, PF_R | PF_W | PF_X);
+    push_program_header(&mut image, 2, DYNAMIC_OFFSET, 24, 24, PF_R);
+
+    // Code : B . ; NOP
+    image.extend_from_slice(b"\xfe\xe7\x00\xbf");
+    // Data : pointer to the code
+    push_u32(&mut image, CODE_OFFSET);
+    // Relocation table
+    push_u32(&mut image, DATA_OFFSET);
+    push_u32(&mut image, relocation_type);
+    // Dynamic section : DT_REL, DT_RELSZ, DT_NULL
+    for value in [17, REL_OFFSET, 18, 8, 0, 0] {
+        push_u32(&mut image, value);
+    }
+
+    image
+}
+
+#[test_case]
+#[inline(never)]
+fn test_elf_load_relocate() {
+    let image = build_image(23);
+    let loaded = elf::load(&image).expect("Valid image should load");
+    log_debug!("Image loaded at {:p}, entry point {:p}", loaded.base, loaded.entry_point);
+
+    unsafe {
+        assert_eq!(loaded.size, (IMAGE_SIZE + BSS_SIZE) as usize);
+        assert_eq!(loaded.entry_point, loaded.base.add(CODE_OFFSET as usize));
+
+        // R_ARM_RELATIVE : pointer rebased on the load address
+        let data = *(loaded.base.add(DATA_OFFSET as usize) as *const u32);
+        assert_eq!(data, loaded.base as u32 + CODE_OFFSET);
+
+        // bss zero-filled
+        let bss = core::slice::from_raw_parts(loaded.base.add(IMAGE_SIZE as usize), BSS_SIZE as usize);
+        assert!(bss.iter().all(|byte| *byte == 0));
+
+        heap::deallocate(loaded.base);
+    }
+}
+
+#[test_case]
+#[inline(never)]
+fn test_elf_malformed() {
+    let image = build_image(23);
+
+    assert!(matches!(elf::load(&image[..40]), Err(ElfError::Truncated)));
+
+    let mut bad_magic = image.clone();
+    bad_magic[1] = b'X';
+    assert!(matches!(elf::load(&bad_magic), Err(ElfError::BadMagic)));
+
+    let mut bad_machine = image.clone();
+    bad_machine[18] = 3;
+    assert!(matches!(elf::load(&bad_machine), Err(ElfError::UnsupportedMachine(3))));
+
+    // Segment larger than the file
+    assert!(matches!(elf::load(&image[..100]), Err(ElfError::SegmentOutOfBounds)));
+
+    // Program headers at the end of the address space
+    let mut bad_phoff = image.clone();
+    bad_phoff[28..32].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+    assert!(matches!(elf::load(&bad_phoff), Err(ElfError::Truncated)));
+
+    // Segment spanning more memory than the heap
+    let mut huge_segment = image.clone();
+    huge_segment[72..76].copy_from_slice(&0xf000_0000u32.to_le_bytes());
+    assert!(matches!(elf::load(&huge_segment), Err(ElfError::OutOfMemory)));
+
+    // R_ARM_COPY is not supported, and nothing is left allocated
+    let free_before = heap::get_free_heap_size();
+    assert!(matches!(elf::load(&build_image(20)), Err(ElfError::UnsupportedRelocation(20))));
+    assert_eq!(heap::get_free_heap_size(), free_before);
+}
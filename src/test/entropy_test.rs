@@ -0,0 +1,81 @@
+use core::mem::size_of;
+use core::ptr;
+use crate::entropy::{self, EntropySource};
+use crate::memory_management::heap;
+use crate::proc::{ExitStatus, SystemProcess};
+use crate::log_debug;
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+/// Size of the header of a heap block : next_free, block_size, cookie, owner
+const BLOCK_HEADER_SIZE: usize = size_of::<usize>() * 4;
+
+#[test_case]
+#[inline(never)]
+fn test_random_values() {
+    assert_ne!(entropy::source(), EntropySource::None);
+    log_debug!("Entropy source : {:?}", entropy::source());
+
+    let mut values = [0u32; 32];
+    for value in values.iter_mut() {
+        *value = entropy::random_u32();
+    }
+    for (i, value) in values.iter().enumerate() {
+        assert!(!values[i + 1..].contains(value), "Random values should not repeat");
+    }
+
+    let mut bytes = [0u8; 7];
+    entropy::fill_bytes(&mut bytes);
+    assert!(bytes.iter().any(|byte| *byte != 0));
+}
+
+#[test_case]
+#[inline(never)]
+fn test_heap_cookie_bound_to_address() {
+    unsafe {
+        let ptr_1 = heap::allocate(32);
+        let ptr_2 = heap::allocate(32);
+        assert!(heap::check_cookie(ptr_1) && heap::check_cookie(ptr_2));
+
+        let block_1 = ptr_1.sub(BLOCK_HEADER_SIZE);
+        let block_2 = ptr_2.sub(BLOCK_HEADER_SIZE);
+        let block_size = *(block_1 as *const usize).add(1);
+        let mut saved_block = [0u8; 64];
+        assert!(block_size <= saved_block.len());
+        ptr::copy_nonoverlapping(block_2, saved_block.as_mut_ptr(), block_size);
+
+        // Same size, but the cookie of another block
+        ptr::copy_nonoverlapping(block_1, block_2, BLOCK_HEADER_SIZE);
+        assert!(!heap::check_cookie(ptr_2), "A header copied from another block should not validate");
+
+        // Even with the end cookie of the other block
+        ptr::copy_nonoverlapping(block_1, block_2, block_size);
+        assert!(!heap::check_cookie(ptr_2), "A block copied from another address should not validate");
+
+        // Restore the block before freeing it
+        ptr::copy_nonoverlapping(saved_block.as_ptr(), block_2, block_size);
+        assert!(heap::check_cookie(ptr_2));
+        heap::deallocate(ptr_2);
+        heap::deallocate(ptr_1);
+    }
+}
+
+#[test_case]
+#[inline(never)]
+fn test_stack_canary() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), pid);
+
+    // The process writes below its stack : killed on next scheduler call
+    let stack_base = system_process.get_process_by_id(pid).expect("No process with this ID").get_stack_base();
+    unsafe {
+        ptr::write_volatile(stack_base as *mut u32, 0);
+    }
+    system_process.schedule_next_process();
+    assert!(system_process.get_process_by_id(pid).is_none());
+    assert_eq!(system_process.get_exit_status(pid), Some(ExitStatus::StackOverflow));
+}
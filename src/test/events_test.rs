@@ -0,0 +1,90 @@
+use crate::proc::{EventWait, ExitStatus, IpcError, ProcStatus, SystemProcess, Timeout};
+use crate::syscall::errno;
+use super::helpers::{stacked_r0, status};
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+#[test_case]
+#[inline(never)]
+fn test_events() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+
+    assert_eq!(system_process.wait_events(0, EventWait::Any, Timeout::NoWait), Err(IpcError::InvalidArgument));
+    assert_eq!(system_process.wait_events(0b1, EventWait::Any, Timeout::NoWait), Err(IpcError::WouldBlock));
+    assert_eq!(system_process.notify(pid + 1, 0b1), Err(IpcError::NoSuchProcess));
+    assert_eq!(IpcError::NoSuchProcess.errno(), errno::ESRCH);
+
+    // Only the events of the mask are consumed
+    assert_eq!(system_process.notify(pid, 0b101), Ok(()));
+    assert_eq!(system_process.wait_events(0b110, EventWait::Any, Timeout::NoWait), Ok(Some(0b100)));
+    assert_eq!(system_process.wait_events(0b110, EventWait::Any, Timeout::NoWait), Err(IpcError::WouldBlock));
+    assert_eq!(system_process.wait_events(0b011, EventWait::All, Timeout::NoWait), Err(IpcError::WouldBlock));
+    assert_eq!(system_process.notify(pid, 0b010), Ok(()));
+    assert_eq!(system_process.wait_events(0b011, EventWait::All, Timeout::NoWait), Ok(Some(0b011)));
+
+    system_process.kill_process(pid);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_events_wake_up() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+
+    // Waiting for all the events : woken up once the last one is notified
+    assert_eq!(system_process.wait_events(0b11, EventWait::All, Timeout::Forever), Ok(None));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.notify(pid, 0b01), Ok(()));
+    assert_eq!(status(&mut system_process, pid), ProcStatus::Waiting);
+    assert_eq!(system_process.notify(pid, 0b110), Ok(()));
+    assert_eq!(status(&mut system_process, pid), ProcStatus::Idle);
+    assert!(system_process.need_resched());
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), pid);
+    assert_eq!(stacked_r0(&mut system_process, pid), 0b11);
+
+    // The event not waited for is still pending
+    assert_eq!(system_process.wait_events(0b100, EventWait::Any, Timeout::NoWait), Ok(Some(0b100)));
+
+    // Without events before the deadline, the process times out
+    assert_eq!(system_process.wait_events(0b1, EventWait::Any, Timeout::Ticks(3)), Ok(None));
+    system_process.schedule_next_process();
+    for now in 1..=3 {
+        system_process.tick(now);
+    }
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), pid);
+    assert_eq!(stacked_r0(&mut system_process, pid), (-errno::ETIMEDOUT) as u32);
+
+    system_process.kill_process(pid);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_events_before_switch() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+
+    // An interrupt handler notifies the process before the context switch requested by the wait : it keeps running
+    assert_eq!(system_process.wait_events(0b1, EventWait::Any, Timeout::Forever), Ok(None));
+    assert_eq!(system_process.notify(pid, 0b1), Ok(()));
+    assert_eq!(status(&mut system_process, pid), ProcStatus::Running);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), pid);
+    assert_eq!(stacked_r0(&mut system_process, pid), 0b1);
+
+    // It was not queued : once it exits, the idle process runs
+    system_process.exit_current_process(ExitStatus::Exited(0));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), 0);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), 0);
+}
@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+use crate::memory_management::heap;
+use crate::proc::SystemProcess;
+use crate::log_debug;
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+#[test_case]
+#[inline(never)]
+fn test_heap_coalescing() {
+    let before = heap::heap_stats();
+    log_debug!("Heap before : {:?}", before);
+
+    unsafe {
+        let ptr_1 = heap::allocate(32);
+        let ptr_2 = heap::allocate(64);
+        let ptr_3 = heap::allocate(128);
+        let ptr_4 = heap::allocate(16);
+
+        // Free blocks not adjacent to each other stay separate
+        heap::deallocate(ptr_1);
+        heap::deallocate(ptr_3);
+        assert_eq!(heap::heap_stats().free_fragments, before.free_fragments + 2);
+
+        // Merged with the previous and next free blocks
+        heap::deallocate(ptr_2);
+        assert_eq!(heap::heap_stats().free_fragments, before.free_fragments + 1);
+
+        // Merged with the previous block and the rest of the heap
+        heap::deallocate(ptr_4);
+    }
+
+    let after = heap::heap_stats();
+    assert_eq!(after.free_fragments, before.free_fragments);
+    assert_eq!(after.free_bytes, before.free_bytes);
+    assert_eq!(after.largest_free_block, before.largest_free_block);
+    assert_eq!(after.allocation_count, before.allocation_count + 4);
+    assert_eq!(after.free_count, before.free_count + 4);
+    assert!(after.minimum_ever_free_bytes < before.free_bytes);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_heap_process_churn() {
+    let before = heap::heap_stats();
+    assert_eq!(before.free_fragments, heap::region_count(), "Heap should start as a single free block per region");
+
+    let mut system_process = SystemProcess::new();
+    let mut pids = Vec::new();
+
+    for round in 0..50 {
+        for _ in 0..4 {
+            pids.push(system_process.create_process("Churn", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap());
+        }
+
+        // Kill the processes in an order changing with each round, leaving holes between blocks
+        while !pids.is_empty() {
+            let pid = pids.remove((round * 3) % pids.len());
+            system_process.kill_process(pid);
+        }
+    }
+    drop(pids);
+
+    let after = heap::heap_stats();
+    log_debug!("Heap after churn : {:?}", after);
+    assert_eq!(after.free_fragments, heap::region_count(), "Heap should be back to a single free block per region");
+    assert_eq!(after.free_bytes, before.free_bytes);
+    assert_eq!(after.largest_free_block, before.largest_free_block);
+}
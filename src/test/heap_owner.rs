@@ -0,0 +1,82 @@
+use crate::memory_management::heap::{self, Owner};
+use crate::proc::SystemProcess;
+use crate::log_debug;
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+#[test_case]
+#[inline(never)]
+fn test_reclaim_on_kill() {
+    let free_before = heap::get_free_heap_size();
+    let mut system_process = SystemProcess::new();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+
+    // The image and the stack are owned by the process
+    let spawned_usage = system_process.get_memory_usage(pid);
+    assert!(spawned_usage > 1024);
+
+    // Memory allocated on behalf of the process, never freed by it, around a kernel block
+    let owner = system_process.get_heap_owner(pid).expect("No process with this ID");
+    let kernel_block;
+    unsafe {
+        assert!(!heap::allocate_for(owner, 100).is_null());
+        kernel_block = heap::allocate(64);
+        assert!(!heap::allocate_for(owner, 200).is_null());
+    }
+    assert!(system_process.get_memory_usage(pid) >= spawned_usage + 300);
+    system_process.list_proc();
+
+    // The blocks are freed and merged with the free blocks around them
+    system_process.kill_process(pid);
+    assert_eq!(heap::usage(pid), 0);
+    assert!(heap::verify().is_ok());
+    unsafe { heap::deallocate(kernel_block) };
+    assert_eq!(heap::get_free_heap_size(), free_before);
+    assert!(heap::verify().is_ok());
+}
+
+#[test_case]
+#[inline(never)]
+fn test_memory_quota() {
+    let mut system_process = SystemProcess::new();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let usage = system_process.get_memory_usage(pid);
+
+    assert!(system_process.set_memory_quota(pid, usage + 256));
+    let owner = system_process.get_heap_owner(pid).expect("No process with this ID");
+    assert_eq!(owner, Owner { pid, quota: usage + 256 });
+
+    unsafe {
+        assert!(!heap::allocate_for(owner, 128).is_null());
+        // Over the quota, even if the heap has enough free memory
+        assert!(heap::allocate_for(owner, 128).is_null());
+        log_debug!("PID {} holds {} bytes", pid, heap::usage(pid));
+
+        // The kernel is not limited
+        let ptr = heap::allocate(128);
+        assert!(!ptr.is_null());
+        heap::deallocate(ptr);
+    }
+
+    system_process.kill_process(pid);
+    assert_eq!(heap::usage(pid), 0);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_spawn_over_quota() {
+    /// Code filling the whole default quota, leaving no room for the stack
+    static LARGE_CODE: [u8; 16 * 1024 - 64] = [0; 16 * 1024 - 64];
+
+    let free_before = heap::get_free_heap_size();
+    let mut system_process = SystemProcess::new();
+
+    // Nothing stays allocated, no process is created
+    assert_eq!(system_process.create_process("Large", &LARGE_CODE, LARGE_CODE.len(), 0), None);
+    assert_eq!(heap::get_free_heap_size(), free_before);
+    assert!(heap::verify().is_ok());
+
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.kill_process(pid);
+}
@@ -0,0 +1,27 @@
+use crate::memory_management::heap::{self, region_attr, HeapRegion};
+use crate::log_debug;
+
+#[test_case]
+#[inline(never)]
+fn test_heap_regions() {
+    let (ccm_start, ccm_end) = HeapRegion::Ccm.bounds();
+    let (sram_start, sram_end) = HeapRegion::Sram.bounds();
+    log_debug!("Heap regions : CCM {:#x}..{:#x}, SRAM {:#x}..{:#x}", ccm_start, ccm_end, sram_start, sram_end);
+    assert!(ccm_start >= 0x1000_0000 && ccm_end <= 0x1001_0000);
+    assert!(sram_start >= 0x2000_0000 && sram_end <= 0x2003_0000);
+
+    assert_eq!(HeapRegion::Ccm.attributes() & region_attr::DMA_CAPABLE, 0);
+    assert_ne!(HeapRegion::Sram.attributes() & region_attr::DMA_CAPABLE, 0);
+
+    unsafe {
+        let ccm_ptr = heap::allocate_in(HeapRegion::Ccm, 64);
+        let sram_ptr = heap::allocate(64);
+        assert!(HeapRegion::Ccm.contains(ccm_ptr as usize), "Block should be in CCM");
+        assert!(HeapRegion::Sram.contains(sram_ptr as usize), "Default allocations should be in SRAM");
+        assert!(heap::verify().is_ok());
+
+        heap::deallocate(ccm_ptr);
+        heap::deallocate(sram_ptr);
+    }
+    assert!(heap::verify().is_ok());
+}
@@ -106,7 +106,7 @@ fn cookie_overflow(){
         log_debug!("Initial cookie: {:#x}", *initial_cookie_ptr);
 
         // Write some data beyond the allocated size to corrupt the cookie
-        let final_cookie_ptr = ptr.add(block_size).sub(size_of::<usize>()-3); // Point to the cookie location
+        let final_cookie_ptr = ptr.add(block_size).sub(size_of::<usize>()*4); // Point to the cookie location (last word of the block)
         *final_cookie_ptr = 0x42; // This should corrupt the cookie
 
         // Get cookie value again
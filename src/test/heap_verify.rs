@@ -0,0 +1,74 @@
+use core::mem::size_of;
+use core::ptr;
+use crate::memory_management::heap::{self, HeapCorruption};
+use crate::proc::SystemProcess;
+use crate::log_debug;
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+/// Size of the header of a heap block : next_free, block_size, cookie, owner
+const BLOCK_HEADER_SIZE: usize = size_of::<usize>() * 4;
+
+/// Address of the end cookie of the block allocated at `ptr`, in the last word of the block
+unsafe fn end_cookie_of(ptr: *mut u8) -> *mut usize {
+    unsafe {
+        let block = ptr.sub(BLOCK_HEADER_SIZE);
+        let block_size = *(block as *const usize).add(1);
+        block.add(block_size - size_of::<usize>()) as *mut usize
+    }
+}
+
+#[test_case]
+#[inline(never)]
+fn test_heap_verify() {
+    let report = heap::verify().expect("Heap should not be corrupted");
+    log_debug!("Heap report : {:?}", report);
+
+    let stats = heap::heap_stats();
+    assert_eq!(report.free_bytes, stats.free_bytes);
+    assert_eq!(report.free_blocks, stats.free_fragments);
+
+    unsafe {
+        let ptr_1 = heap::allocate(32);
+        let ptr_2 = heap::allocate(32);
+        assert_eq!(heap::verify().map(|report| report.allocated_blocks), Ok(report.allocated_blocks + 2));
+
+        // Overflow of the first block, over its end cookie
+        let end_cookie = end_cookie_of(ptr_1);
+        let saved_cookie = ptr::read(end_cookie);
+        ptr::write_bytes(ptr_1, 0x55, 32 + size_of::<usize>());
+        let error = heap::verify().expect_err("Overflow should be detected");
+        assert_eq!(error.corruption, HeapCorruption::BadCookie);
+        assert_eq!(error.user_memory, ptr_1 as usize);
+
+        ptr::write(end_cookie, saved_cookie);
+        assert!(heap::verify().is_ok());
+
+        heap::deallocate(ptr_2);
+        heap::deallocate(ptr_1);
+    }
+    assert_eq!(heap::verify(), Ok(report));
+}
+
+#[test_case]
+#[inline(never)]
+fn test_heap_verify_owner() {
+    let mut system_process = SystemProcess::new();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let stack = system_process.get_process_by_id(pid).expect("No process with this ID").get_stack_base();
+
+    unsafe {
+        // Overflow of the stack block
+        let end_cookie = end_cookie_of(stack);
+        let saved_cookie = ptr::read(end_cookie);
+        ptr::write(end_cookie, !saved_cookie);
+
+        let error = system_process.verify_heap().expect_err("Overflow should be detected");
+        assert_eq!(error.corruption, HeapCorruption::BadCookie);
+        assert_eq!(error.owner, Some(pid));
+
+        ptr::write(end_cookie, saved_cookie);
+    }
+    assert!(system_process.verify_heap().is_ok());
+    system_process.kill_process(pid);
+}
@@ -0,0 +1,28 @@
+//! Helpers shared by the tests driving the scheduler and the blocking syscalls
+
+use core::ptr;
+use crate::proc::{ProcStatus, SystemProcess, CALLEE_SAVED_SIZE};
+
+/// R0 stacked by a process, returned to it when it resumes (R4-R11 are saved below the exception frame)
+pub fn stacked_r0(system_process: &mut SystemProcess, pid: u16) -> u32 {
+    let sp = system_process.get_process_by_id(pid).unwrap().get_stack_ptr();
+    unsafe { ptr::read_volatile((sp as usize + CALLEE_SAVED_SIZE) as *const u32) }
+}
+
+pub fn status(system_process: &mut SystemProcess, pid: u16) -> ProcStatus {
+    system_process.get_process_by_id(pid).unwrap().get_status()
+}
+
+pub fn priority(system_process: &mut SystemProcess, pid: u16) -> u8 {
+    system_process.get_process_by_id(pid).unwrap().get_priority()
+}
+
+/// Simulate `ticks` SysTick periods, calling the scheduler when requested
+pub fn run_ticks(system_process: &mut SystemProcess, now: &mut u64, ticks: u64) {
+    for _ in 0..ticks {
+        *now += 1;
+        if system_process.tick(*now) {
+            system_process.schedule_next_process();
+        }
+    }
+}
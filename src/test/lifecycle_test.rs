@@ -0,0 +1,139 @@
+use core::ptr;
+use crate::memory_management::heap::KERNEL_PID;
+use crate::proc::{ExitStatus, IpcError, ProcStatus, SystemProcess, Timeout};
+use crate::syscall::errno;
+use super::helpers::{stacked_r0, status, run_ticks};
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+/// Exit status written by `wait_child` : kind, then value
+fn exit_status(address: u32) -> [u32; 2] {
+    unsafe { ptr::read_volatile(address as *const [u32; 2]) }
+}
+
+#[test_case]
+#[inline(never)]
+fn test_spawn_wait() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let mut now = 0;
+    let program = system_process.register_program("child", crate::PROC_1_ELF, 2).unwrap();
+    let broken = system_process.register_program("broken", LOOP_BYTE_CODE, 2).unwrap();
+    assert_eq!(system_process.find_program(b"child"), Some(program));
+    assert_eq!(system_process.find_program(b"missing"), None);
+
+    let parent = system_process.create_process("Parent", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    assert_eq!(system_process.getpid(), Err(IpcError::NoProcess));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.getpid(), Ok(parent));
+    let status_address = system_process.map_memory(32).unwrap();
+
+    assert_eq!(system_process.spawn(broken), Err(IpcError::InvalidImage));
+    assert_eq!(IpcError::InvalidImage.errno(), errno::ENOEXEC);
+    assert_eq!(system_process.spawn(program + 10), Err(IpcError::NotFound));
+    let child = system_process.spawn(program).expect("Spawn should succeed");
+    assert_eq!(system_process.get_process_by_id(child).unwrap().get_parent_id(), parent);
+
+    assert_eq!(system_process.wait_child(parent, 0, Timeout::NoWait), Err(IpcError::NotChild));
+    assert_eq!(IpcError::NotChild.errno(), errno::ECHILD);
+    assert_eq!(system_process.wait_child(child, 0, Timeout::NoWait), Err(IpcError::WouldBlock));
+    assert_eq!(system_process.wait_child(child, 4, Timeout::Forever), Err(IpcError::Fault));
+
+    // The parent waits, the child runs and exits : its status is delivered to the parent, and it is reaped
+    assert_eq!(system_process.wait_child(child, status_address, Timeout::Forever), Ok(None));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), child);
+    system_process.exit_current_process(ExitStatus::Exited(7));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), parent);
+    assert_eq!(stacked_r0(&mut system_process, parent), child as u32);
+    assert_eq!(exit_status(status_address), [0, 7]);
+    assert!(system_process.get_process_by_id(child).is_none());
+
+    // A child exiting before its parent waits for it stays a zombie
+    let child = system_process.spawn(program).unwrap();
+    system_process.sleep_current_process(5);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), child);
+    system_process.exit_current_process(ExitStatus::Exited(3));
+    system_process.schedule_next_process();
+    assert_eq!(status(&mut system_process, child), ProcStatus::Zombie);
+    assert_eq!(system_process.notify(child, 0b1), Err(IpcError::NoSuchProcess));
+    run_ticks(&mut system_process, &mut now, 5);
+    assert_eq!(system_process.get_current_process_id(), parent);
+    assert_eq!(system_process.wait_child(child, status_address, Timeout::NoWait), Ok(Some(child as u32)));
+    assert_eq!(exit_status(status_address), [0, 3]);
+    assert_eq!(system_process.wait_child(child, 0, Timeout::NoWait), Err(IpcError::NotChild));
+
+    system_process.kill_process(parent);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_kill_permissions() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let program = system_process.register_program("child", crate::PROC_1_ELF, 2).unwrap();
+    let parent = system_process.create_process("Parent", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    let other = system_process.create_process("Other", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), parent);
+    let status_address = system_process.map_memory(32).unwrap();
+
+    // Only the process itself and its children may be killed
+    let child = system_process.spawn(program).unwrap();
+    assert_eq!(system_process.kill_from_process(other), Err(IpcError::NotPermitted));
+    assert_eq!(IpcError::NotPermitted.errno(), errno::EPERM);
+    assert_eq!(system_process.kill_from_process(child + 10), Err(IpcError::NoSuchProcess));
+    assert_eq!(system_process.kill_from_process(child), Ok(0));
+    assert_eq!(status(&mut system_process, child), ProcStatus::Zombie);
+    assert_eq!(system_process.kill_from_process(child), Ok(0));
+    assert_eq!(system_process.wait_child(child, status_address, Timeout::NoWait), Ok(Some(child as u32)));
+    assert_eq!(exit_status(status_address), [2, 0]);
+
+    // The children of a terminated process are adopted by the kernel, its zombies are reaped
+    let zombie = system_process.spawn(program).unwrap();
+    let orphan = system_process.spawn(program).unwrap();
+    assert_eq!(system_process.kill_from_process(zombie), Ok(0));
+    assert_eq!(system_process.kill_from_process(parent), Ok(0));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), other);
+    assert!(system_process.get_process_by_id(parent).is_none());
+    assert!(system_process.get_process_by_id(zombie).is_none());
+    assert_eq!(system_process.get_process_by_id(orphan).unwrap().get_parent_id(), KERNEL_PID);
+    assert_eq!(system_process.kill_from_process(orphan), Err(IpcError::NotPermitted));
+
+    // Without a parent, a terminated process is reaped at once
+    system_process.kill_process(orphan);
+    assert!(system_process.get_process_by_id(orphan).is_none());
+    system_process.kill_process(other);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_yield() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let first = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    let second = system_process.create_process("Process 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), first);
+
+    // Yielding rotates with the other processes of the same priority, without waiting for the next tick
+    system_process.yield_current_process();
+    assert!(system_process.need_resched());
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), second);
+    system_process.yield_current_process();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), first);
+
+    // Alone at its priority, the process keeps running
+    system_process.kill_process(second);
+    system_process.yield_current_process();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), first);
+
+    system_process.kill_process(first);
+}
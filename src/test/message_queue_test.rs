@@ -0,0 +1,194 @@
+use core::ptr;
+use crate::memory_management::heap;
+use crate::proc::{IpcError, ProcStatus, SystemProcess, Timeout};
+use crate::syscall::errno;
+use super::helpers::{stacked_r0, status};
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+fn write_user(address: u32, data: &[u8]) {
+    unsafe { ptr::copy_nonoverlapping(data.as_ptr(), address as *mut u8, data.len()) };
+}
+
+fn read_user(address: u32, len: usize) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(address as *const u8, len) }
+}
+
+#[test_case]
+#[inline(never)]
+fn test_message_queue_non_blocking() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+    let usage = system_process.get_memory_usage(pid);
+
+    let buffer = system_process.map_memory(32).unwrap();
+    let mapped_usage = system_process.get_memory_usage(pid);
+    assert_eq!(system_process.mq_create(0, 2), Err(IpcError::InvalidArgument));
+    assert_eq!(system_process.mq_create(4, 0), Err(IpcError::InvalidArgument));
+    let queue = system_process.mq_create(4, 2).expect("Queue creation should succeed");
+    assert!(system_process.get_memory_usage(pid) > mapped_usage);
+
+    // Messages are received in order, up to the capacity of the queue
+    write_user(buffer, &[1, 2, 3, 4]);
+    assert_eq!(system_process.mq_send(queue, buffer, Timeout::NoWait), Ok(Some(0)));
+    write_user(buffer, &[5, 6, 7, 8]);
+    assert_eq!(system_process.mq_send(queue, buffer, Timeout::NoWait), Ok(Some(0)));
+    assert_eq!(system_process.mq_send(queue, buffer, Timeout::NoWait), Err(IpcError::WouldBlock));
+
+    assert_eq!(system_process.mq_receive(queue, buffer + 16, Timeout::NoWait), Ok(Some(4)));
+    assert_eq!(read_user(buffer + 16, 4), [1, 2, 3, 4]);
+    assert_eq!(system_process.mq_receive(queue, buffer + 16, Timeout::NoWait), Ok(Some(4)));
+    assert_eq!(read_user(buffer + 16, 4), [5, 6, 7, 8]);
+    assert_eq!(system_process.mq_receive(queue, buffer + 16, Timeout::NoWait), Err(IpcError::WouldBlock));
+
+    // Buffers outside the memory of the process, and unknown handles, are rejected
+    assert_eq!(system_process.mq_send(queue, 0, Timeout::NoWait), Err(IpcError::Fault));
+    assert_eq!(system_process.mq_receive(queue, buffer + 30, Timeout::NoWait), Err(IpcError::Fault));
+    assert_eq!(system_process.mq_send(queue + 1, buffer, Timeout::NoWait), Err(IpcError::InvalidHandle));
+    assert_eq!(IpcError::InvalidHandle.errno(), errno::EBADF);
+
+    system_process.unmap_memory(buffer).unwrap();
+    assert!(system_process.get_memory_usage(pid) > usage);
+
+    // The queue is destroyed with its owner
+    system_process.kill_process(pid);
+    assert_eq!(heap::usage(pid), 0);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_message_queue_blocking() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let receiver = system_process.create_process("Receiver", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let sender = system_process.create_process("Sender", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    let mut now = 0;
+
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), receiver);
+    let receiver_buffer = system_process.map_memory(32).unwrap();
+    let queue = system_process.mq_create(4, 1).unwrap();
+
+    // The receiver waits for a message, and gets it from the sender
+    assert_eq!(system_process.mq_receive(queue, receiver_buffer, Timeout::Forever), Ok(None));
+    assert_eq!(status(&mut system_process, receiver), ProcStatus::Waiting);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), sender);
+
+    let sender_buffer = system_process.map_memory(32).unwrap();
+    write_user(sender_buffer, &[0xca, 0xfe, 0xba, 0xbe]);
+    assert_eq!(system_process.mq_send(queue, sender_buffer, Timeout::NoWait), Ok(Some(0)));
+    assert_eq!(read_user(receiver_buffer, 4), [0xca, 0xfe, 0xba, 0xbe]);
+    assert!(system_process.need_resched());
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), receiver);
+    assert_eq!(stacked_r0(&mut system_process, receiver), 4);
+
+    // Without a message before its deadline, the receiver times out
+    assert_eq!(system_process.mq_receive(queue, receiver_buffer, Timeout::Ticks(2)), Ok(None));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), sender);
+    for _ in 0..2 {
+        now += 1;
+        system_process.tick(now);
+    }
+    assert_eq!(status(&mut system_process, receiver), ProcStatus::Idle);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), receiver);
+    assert_eq!(stacked_r0(&mut system_process, receiver), (-errno::ETIMEDOUT) as u32);
+
+    // The sender waits for a free slot, and fills the slot freed by the receiver
+    system_process.sleep_current_process(10);
+    system_process.schedule_next_process();
+    write_user(sender_buffer, &[1, 1, 1, 1]);
+    assert_eq!(system_process.mq_send(queue, sender_buffer, Timeout::NoWait), Ok(Some(0)));
+    write_user(sender_buffer, &[2, 2, 2, 2]);
+    assert_eq!(system_process.mq_send(queue, sender_buffer, Timeout::NoWait), Err(IpcError::WouldBlock));
+    assert_eq!(system_process.mq_send(queue, sender_buffer, Timeout::Forever), Ok(None));
+    system_process.schedule_next_process();
+    assert_eq!(status(&mut system_process, sender), ProcStatus::Waiting);
+
+    now += 10;
+    system_process.tick(now);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), receiver);
+    assert_eq!(system_process.mq_receive(queue, receiver_buffer, Timeout::NoWait), Ok(Some(4)));
+    assert_eq!(read_user(receiver_buffer, 4), [1, 1, 1, 1]);
+    assert_eq!(status(&mut system_process, sender), ProcStatus::Idle);
+    assert_eq!(system_process.mq_receive(queue, receiver_buffer, Timeout::NoWait), Ok(Some(4)));
+    assert_eq!(read_user(receiver_buffer, 4), [2, 2, 2, 2]);
+
+    // A process waiting on a queue is woken up when the queue is destroyed with its owner
+    system_process.sleep_current_process(10);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), sender);
+    assert_eq!(system_process.mq_receive(queue, sender_buffer, Timeout::Forever), Ok(None));
+    system_process.schedule_next_process();
+    system_process.kill_process(receiver);
+    assert_eq!(status(&mut system_process, sender), ProcStatus::Idle);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), sender);
+    assert_eq!(stacked_r0(&mut system_process, sender), (-errno::EIDRM) as u32);
+    assert_eq!(system_process.mq_receive(queue, sender_buffer, Timeout::NoWait), Err(IpcError::InvalidHandle));
+
+    system_process.kill_process(sender);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_message_queue_receiver_fault() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let first = system_process.create_process("Receiver 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let second = system_process.create_process("Receiver 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let sender = system_process.create_process("Sender", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+
+    // The buffer of the first receiver is unmapped while it waits, before it is switched out
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), first);
+    let queue = system_process.mq_create(4, 1).unwrap();
+    let first_buffer = system_process.map_memory(32).unwrap();
+    assert_eq!(system_process.mq_receive(queue, first_buffer, Timeout::Forever), Ok(None));
+    system_process.unmap_memory(first_buffer).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), second);
+    let second_buffer = system_process.map_memory(32).unwrap();
+    assert_eq!(system_process.mq_receive(queue, second_buffer, Timeout::Forever), Ok(None));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), sender);
+
+    // The first receiver gets the error, the message goes to the next one
+    let sender_buffer = system_process.map_memory(32).unwrap();
+    write_user(sender_buffer, &[1, 2, 3, 4]);
+    assert_eq!(system_process.mq_send(queue, sender_buffer, Timeout::NoWait), Ok(Some(0)));
+    assert_eq!(status(&mut system_process, first), ProcStatus::Idle);
+    assert_eq!(status(&mut system_process, second), ProcStatus::Idle);
+    assert_eq!(read_user(second_buffer, 4), [1, 2, 3, 4]);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), first);
+    assert_eq!(stacked_r0(&mut system_process, first), (-errno::EFAULT) as u32);
+    system_process.sleep_current_process(10);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), second);
+    assert_eq!(stacked_r0(&mut system_process, second), 4);
+
+    // Without another receiver, the message is queued
+    assert_eq!(system_process.mq_receive(queue, second_buffer, Timeout::Forever), Ok(None));
+    system_process.unmap_memory(second_buffer).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), sender);
+    write_user(sender_buffer, &[5, 6, 7, 8]);
+    assert_eq!(system_process.mq_send(queue, sender_buffer, Timeout::NoWait), Ok(Some(0)));
+    assert_eq!(system_process.mq_receive(queue, sender_buffer + 16, Timeout::NoWait), Ok(Some(4)));
+    assert_eq!(read_user(sender_buffer + 16, 4), [5, 6, 7, 8]);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), second);
+    assert_eq!(stacked_r0(&mut system_process, second), (-errno::EFAULT) as u32);
+
+    system_process.kill_process(sender);
+    system_process.kill_process(second);
+    system_process.kill_process(first);
+}
@@ -0,0 +1,73 @@
+use alloc::vec::Vec;
+use crate::memory_management::{heap, mpu::Access};
+use crate::proc::{MapError, SystemProcess};
+use crate::log_debug;
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+#[test_case]
+#[inline(never)]
+fn test_map_memory() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+    let usage = system_process.get_memory_usage(pid);
+
+    // Rounded up to a power of two, aligned on its size, zeroed, and writable by the process
+    let address = system_process.map_memory(100).expect("Mapping should succeed");
+    log_debug!("Mapped at {:#x}", address);
+    assert!(address.is_multiple_of(128));
+    assert!(system_process.get_memory_usage(pid) >= usage + 128);
+    let mpu = system_process.get_current_process_mpu().unwrap();
+    assert!(mpu.check_unprivileged_access(address, 128, Access::Write));
+    let memory = unsafe { core::slice::from_raw_parts(address as *const u8, 128) };
+    assert!(memory.iter().all(|byte| *byte == 0));
+
+    assert_eq!(system_process.map_memory(0), Err(MapError::InvalidLength));
+
+    // Unmapped : no longer accessible, and freed
+    assert_eq!(system_process.unmap_memory(address), Ok(()));
+    assert_eq!(system_process.unmap_memory(address), Err(MapError::InvalidAddress));
+    let mpu = system_process.get_current_process_mpu().unwrap();
+    assert!(!mpu.check_unprivileged_access(address, 128, Access::Write));
+    assert_eq!(system_process.get_memory_usage(pid), usage);
+
+    // The stack can't be unmapped
+    let stack = system_process.get_process_by_id(pid).unwrap().get_stack_base() as u32;
+    assert_eq!(system_process.unmap_memory(stack), Err(MapError::InvalidAddress));
+
+    system_process.kill_process(pid);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_map_memory_exhausted() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+
+    // Over the memory quota of the process
+    assert_eq!(system_process.map_memory(32 * 1024), Err(MapError::OutOfMemory));
+
+    // Until all the MPU regions are used
+    let mut mappings = Vec::new();
+    let error = loop {
+        match system_process.map_memory(32) {
+            Ok(address) => mappings.push(address),
+            Err(error) => break error
+        }
+    };
+    assert_eq!(error, MapError::NoFreeRegion);
+    assert!(!mappings.is_empty());
+
+    // A freed region can be used again
+    assert_eq!(system_process.unmap_memory(mappings[0]), Ok(()));
+    assert!(system_process.map_memory(32).is_ok());
+
+    // The mappings are reclaimed with the process
+    system_process.kill_process(pid);
+    assert_eq!(heap::usage(pid), 0);
+}
@@ -1,6 +1,44 @@
 #![allow(unused_imports)]
 use crate::log_debug;
-//mod scheduler;
+#[cfg(test)]
+mod helpers;
+// Runs first, on the heap left untouched by the other tests
+#[cfg(test)]
+mod heap_churn;
+#[cfg(test)]
+mod scheduler;
+#[cfg(test)]
+mod elf_test;
+#[cfg(test)]
+mod user_ptr;
+#[cfg(test)]
+mod crash_dump_test;
+#[cfg(test)]
+mod allocator_test;
+#[cfg(test)]
+mod entropy_test;
+#[cfg(test)]
+mod heap_verify;
+#[cfg(test)]
+mod heap_owner;
+#[cfg(test)]
+mod mmap_test;
+#[cfg(test)]
+mod pool_test;
+#[cfg(test)]
+mod heap_region;
+#[cfg(test)]
+mod mpu_region;
+#[cfg(test)]
+mod message_queue_test;
+#[cfg(test)]
+mod sync_test;
+#[cfg(test)]
+mod events_test;
+#[cfg(test)]
+mod shared_memory_test;
+#[cfg(test)]
+mod lifecycle_test;
 //mod exception_test;
 //mod mpu_test;
 //mod heap_test;
@@ -0,0 +1,95 @@
+use crate::memory_management::mpu::{Access, AccessPermission, Execute, MemoryType, Mpu, MpuError, RegionBuilder, Shareability};
+use crate::proc::SystemProcess;
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+#[test_case]
+#[inline(never)]
+fn test_region_encoding() {
+    // Default region : privileged RW, execute-never, normal write-through shareable memory
+    let region = RegionBuilder::new(3, 0x2000_0400, 1024).build().expect("Region should be valid");
+    assert_eq!(region.rbar(), 0x2000_0400 | (1 << 4) | 3);
+    assert_eq!(region.rasr(), (1 << 28) | (0b001 << 24) | (1 << 18) | (1 << 17) | (9 << 1) | 1);
+
+    let region = RegionBuilder::new(7, 0x2001_0000, 64 * 1024)
+        .access(AccessPermission::ReadOnly)
+        .execute(Execute::Never)
+        .memory_type(MemoryType::NormalWriteBackAllocate)
+        .shareability(Shareability::NonShareable)
+        .disable_subregions(0b1000_0001)
+        .build()
+        .expect("Region should be valid");
+    assert_eq!(region.rbar(), 0x2001_0000 | (1 << 4) | 7);
+    assert_eq!(region.rasr(), (1 << 28) | (0b110 << 24) | (0b001 << 19) | (1 << 17) | (1 << 16) | (0x81 << 8) | (15 << 1) | 1);
+
+    let region = RegionBuilder::new(0, 0x4000_0000, 32)
+        .access(AccessPermission::PrivilegedRo)
+        .execute(Execute::Allowed)
+        .memory_type(MemoryType::Device)
+        .shareability(Shareability::NonShareable)
+        .build()
+        .expect("Region should be valid");
+    assert_eq!(region.rasr(), (0b101 << 24) | (1 << 16) | (4 << 1) | 1);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_region_validation() {
+    assert_eq!(RegionBuilder::new(8, 0x2000_0000, 1024).build(), Err(MpuError::InvalidRegionNumber));
+    assert_eq!(RegionBuilder::new(0, 0x2000_0000, 16).build(), Err(MpuError::SizeTooSmall));
+    assert_eq!(RegionBuilder::new(0, 0x2000_0000, 768).build(), Err(MpuError::SizeNotPowerOfTwo));
+    assert_eq!(RegionBuilder::new(0, 0x2000_0200, 1024).build(), Err(MpuError::UnalignedBase));
+    assert_eq!(RegionBuilder::new(0, 0x2000_0000, 128).disable_subregions(1).build(), Err(MpuError::SubregionsNotSupported));
+    assert_eq!(RegionBuilder::new(0, 0x2000_0000, 256).disable_subregions(0xFF).build(), Err(MpuError::NoSubregionEnabled));
+    let writable_code = RegionBuilder::new(0, 0x2000_0000, 256).access(AccessPermission::FullAccess).execute(Execute::Allowed);
+    assert_eq!(writable_code.build(), Err(MpuError::WritableAndExecutable));
+
+    let mut mpu = Mpu::new();
+    assert_eq!(mpu.configure_region(0, 0x2000_0100, 10, 1), Err(MpuError::UnalignedBase));
+    assert_eq!(mpu.configure_region(0, 0x2000_0000, 4, 1), Err(MpuError::SizeTooSmall));
+    assert_eq!(mpu.configure_region(0, 0x2000_0000, 10, 1), Ok(()));
+}
+
+#[test_case]
+#[inline(never)]
+fn test_region_subregions() {
+    // 0x2000_0100..0x2000_0500 : 1 KiB not aligned on its size, covered by a 2 KiB region whose
+    // sub-regions of 256 bytes outside of the range are disabled
+    let builder = RegionBuilder::covering(1, 0x2000_0100, 0x400).access(AccessPermission::FullAccess);
+    let region = builder.build().expect("Region should be valid");
+    assert_eq!(region.rbar(), 0x2000_0000 | (1 << 4) | 1);
+    assert_eq!((region.rasr() >> 8) & 0xFF, 0b1110_0001);
+    assert_eq!((region.rasr() >> 1) & 0x1F, 10);
+
+    let mut mpu = Mpu::new();
+    mpu.configure(builder).expect("Region should be valid");
+    assert!(mpu.check_unprivileged_access(0x2000_0100, 0x400, Access::Write));
+    assert!(!mpu.check_unprivileged_access(0x2000_00FC, 4, Access::Read));
+    assert!(!mpu.check_unprivileged_access(0x2000_04FC, 8, Access::Read));
+}
+
+#[test_case]
+#[inline(never)]
+fn test_process_w_xor_x() {
+    let mut system_process = SystemProcess::new();
+    let pid = system_process.create_process("W^X", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let process = system_process.get_process_by_id(pid).unwrap();
+    let mpu = process.get_mpu();
+
+    // The code is executable and read-only, the stack is writable and execute-never
+    let code = process.get_entry_point() as u32;
+    assert!(mpu.check_unprivileged_access(code, LOOP_BYTE_CODE.len(), Access::Execute));
+    assert!(!mpu.check_unprivileged_access(code, LOOP_BYTE_CODE.len(), Access::Write));
+    let stack = process.get_stack_ptr();
+    assert!(mpu.check_unprivileged_access(stack, 4, Access::Write));
+    assert!(!mpu.check_unprivileged_access(stack, 4, Access::Execute));
+
+    for number in 0..8 {
+        if let Some(region) = mpu.region(number) {
+            assert!(!(region.is_writable() && region.is_executable()), "Region {} is writable and executable", number);
+        }
+    }
+
+    system_process.kill_process(pid);
+}
@@ -0,0 +1,72 @@
+use core::mem::size_of;
+use core::ptr::NonNull;
+use alloc::boxed::Box;
+use crate::memory_management::pool::{self, Pool, PoolAllocator, PoolError, POOL_ALIGN};
+use crate::proc::Process;
+use crate::utils::LinkedList;
+use crate::log_debug;
+
+#[test_case]
+#[inline(never)]
+fn test_pool_allocate() {
+    let mut pool = Pool::new("test", 20, 4);
+    assert_eq!(pool.allocate(), Err(PoolError::NotInitialized));
+    assert_eq!(pool.init(), Ok(()));
+    assert_eq!(pool.block_size(), 24);
+
+    let blocks: [NonNull<u8>; 4] = core::array::from_fn(|_| pool.allocate().expect("Pool should not be exhausted"));
+    for (i, block) in blocks.iter().enumerate() {
+        assert!((block.as_ptr() as usize).is_multiple_of(POOL_ALIGN));
+        assert!(!blocks[i + 1..].contains(block));
+    }
+    assert_eq!(pool.allocate(), Err(PoolError::Exhausted));
+
+    let stats = pool.stats();
+    log_debug!("Pool stats : {:?}", stats);
+    assert_eq!((stats.used, stats.peak_used, stats.exhausted_count), (4, 4, 1));
+
+    // A freed block is the next one allocated
+    assert_eq!(pool.deallocate(blocks[2]), Ok(()));
+    assert_eq!(pool.allocate(), Ok(blocks[2]));
+
+    // Not a block of the pool
+    let mut outside = 0u32;
+    assert_eq!(pool.deallocate(NonNull::from(&mut outside).cast()), Err(PoolError::InvalidPointer));
+    let inside = unsafe { NonNull::new_unchecked(blocks[0].as_ptr().add(4)) };
+    assert_eq!(pool.deallocate(inside), Err(PoolError::InvalidPointer));
+    assert_eq!(pool.stats().used, 4);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_pool_allocator() {
+    let used = |name: &str| pool::kernel_pool_stats().iter().find(|stats| stats.name == name).unwrap().used;
+    let nodes = used("node");
+
+    // Smallest pool large enough
+    let boxed = Box::new_in(0x1234u64, PoolAllocator);
+    assert_eq!(used("node"), nodes + 1);
+    drop(boxed);
+    assert_eq!(used("node"), nodes);
+
+    let mut list = LinkedList::new_in(PoolAllocator);
+    for value in 0..3u32 {
+        list.add(value);
+    }
+    assert_eq!(used("node"), nodes + 3);
+    while list.pop_front().is_some() {}
+    assert_eq!(used("node"), nodes);
+
+    // Dropping a list frees its remaining nodes
+    for value in 0..3u32 {
+        list.add(value);
+    }
+    drop(list);
+    assert_eq!(used("node"), nodes);
+
+    // Process list nodes fit in the process pool
+    assert!(size_of::<Process>() + size_of::<usize>() <= 320);
+    let fallbacks = pool::heap_fallbacks();
+    drop(Box::new_in([0u8; 512], PoolAllocator));
+    assert_eq!(pool::heap_fallbacks(), fallbacks + 1);
+}
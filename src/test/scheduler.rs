@@ -1,82 +1,202 @@
-use crate::proc::SystemProcess;
-use crate::utils::LinkedList;
+use alloc::vec::Vec;
+use crate::proc::{ExitStatus, FaultInfo, FaultKind, ProcStatus, SystemProcess, PRIORITY_LEVELS};
 use crate::log_debug;
 
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+/// Simulate `ticks` SysTick periods and record the PID running after each of them
+fn run_ticks(system_process: &mut SystemProcess, now: &mut u64, ticks: usize) -> Vec<u16> {
+    let mut order = Vec::new();
+
+    for _ in 0..ticks {
+        *now += 1;
+        if system_process.tick(*now) {
+            system_process.schedule_next_process();
+        }
+        order.push(system_process.get_current_process_id());
+    }
+    log_debug!("Execution order : {:?}", order);
+    order
+}
+
+fn kill_all(system_process: &mut SystemProcess, pids: &[u16]) {
+    for pid in pids {
+        system_process.kill_process(*pid);
+    }
+}
+
 #[test_case]
 #[inline(never)]
-fn test_scheduler() {
-    // Initialize the system process manager
-    let mut system_process = SystemProcess {
-        last_proc_id: 0,
-        process_list: LinkedList::new(),
-    };
-
-    // Create some processes
-    system_process.create_process("Process 1", process_1_entry as usize);
-    system_process.create_process("Process 2", process_2_entry as usize);
-
-    // Main scheduler loop
-    loop {
-        system_process.schedule_next_process();
-    }
+fn test_round_robin_same_priority() {
+    let mut system_process = SystemProcess::new();
+    let mut now = 0;
+
+    let pid_1 = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let pid_2 = system_process.create_process("Process 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+
+    assert_eq!(run_ticks(&mut system_process, &mut now, 2), [pid_1, pid_2]);
+
+    // A process added later gets its turn too
+    let pid_3 = system_process.create_process("Process 3", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+
+    assert_eq!(run_ticks(&mut system_process, &mut now, 6), [pid_1, pid_3, pid_2, pid_1, pid_3, pid_2]);
+
+    kill_all(&mut system_process, &[pid_1, pid_2, pid_3]);
 }
 
 #[test_case]
 #[inline(never)]
-fn test_list_proc(){
-    // Initialize the system process manager
-    let mut system_process = SystemProcess {
-        last_proc_id: 0,
-        process_list: LinkedList::new(),
-    };
-
-    // Create some processes
-    system_process.create_process("Process 1", process_1_entry as usize);
-    system_process.create_process("Process 2", process_2_entry as usize);
-
-    for process in system_process.process_list.iter_mut() {
-        log_debug!("{}", process.proc_name);
-    }
-    log_debug!("{}", system_process.last_proc_id);
+fn test_priority_preemption() {
+    let mut system_process = SystemProcess::new();
+    let mut now = 0;
+
+    let pid_low_1 = system_process.create_process("Low 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 2).unwrap();
+    let pid_low_2 = system_process.create_process("Low 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 2).unwrap();
+
+    assert_eq!(run_ticks(&mut system_process, &mut now, 1), [pid_low_1]);
+
+    // A higher priority process preempts the running one, and is never rotated with lower priorities
+    let pid_high = system_process.create_process("High", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    now += 1;
+    assert!(system_process.tick(now), "Higher priority process should trigger a reschedule");
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), pid_high);
+
+    assert_eq!(run_ticks(&mut system_process, &mut now, 3), [pid_high, pid_high, pid_high]);
+
+    // Once the high priority process is gone, the preempted process resumes first
+    system_process.exit_current_process(ExitStatus::Exited(0));
+    assert_eq!(run_ticks(&mut system_process, &mut now, 3), [pid_low_1, pid_low_2, pid_low_1]);
+
+    kill_all(&mut system_process, &[pid_low_1, pid_low_2]);
 }
 
+#[test_case]
+#[inline(never)]
+fn test_time_slice_per_priority() {
+    let mut system_process = SystemProcess::new();
+    let mut now = 0;
+    system_process.set_time_slice(1, 3);
+
+    let pid_1 = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    let pid_2 = system_process.create_process("Process 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+
+    assert_eq!(run_ticks(&mut system_process, &mut now, 7), [pid_1, pid_1, pid_1, pid_2, pid_2, pid_2, pid_1]);
+
+    kill_all(&mut system_process, &[pid_1, pid_2]);
+}
 
 #[test_case]
 #[inline(never)]
-fn test_kill_proc(){
-    // Initialize the system process manager
-    let mut system_process = SystemProcess {
-        last_proc_id: 0,
-        process_list: LinkedList::new(),
-    };
-
-    // Create some processes
-    system_process.create_process("Process 1", process_1_entry as usize);
-    system_process.create_process("Process 2", process_2_entry as usize);
-
-    for process in system_process.process_list.iter_mut() {
-        log_debug!("{}", process.proc_name);
-    }
-    log_debug!("{}", system_process.last_proc_id);
+fn test_kill_proc() {
+    let mut system_process = SystemProcess::new();
+    let mut now = 0;
 
-    system_process.kill_process(1);
+    let pid_1 = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let pid_2 = system_process.create_process("Process 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
 
-    for process in system_process.process_list.iter_mut() {
-        log_debug!("{}", process.proc_name);
-    }
-    log_debug!("{}", system_process.last_proc_id);
+    system_process.kill_process(pid_1);
+    system_process.list_proc();
+
+    assert!(system_process.get_process_by_id(pid_1).is_none());
+    assert_eq!(run_ticks(&mut system_process, &mut now, 2), [pid_2, pid_2]);
+
+    kill_all(&mut system_process, &[pid_2]);
 }
 
+#[test_case]
+#[inline(never)]
+fn test_sleep_wake_up() {
+    let mut system_process = SystemProcess::new();
+    let mut now = 0;
 
-// Dummy entry points for processes
-extern "C" fn process_1_entry() {
-    loop {
-        log_debug!("Process 1 running");
-    }
+    let pid_high = system_process.create_process("High", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let pid_low = system_process.create_process("Low", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+
+    assert_eq!(run_ticks(&mut system_process, &mut now, 1), [pid_high]);
+
+    // The sleeping process leaves the CPU and preempts the lower priority process once its deadline is reached
+    system_process.sleep_current_process(3);
+    assert!(system_process.need_resched());
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), pid_low);
+
+    assert_eq!(run_ticks(&mut system_process, &mut now, 4), [pid_low, pid_low, pid_high, pid_high]);
+
+    // Sleeping for more than a round of the timer wheel
+    system_process.sleep_current_process(40);
+    system_process.schedule_next_process();
+    let order = run_ticks(&mut system_process, &mut now, 41);
+    assert!(order[..39].iter().all(|pid| *pid == pid_low));
+    assert_eq!(order[39..], [pid_high, pid_high]);
+
+    kill_all(&mut system_process, &[pid_high, pid_low]);
 }
 
-extern "C" fn process_2_entry() {
-    loop {
-        log_debug!("Process 2 running");
-    }
+#[test_case]
+#[inline(never)]
+fn test_sleep_expired_before_switch() {
+    let mut system_process = SystemProcess::new();
+    let mut now = 0;
+    system_process.create_idle_process();
+
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    assert_eq!(run_ticks(&mut system_process, &mut now, 1), [pid]);
+
+    // SysTick reaches the deadline before the context switch requested by the sleep : the process keeps running
+    system_process.sleep_current_process(1);
+    now += 1;
+    system_process.tick(now);
+    assert_eq!(system_process.get_process_by_id(pid).unwrap().get_status(), ProcStatus::Running);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), pid);
+
+    // It was not queued : once it exits, the idle process runs
+    system_process.exit_current_process(ExitStatus::Exited(0));
+    assert_eq!(run_ticks(&mut system_process, &mut now, 2), [0, 0]);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_idle_fallback() {
+    let mut system_process = SystemProcess::new();
+    let mut now = 0;
+    system_process.create_idle_process();
+
+    let pid_1 = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    assert_eq!(run_ticks(&mut system_process, &mut now, 2), [pid_1, pid_1]);
+
+    // No process left : the idle process (PID 0) runs instead of panicking
+    system_process.exit_current_process(ExitStatus::Exited(0));
+    assert_eq!(run_ticks(&mut system_process, &mut now, 8), [0; 8]);
+    assert_eq!(system_process.get_cpu_load(), 30);
+
+    // A new process preempts the idle process
+    let pid_2 = system_process.create_process("Process 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), PRIORITY_LEVELS as u8 - 1).unwrap();
+    assert_eq!(run_ticks(&mut system_process, &mut now, 1), [pid_2]);
+
+    kill_all(&mut system_process, &[pid_2]);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_fault_exit_status() {
+    let mut system_process = SystemProcess::new();
+    let mut now = 0;
+    system_process.create_idle_process();
+
+    let pid_1 = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let pid_2 = system_process.create_process("Process 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    assert_eq!(run_ticks(&mut system_process, &mut now, 1), [pid_1]);
+
+    // The faulting process is killed on next scheduler call, the other processes keep running
+    let fault = FaultInfo { kind: FaultKind::MemManage, cfsr: 0x82, pc: Some(0x2000_0100), address: Some(0x2000_0000) };
+    system_process.exit_current_process(ExitStatus::Faulted(fault));
+    assert_eq!(run_ticks(&mut system_process, &mut now, 2), [pid_2, pid_2]);
+    assert!(system_process.get_process_by_id(pid_1).is_none());
+    assert_eq!(system_process.get_exit_status(pid_1), Some(ExitStatus::Faulted(fault)));
+
+    kill_all(&mut system_process, &[pid_2]);
+    assert_eq!(system_process.get_exit_status(pid_2), Some(ExitStatus::Killed));
 }
@@ -0,0 +1,66 @@
+use crate::memory_management::mpu::Access;
+use crate::proc::{MapError, ShmAccess, SystemProcess};
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+#[test_case]
+#[inline(never)]
+fn test_shared_memory() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let producer = system_process.create_process("Producer", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let consumer = system_process.create_process("Consumer", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 1).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), producer);
+
+    assert_eq!(system_process.shm_create(b"", 32), Err(MapError::InvalidName));
+    assert_eq!(system_process.shm_create(b"a-much-too-long-name", 32), Err(MapError::InvalidName));
+    assert_eq!(system_process.shm_create(b"frames", 0), Err(MapError::InvalidLength));
+    assert_eq!(system_process.shm_map(b"frames", ShmAccess::ReadOnly), Err(MapError::NotFound));
+
+    // The producer creates the memory, mapped read-write, and aligned on its size
+    let address = system_process.shm_create(b"frames", 100).expect("Creation should succeed");
+    assert!(address.is_multiple_of(128));
+    assert_eq!(system_process.shm_create(b"frames", 100), Err(MapError::AlreadyExists));
+    let mpu = system_process.get_current_process_mpu().unwrap();
+    assert!(mpu.check_unprivileged_access(address, 128, Access::Write));
+    let memory = unsafe { core::slice::from_raw_parts_mut(address as *mut u8, 128) };
+    assert!(memory.iter().all(|byte| *byte == 0));
+    memory[..4].copy_from_slice(b"KRST");
+
+    // The consumer maps it read-only
+    system_process.sleep_current_process(10);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), consumer);
+    assert_eq!(system_process.shm_map(b"frames", ShmAccess::ReadOnly), Ok(address));
+    let mpu = system_process.get_current_process_mpu().unwrap();
+    assert!(mpu.check_unprivileged_access(address, 128, Access::Read));
+    assert!(!mpu.check_unprivileged_access(address, 128, Access::Write));
+    assert_eq!(&memory[..4], b"KRST");
+
+    // Shared memory is unmapped by its exact address, and not by `unmap_memory`
+    assert_eq!(system_process.shm_unmap(address + 4), Err(MapError::InvalidAddress));
+    assert_eq!(system_process.unmap_memory(address), Err(MapError::InvalidAddress));
+
+    // The memory outlives its creator, until its last mapping is removed
+    system_process.kill_process(producer);
+    assert_eq!(system_process.shm_map(b"frames", ShmAccess::ReadWrite), Ok(address));
+    assert_eq!(&memory[..4], b"KRST");
+    assert_eq!(system_process.shm_unmap(address), Ok(()));
+    assert_eq!(system_process.shm_unmap(address), Ok(()));
+    assert_eq!(system_process.shm_unmap(address), Err(MapError::InvalidAddress));
+    let mpu = system_process.get_current_process_mpu().unwrap();
+    assert!(!mpu.check_unprivileged_access(address, 128, Access::Read));
+    assert_eq!(system_process.shm_map(b"frames", ShmAccess::ReadOnly), Err(MapError::NotFound));
+
+    // Mappings are released when the process is killed
+    assert!(system_process.shm_create(b"frames", 32).is_ok());
+    system_process.kill_process(consumer);
+    let producer = system_process.create_process("Producer", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), producer);
+    assert_eq!(system_process.shm_map(b"frames", ShmAccess::ReadOnly), Err(MapError::NotFound));
+
+    system_process.kill_process(producer);
+}
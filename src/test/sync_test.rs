@@ -0,0 +1,169 @@
+use crate::proc::{ExitStatus, IpcError, ProcStatus, SystemProcess, Timeout};
+use crate::syscall::errno;
+use super::helpers::{stacked_r0, status, priority, run_ticks};
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+#[test_case]
+#[inline(never)]
+fn test_priority_inversion() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let mut now = 0;
+
+    let low = system_process.create_process("Low", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 5).unwrap();
+    system_process.schedule_next_process();
+    let mutex = system_process.mutex_create().unwrap();
+    assert_eq!(system_process.mutex_lock(mutex, Timeout::NoWait), Ok(Some(0)));
+    assert_eq!(system_process.mutex_lock(mutex, Timeout::NoWait), Err(IpcError::Deadlock));
+
+    // High waits for the mutex held by Low, while Medium is ready
+    let medium = system_process.create_process("Medium", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 3).unwrap();
+    let high = system_process.create_process("High", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), high);
+    assert_eq!(system_process.mutex_unlock(mutex), Err(IpcError::NotOwner));
+    assert_eq!(system_process.mutex_lock(mutex, Timeout::Forever), Ok(None));
+    assert_eq!(status(&mut system_process, high), ProcStatus::Waiting);
+
+    // Low inherits the priority of High : Medium can't preempt it
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), low);
+    assert_eq!(system_process.get_current_priority_process(), 0);
+    run_ticks(&mut system_process, &mut now, 5);
+    assert_eq!(system_process.get_current_process_id(), low);
+
+    // Releasing the mutex hands it over to High, and Low gets back its priority
+    assert_eq!(system_process.mutex_unlock(mutex), Ok(0));
+    assert_eq!(priority(&mut system_process, low), 5);
+    assert!(system_process.need_resched());
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), high);
+    assert_eq!(stacked_r0(&mut system_process, high), 0);
+
+    // High releases the mutex and exits, Medium and Low run
+    assert_eq!(system_process.mutex_unlock(mutex), Ok(0));
+    system_process.kill_process(high);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), medium);
+    system_process.sleep_current_process(5);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), low);
+    assert_eq!(system_process.mutex_lock(mutex, Timeout::NoWait), Ok(Some(0)));
+
+    // A waiter which times out no longer boosts the holder
+    run_ticks(&mut system_process, &mut now, 5);
+    assert_eq!(system_process.get_current_process_id(), medium);
+    assert_eq!(system_process.mutex_lock(mutex, Timeout::Ticks(2)), Ok(None));
+    assert_eq!(priority(&mut system_process, low), 3);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), low);
+    run_ticks(&mut system_process, &mut now, 2);
+    assert_eq!(priority(&mut system_process, low), 5);
+    assert_eq!(system_process.get_current_process_id(), medium);
+    assert_eq!(stacked_r0(&mut system_process, medium), (-errno::ETIMEDOUT) as u32);
+
+    system_process.kill_process(medium);
+    system_process.kill_process(low);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_priority_inheritance_chain() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let mut now = 0;
+
+    let high = system_process.create_process("High", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    system_process.schedule_next_process();
+    let mutex_1 = system_process.mutex_create().unwrap();
+    let mutex_2 = system_process.mutex_create().unwrap();
+    system_process.sleep_current_process(20);
+
+    let low = system_process.create_process("Low", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 6).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.mutex_lock(mutex_1, Timeout::NoWait), Ok(Some(0)));
+    system_process.sleep_current_process(10);
+
+    // Medium holds mutex 2 and waits for mutex 1 held by Low
+    let medium = system_process.create_process("Medium", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 4).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.mutex_lock(mutex_2, Timeout::NoWait), Ok(Some(0)));
+    assert_eq!(system_process.mutex_lock(mutex_1, Timeout::Forever), Ok(None));
+    assert_eq!(priority(&mut system_process, low), 4);
+    system_process.schedule_next_process();
+    run_ticks(&mut system_process, &mut now, 10);
+    assert_eq!(system_process.get_current_process_id(), low);
+
+    // High waits for mutex 2 : Medium, then Low inherit its priority
+    run_ticks(&mut system_process, &mut now, 10);
+    assert_eq!(system_process.get_current_process_id(), high);
+    assert_eq!(system_process.mutex_lock(mutex_2, Timeout::Forever), Ok(None));
+    assert_eq!(priority(&mut system_process, medium), 0);
+    assert_eq!(priority(&mut system_process, low), 0);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), low);
+
+    // Low exits while holding mutex 1 : Medium gets it, still boosted by High
+    system_process.exit_current_process(ExitStatus::Exited(0));
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), medium);
+    assert_eq!(stacked_r0(&mut system_process, medium), 0);
+    assert_eq!(system_process.get_current_priority_process(), 0);
+
+    // Medium releases mutex 2 to High
+    assert_eq!(system_process.mutex_unlock(mutex_2), Ok(0));
+    assert_eq!(system_process.get_current_priority_process(), 4);
+    system_process.schedule_next_process();
+    assert_eq!(system_process.get_current_process_id(), high);
+    assert_eq!(stacked_r0(&mut system_process, high), 0);
+    assert_eq!(system_process.mutex_unlock(mutex_2), Ok(0));
+
+    system_process.kill_process(medium);
+    system_process.kill_process(high);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_semaphore() {
+    let mut system_process = SystemProcess::new();
+    system_process.create_idle_process();
+    let mut now = 0;
+
+    let poster = system_process.create_process("Poster", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 7).unwrap();
+    system_process.schedule_next_process();
+    assert_eq!(system_process.sem_create(3, 2), Err(IpcError::InvalidArgument));
+    let semaphore = system_process.sem_create(1, 2).unwrap();
+    assert_eq!(system_process.sem_wait(semaphore, Timeout::NoWait), Ok(Some(0)));
+    assert_eq!(system_process.sem_wait(semaphore, Timeout::NoWait), Err(IpcError::WouldBlock));
+    assert_eq!(system_process.sem_post(semaphore), Ok(0));
+    assert_eq!(system_process.sem_post(semaphore), Ok(0));
+    assert_eq!(system_process.sem_post(semaphore), Err(IpcError::Overflow));
+    assert_eq!(IpcError::Overflow.errno(), errno::EOVERFLOW);
+    assert_eq!(system_process.sem_wait(semaphore, Timeout::NoWait), Ok(Some(0)));
+    assert_eq!(system_process.sem_wait(semaphore, Timeout::NoWait), Ok(Some(0)));
+    system_process.sleep_current_process(10);
+
+    // Waiters are woken up by priority, in FIFO order within a priority
+    let waiters = [("Waiter 1", 4), ("Waiter 2", 2), ("Waiter 3", 4)].map(|(name, priority)| {
+        let pid = system_process.create_process(name, LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), priority).unwrap();
+        system_process.schedule_next_process();
+        assert_eq!(system_process.get_current_process_id(), pid);
+        assert_eq!(system_process.sem_wait(semaphore, Timeout::Forever), Ok(None));
+        pid
+    });
+    system_process.schedule_next_process();
+    run_ticks(&mut system_process, &mut now, 10);
+    assert_eq!(system_process.get_current_process_id(), poster);
+
+    for pid in [waiters[1], waiters[0], waiters[2]] {
+        assert_eq!(system_process.sem_post(semaphore), Ok(0));
+        assert_eq!(status(&mut system_process, pid), ProcStatus::Idle);
+    }
+
+    for pid in waiters {
+        system_process.kill_process(pid);
+    }
+    system_process.kill_process(poster);
+}
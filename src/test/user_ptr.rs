@@ -0,0 +1,83 @@
+use crate::memory_management::mpu;
+use crate::proc::SystemProcess;
+use crate::syscall::{errno, user};
+use crate::log_debug;
+
+/// Dummy process code : `B .`
+const LOOP_BYTE_CODE: &[u8;2] = b"\xfe\xe7";
+
+/// Kernel data a process must not be able to read
+static KERNEL_SECRET: [u8; 16] = [0x5a; 16];
+
+#[test_case]
+#[inline(never)]
+fn test_copy_from_user_rejects_kernel_address() {
+    let mut system_process = SystemProcess::new();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let process = system_process.get_process_by_id(pid).unwrap();
+    let mpu = process.get_mpu();
+
+    // Kernel data and kernel code are not in the process regions
+    let mut buffer = [0u8; 16];
+    let secret = KERNEL_SECRET.as_ptr() as u32;
+    log_debug!("Reading kernel address {:#x}", secret);
+    assert_eq!(user::copy_from(mpu, &mut buffer, secret), Err(errno::EFAULT));
+    assert_eq!(user::copy_from(mpu, &mut buffer, 0x0800_0000), Err(errno::EFAULT));
+    assert_eq!(buffer, [0; 16]);
+
+    // Wrapping around the address space
+    assert_eq!(user::copy_from(mpu, &mut buffer, 0xffff_fff8), Err(errno::EFAULT));
+
+    // The process stack is readable
+    let stack = process.get_stack_ptr();
+    assert_eq!(user::copy_from(mpu, &mut buffer, stack), Ok(()));
+
+    system_process.kill_process(pid);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_copy_to_user_permissions() {
+    let mut system_process = SystemProcess::new();
+    let pid = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let process = system_process.get_process_by_id(pid).unwrap();
+    let mpu = process.get_mpu();
+    let data = [0xa5u8; 8];
+
+    // Code is read-only for the process
+    let code = process.get_entry_point() as u32;
+    assert_eq!(user::copy_to(mpu, code, &data[..2]), Err(errno::EFAULT));
+    assert_eq!(unsafe { *(code as *const [u8; 2]) }, *LOOP_BYTE_CODE);
+
+    // Kernel data is not writable
+    assert_eq!(user::copy_to(mpu, KERNEL_SECRET.as_ptr() as u32, &data), Err(errno::EFAULT));
+
+    // The process stack is writable
+    let stack = process.get_stack_ptr();
+    assert_eq!(user::copy_to(mpu, stack, &data), Ok(()));
+    assert_eq!(unsafe { *(stack as *const [u8; 8]) }, data);
+
+    system_process.kill_process(pid);
+}
+
+#[test_case]
+#[inline(never)]
+fn test_process_isolation() {
+    let mut system_process = SystemProcess::new();
+    let pid_1 = system_process.create_process("Process 1", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let pid_2 = system_process.create_process("Process 2", LOOP_BYTE_CODE, LOOP_BYTE_CODE.len(), 0).unwrap();
+    let process_1 = system_process.get_process_by_id(pid_1).unwrap();
+    let process_2 = system_process.get_process_by_id(pid_2).unwrap();
+    let mut buffer = [0u8; 4];
+
+    // Memory of a process is aligned on its MPU region size
+    assert_eq!(process_1.get_entry_point() as usize % mpu::MIN_REGION_SIZE, 0);
+
+    // A process can't read the stack or the code of another process
+    assert_eq!(user::copy_from(process_1.get_mpu(), &mut buffer, process_2.get_stack_ptr()), Err(errno::EFAULT));
+    assert_eq!(user::copy_from(process_1.get_mpu(), &mut buffer, process_2.get_entry_point() as u32), Err(errno::EFAULT));
+    assert_eq!(user::copy_from(process_2.get_mpu(), &mut buffer, process_1.get_stack_ptr()), Err(errno::EFAULT));
+
+    system_process.kill_process(pid_1);
+    system_process.kill_process(pid_2);
+}
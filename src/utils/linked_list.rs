@@ -1,6 +1,8 @@
 extern crate alloc;
 
+use alloc::alloc::Global;
 use alloc::boxed::Box;
+use core::alloc::Allocator;
 use core::ptr;
 
 struct Node<T> {
@@ -14,22 +16,43 @@ impl<T> Node<T> {
     }
 }
 
-pub struct LinkedList<T> {
+/// Singly linked list, whose nodes are allocated with `A` : the kernel heap by default, or the kernel
+/// pools with `LinkedList::new_in(PoolAllocator)`
+pub struct LinkedList<T, A: Allocator + Clone = Global> {
     head: *mut Node<T>,
-    tail: *mut Node<T>
+    tail: *mut Node<T>,
+    alloc: A
 }
 
 impl<T: PartialEq> LinkedList<T> {
+    #[allow(dead_code)]
     pub fn new() -> LinkedList<T> {
+        LinkedList::new_in(Global)
+    }
+}
+
+impl<T: PartialEq, A: Allocator + Clone> LinkedList<T, A> {
+    /// Creates an empty linked list whose nodes are allocated with `alloc`
+    pub fn new_in(alloc: A) -> LinkedList<T, A> {
         LinkedList {
             head: ptr::null_mut(),
             tail: ptr::null_mut(),
+            alloc
         }
     }
 
+    fn new_node(&self, data: T, next: *mut Node<T>) -> *mut Node<T> {
+        Box::into_raw_with_allocator(Box::new_in(Node::new(data, next), self.alloc.clone())).0
+    }
+
+    /// Frees a node, and returns its data
+    unsafe fn free_node(&self, node: *mut Node<T>) -> T {
+        unsafe { Box::from_raw_in(node, self.alloc.clone()).data }
+    }
+
     /// Add node at the end of the linked list
     pub fn add(&mut self, data: T) {
-        let new_node = Box::into_raw(Box::new(Node::new(data, ptr::null_mut())));
+        let new_node = self.new_node(data, ptr::null_mut());
 
         if self.head.is_null() {
             self.head = new_node;
@@ -42,6 +65,76 @@ impl<T: PartialEq> LinkedList<T> {
         }
     }
 
+    /// Add node at the beginning of the linked list
+    pub fn push_front(&mut self, data: T) {
+        let new_node = self.new_node(data, self.head);
+
+        if self.head.is_null() {
+            self.tail = new_node;
+        }
+        self.head = new_node;
+    }
+
+    /// Add node before the first node whose data matches `before`, or at the end of the linked list
+    pub fn insert_before<F: Fn(&T) -> bool>(&mut self, data: T, before: F) {
+        let mut node: *mut Node<T> = self.head;
+        let mut prev: *mut Node<T> = ptr::null_mut();
+
+        while !node.is_null() && unsafe { !before(&(*node).data) } {
+            prev = node;
+            node = unsafe { (*node).next };
+        }
+
+        if node.is_null() {
+            self.add(data);
+        } else if prev.is_null() {
+            self.push_front(data);
+        } else {
+            let new_node = self.new_node(data, node);
+            unsafe {
+                (*prev).next = new_node;
+            }
+        }
+    }
+
+    /// Get reference to the data of the first node
+    pub fn first(&self) -> Option<&T> {
+        if self.head.is_null() {
+            None
+        } else {
+            unsafe { Some(&(*self.head).data) }
+        }
+    }
+
+    /// Remove the first node of the linked list and return its data
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.head.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let node = self.head;
+            self.head = (*node).next;
+            if self.head.is_null() {
+                self.tail = ptr::null_mut();
+            }
+            Some(self.free_node(node))
+        }
+    }
+
+    /// Get mutable reference to the data of the last node
+    pub fn last_mut(&mut self) -> Option<&mut T> {
+        if self.tail.is_null() {
+            None
+        } else {
+            unsafe { Some(&mut (*self.tail).data) }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
     /// Delete specific node in the linked list 
     pub fn delete(&mut self, data: T) -> bool {
         let mut node: *mut Node<T> = self.head;
@@ -52,14 +145,17 @@ impl<T: PartialEq> LinkedList<T> {
                 if (*node).data == data {
                     if prev.is_null() {
                         self.head = (*node).next;
-                        drop(Box::from_raw(node));
+                        if self.tail == node {
+                            self.tail = ptr::null_mut();
+                        }
+                        drop(self.free_node(node));
                     } else if self.tail == node {
-                        drop(Box::from_raw(self.tail));
+                        drop(self.free_node(self.tail));
                         self.tail = prev;
                         (*prev).next = ptr::null_mut();
                     } else {
                         (*prev).next = (*node).next;
-                        drop(Box::from_raw(node));
+                        drop(self.free_node(node));
                     }
                     return true;
                 } else {
@@ -86,6 +182,20 @@ impl<T: PartialEq> LinkedList<T> {
     }
 }
 
+impl<T, A: Allocator + Clone> Drop for LinkedList<T, A> {
+    /// Frees the remaining nodes, and drops their data
+    fn drop(&mut self) {
+        let mut node = self.head;
+        while !node.is_null() {
+            unsafe {
+                let next = (*node).next;
+                drop(Box::from_raw_in(node, self.alloc.clone()));
+                node = next;
+            }
+        }
+    }
+}
+
 pub struct LinkedListIter<T> {
     current: *mut Node<T>,
 }